@@ -0,0 +1,133 @@
+use anyhow::Result;
+use mobiusdb_lsm::wal::inspect::{
+    dump_segment, export_segment, segment_paths, segments, truncate_segment, verify_segment,
+    ExportFormat,
+};
+
+const USAGE: &str = "wal_tool: 查看和修复mobiusdb的wal文件
+
+用法:
+    wal_tool list <wal_dir>                           列出目录下所有的wal文件
+    wal_tool dump <wal_file>                          打印wal文件中的每条记录
+    wal_tool verify <wal_dir|wal_file>                校验wal文件，存在损坏时退出码为1
+    wal_tool truncate <wal_file>                      截断wal文件末尾不完整的记录
+    wal_tool export <wal_file> <out_dir> [ipc|parquet] 将wal中的数据按表导出";
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+    let result = match args.as_slice() {
+        ["list", dir] => list(dir).await,
+        ["dump", path] => dump(path).await,
+        ["verify", path] => verify(path).await,
+        ["truncate", path] => truncate(path).await,
+        ["export", path, out_dir] => export(path, out_dir, ExportFormat::default()).await,
+        ["export", path, out_dir, format] => match ExportFormat::try_from(*format) {
+            Ok(format) => export(path, out_dir, format).await,
+            Err(e) => Err(e),
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+    match result {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn list(dir: &str) -> Result<bool> {
+    println!(
+        "{:<60} {:>10} {:>10} {:>8} {:>10} {:>10}  status",
+        "file", "size", "start_seq", "records", "first_lsn", "last_lsn"
+    );
+    for info in segments(dir).await? {
+        let status = match (&info.error, info.torn) {
+            (Some(e), _) => format!("corrupted: {}", e),
+            (None, true) => format!("torn tail after {}", info.valid_len),
+            (None, false) => "ok".to_string(),
+        };
+        println!(
+            "{:<60} {:>10} {:>10} {:>8} {:>10} {:>10}  {}",
+            info.path,
+            info.file_len,
+            display(info.start_seq),
+            info.records,
+            display(info.first_lsn),
+            display(info.last_lsn),
+            status
+        );
+    }
+    Ok(true)
+}
+
+async fn dump(path: &str) -> Result<bool> {
+    for record in dump_segment(path).await? {
+        println!(
+            "lsn: {}, offset: {}, len: {}, data_len: {}, flags: {}, kind: {:?}, table: {}, rows: {}",
+            record.lsn,
+            record.offset,
+            record.len,
+            record.data_len,
+            record.flags,
+            record.kind,
+            record.table.as_deref().unwrap_or("-"),
+            record.rows
+        );
+        if let Some(schema) = record.schema {
+            for field in schema.fields() {
+                println!("    {}: {}", field.name(), field.data_type());
+            }
+        }
+    }
+    Ok(true)
+}
+
+async fn verify(path: &str) -> Result<bool> {
+    let paths = match tokio::fs::metadata(path).await?.is_dir() {
+        true => segment_paths(path).await?,
+        false => vec![path.to_string()],
+    };
+    let mut healthy = true;
+    for path in paths {
+        let info = verify_segment(&path).await?;
+        match info.error {
+            Some(e) => {
+                healthy = false;
+                println!("{}: {}", path, e);
+            }
+            None => println!("{}: ok, {} records", path, info.records),
+        }
+    }
+    Ok(healthy)
+}
+
+async fn truncate(path: &str) -> Result<bool> {
+    let truncated = truncate_segment(path).await?;
+    println!("{}: truncated {} bytes", path, truncated);
+    Ok(true)
+}
+
+async fn export(path: &str, out_dir: &str, format: ExportFormat) -> Result<bool> {
+    for (table, rows) in export_segment(path, out_dir, format).await? {
+        println!(
+            "{}: exported {} rows to {}/{}.{}",
+            table,
+            rows,
+            out_dir,
+            table,
+            format.extension()
+        );
+    }
+    Ok(true)
+}
+
+fn display(value: Option<u64>) -> String {
+    value.map_or("-".to_string(), |value| value.to_string())
+}
@@ -0,0 +1,58 @@
+use thiserror::Error;
+
+use crate::wal::Lsn;
+
+pub type LsmResult<T> = std::result::Result<T, LsmError>;
+
+/**
+ * LSM 存储服务对外返回的错误
+ */
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum LsmError {
+    // FlightData 无法解码为 RecordBatch
+    #[error("invalid flight data: {0}")]
+    Decode(String),
+    // RecordBatch 的 schema metadata 中没有表名
+    #[error("missing table name in schema metadata, key: {0}")]
+    MissingTableName(String),
+    // 同名字段的类型和已有数据不一致
+    #[error("schema conflict on table {table}: {msg}")]
+    SchemaConflict { table: String, msg: String },
+    // batch中没有表的时间字段
+    #[error("missing time column {column} in table {table}")]
+    MissingTimeColumn { table: String, column: String },
+    // 时间字段的类型不支持或者没有有效的值
+    #[error("invalid time column {column} in table {table}: {msg}")]
+    InvalidTimeColumn {
+        table: String,
+        column: String,
+        msg: String,
+    },
+    // wal文件已满，并且新建wal文件失败
+    #[error("wal is full and rollover failed: {0}")]
+    WalRollover(String),
+    // 写入wal失败
+    #[error("wal write failed: {0}")]
+    WalWrite(String),
+    // 单条记录超过了wal文件的大小
+    #[error("wal record of {size} bytes exceeds the max record size {max} of a wal file")]
+    RecordTooLarge { size: usize, max: usize },
+    // 按照落盘策略fsync失败
+    #[error("wal sync failed: {0}")]
+    WalSync(String),
+    // 读取wal失败
+    #[error("wal read failed: {0}")]
+    WalRead(String),
+    // 游标之前的数据已经被清理
+    #[error("lsn {lsn} has been purged, the oldest lsn in wal is {oldest}")]
+    LsnPurged { lsn: Lsn, oldest: Lsn },
+    // 恢复时还不能重放的wal操作，写入wal之前被拒绝
+    #[error("wal entry {0} is not supported yet")]
+    UnsupportedEntry(String),
+    // 写入memtable失败
+    #[error("memtable insert failed: {0}")]
+    MemTable(String),
+    // LsmServer 已经关闭
+    #[error("lsm server is closed")]
+    Closed,
+}
@@ -0,0 +1,62 @@
+use std::collections::HashSet;
+
+use arrow::array::RecordBatch;
+
+use crate::{memtable::memory::MemTable, sstable::parquet::ParquetSsTable};
+
+/**
+ * 一个immutable memtable写入sstable的任务
+ */
+#[derive(Debug, Clone)]
+pub struct FlushTask {
+    pub(crate) memtable: MemTable,
+    pub(crate) sstable: ParquetSsTable,
+    pub(crate) batch: RecordBatch,
+}
+
+// 写入成功的memtable和对应的sstable
+pub type Flushed = Vec<(MemTable, ParquetSsTable)>;
+
+/**
+ * 后台执行写入sstable的任务，返回写入成功(已经落盘)的sstable：
+ *  1、同一个表的memtable按顺序写入，一个失败之后，这个表后面的memtable不再写入，
+ *     保证每个表已经写入sstable的数据是连续的，恢复时按序列号跳过这些数据
+ *  2、写入失败的memtable仍然保留在immutables中，下一次flush时重试
+ */
+pub async fn flush(tasks: Vec<FlushTask>) -> Flushed {
+    let mut failed = HashSet::new();
+    let mut resp = Vec::new();
+    for FlushTask {
+        memtable,
+        mut sstable,
+        batch,
+    } in tasks
+    {
+        let prefix = memtable.name().get_prefix_name();
+        if failed.contains(&prefix) {
+            continue;
+        }
+        match sstable.write(&batch).await {
+            Ok(_) => {
+                if let Ok(metadata) = tokio::fs::metadata(sstable.file_path()).await {
+                    sstable.size = metadata.len() as usize;
+                }
+                println!(
+                    "memtable:【{}】已写入sstable:【{}】",
+                    memtable.name().get_memtable_name(),
+                    sstable.file_path()
+                );
+                resp.push((memtable, sstable));
+            }
+            Err(e) => {
+                println!(
+                    "memtable:【{}】写入sstable失败：{:?}",
+                    memtable.name().get_memtable_name(),
+                    e
+                );
+                failed.insert(prefix);
+            }
+        }
+    }
+    resp
+}
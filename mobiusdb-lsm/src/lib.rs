@@ -2,47 +2,105 @@ use anyhow::Result;
 use arrow::array::RecordBatch;
 use arrow_flight::{utils::flight_data_to_batches, FlightData};
 
+use error::{LsmError, LsmResult};
+use flush::{flush, Flushed};
 use lsm_client::LsmClient;
 
-use memtable::MemTableService;
+use memtable::{catalog::TableInfo, MemTableService};
+use options::LsmOptions;
+use recovery::recover_with;
+use sstable::{manifest::Manifest, SSTABLE_DIR};
 use tokio::sync::{
-    mpsc::{self, Receiver},
+    mpsc::{self, Receiver, Sender},
     oneshot,
 };
-use utils::table_name::TableName;
-use wal::{offset::Offset, Append, WalService};
+use wal::{
+    offset::Offset, record::RECORD_HEADER_LEN, wal_entry::WalEntry, wal_msg::WalMsg, Append, Lsn,
+    WalService,
+};
 
+pub mod error;
+pub mod flush;
 pub mod lsm_client;
 pub mod memtable;
+pub mod options;
+pub mod recovery;
 pub mod sstable;
 pub mod utils;
 pub mod wal;
 
 pub const TABLE_NAME: &str = "table";
+// 默认的时间字段，memtable和sstable按这个字段记录数据的时间范围，可以通过 TimeColumns 为每个表单独设置
+pub const TIMESTAMP: &str = "timestamp";
+
+// 以LSN为游标读取到的wal数据
+pub type LsnRecords = Vec<(Lsn, WalMsg)>;
+
+/**
+ * 一次写入的结果
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppendResult {
+    // 分配给这次写入的LSN
+    pub lsn: Lsn,
+    // 数据所属的表(TABLE_NAME 对应的前缀)
+    pub table_name: String,
+    // 写入的行数
+    pub rows: usize,
+    // 写入wal的字节数(包含记录头)
+    pub bytes: usize,
+}
 
 #[derive(Debug)]
 pub enum LsmCommand {
-    Append((Vec<FlightData>, oneshot::Sender<bool>)),
+    // 写入数据，成功时返回分配给这次写入的LSN等信息
+    Append((Vec<FlightData>, oneshot::Sender<LsmResult<AppendResult>>)),
+    // 写入数据之外的操作(修改表结构、删除表)，写入wal之后立即应用到memtable，返回分配的LSN
+    Apply((WalEntry, oneshot::Sender<LsmResult<Lsn>>)),
     OffsetList((String, oneshot::Sender<Vec<Offset>>)),
+    // 最近一次写入的LSN
+    LastLsn(oneshot::Sender<Option<Lsn>>),
+    // 以LSN为游标读取wal中的数据: (起始LSN, 最多返回的条数)
+    ReadFrom((Lsn, usize, oneshot::Sender<LsmResult<LsnRecords>>)),
     // 查询指定表的数据
     Table((String, oneshot::Sender<Option<RecordBatch>>)),
     // 查询语句
     Query((String, oneshot::Sender<Option<RecordBatch>>)),
     // 查询表列表
-    TableList(oneshot::Sender<Option<Vec<TableName>>>),
+    TableList(oneshot::Sender<Option<Vec<TableInfo>>>),
 }
 
 impl LsmCommand {
-    pub fn create_append_cmd(fds: Vec<FlightData>) -> (Self, oneshot::Receiver<bool>) {
+    pub fn create_append_cmd(
+        fds: Vec<FlightData>,
+    ) -> (Self, oneshot::Receiver<LsmResult<AppendResult>>) {
         let (sendre, receiver) = oneshot::channel();
         (LsmCommand::Append((fds, sendre)), receiver)
     }
 
+    pub fn create_apply_cmd(entry: WalEntry) -> (Self, oneshot::Receiver<LsmResult<Lsn>>) {
+        let (sendre, receiver) = oneshot::channel();
+        (LsmCommand::Apply((entry, sendre)), receiver)
+    }
+
     pub fn create_offset_list_cmd(file_name: String) -> (Self, oneshot::Receiver<Vec<Offset>>) {
         let (sendre, receiver) = oneshot::channel();
         (LsmCommand::OffsetList((file_name, sendre)), receiver)
     }
 
+    pub fn create_last_lsn_cmd() -> (Self, oneshot::Receiver<Option<Lsn>>) {
+        let (sendre, receiver) = oneshot::channel();
+        (LsmCommand::LastLsn(sendre), receiver)
+    }
+
+    pub fn create_read_from_cmd(
+        lsn: Lsn,
+        limit: usize,
+    ) -> (Self, oneshot::Receiver<LsmResult<LsnRecords>>) {
+        let (sendre, receiver) = oneshot::channel();
+        (LsmCommand::ReadFrom((lsn, limit, sendre)), receiver)
+    }
+
     pub fn create_table_cmd(file_name: String) -> (Self, oneshot::Receiver<Option<RecordBatch>>) {
         let (sendre, receiver) = oneshot::channel();
         (LsmCommand::Table((file_name, sendre)), receiver)
@@ -53,7 +111,7 @@ impl LsmCommand {
         (LsmCommand::Query((query, sendre)), receiver)
     }
 
-    pub fn created_tables_cmd() -> (Self, oneshot::Receiver<Option<Vec<TableName>>>) {
+    pub fn created_tables_cmd() -> (Self, oneshot::Receiver<Option<Vec<TableInfo>>>) {
         let (sendre, receiver) = oneshot::channel();
         (LsmCommand::TableList(sendre), receiver)
     }
@@ -63,64 +121,324 @@ pub struct LsmServer {
     wal_service: WalService,
     memtable: MemTableService,
     receiver: Receiver<LsmCommand>,
+    // 已经落盘的sstable
+    manifest: Manifest,
+    // 后台写入sstable的任务完成之后，通过这个channel通知server
+    flush_sender: Sender<Flushed>,
+    flush_receiver: Receiver<Flushed>,
+    // 同一时间只有一个后台写入任务
+    flushing: bool,
 }
 
 impl LsmServer {
     async fn run(mut self) {
+        let sync_policy = self.wal_service.sync_policy();
+        // 定时检查memtable是否超过了 max_age，只有设置了 max_age 才会触发
+        let max_age = self.memtable.limits().min_age();
+        let mut age_interval = tokio::time::interval(
+            max_age
+                .map(|age| age / 2)
+                .unwrap_or(std::time::Duration::from_secs(3600))
+                .max(std::time::Duration::from_millis(10)),
+        );
+        // 定时fsync，只有 SyncPolicy::Interval 才会真正触发，间隔为0时interval会panic
+        let mut sync_interval = tokio::time::interval(
+            sync_policy
+                .interval()
+                .unwrap_or(std::time::Duration::from_secs(3600))
+                .max(std::time::Duration::from_millis(1)),
+        );
+        // 组提交时读取到的非Append命令，需要在下一次循环中处理
+        let mut pending: Option<LsmCommand> = None;
         loop {
-            if let Some(cmd) = self.receiver.recv().await {
-                match cmd {
-                    LsmCommand::Append((fds, response)) => {
-                        // 1、数据写入到 WAL
-                        // 2、数据写入到 MemTable
-                        let mut resp = false;
-                        if let true = self.wal_service.append(fds.clone()).await {
-                            if let Ok(batches) = flight_data_to_batches(&fds) {
-                                let _ = self.memtable.batch_insert(batches).await;
-                                resp = true;
-                            }
+            let cmd = match pending.take() {
+                Some(cmd) => cmd,
+                None => tokio::select! {
+                    cmd = self.receiver.recv() => match cmd {
+                        Some(cmd) => cmd,
+                        None => break,
+                    },
+                    _ = sync_interval.tick(), if sync_policy.interval().is_some() => {
+                        if let Err(e) = self.wal_service.sync().await {
+                            println!("wal 定时落盘失败：{:?}", e);
                         }
-                        println!("append resp: {:?}", resp);
-                        let _ = response.send(resp);
+                        continue;
                     }
-                    LsmCommand::OffsetList((file_name, response)) => {
-                        let resp = self.wal_service.indexs_map.get(file_name.as_str());
-                        let resp = match resp {
-                            Some(r) => r.clone(),
-                            None => Vec::new(),
-                        };
-                        let _ = response.send(resp);
+                    _ = age_interval.tick(), if max_age.is_some() => {
+                        self.memtable.seal_memtables();
+                        self.schedule_flush().await;
+                        continue;
                     }
-                    LsmCommand::Table((file_name, response)) => {
-                        let tables = self.memtable.tables().await;
-                        println!("查询表: {:?}", tables);
-                        if let Ok(table) = self.memtable.query_with_table(file_name.as_str()).await
-                        {
-                            let resp_table = table.first().unwrap().clone();
-                            let _ = response.send(Some(resp_table));
-                        } else {
-                            let _ = response.send(None);
+                    Some(flushed) = self.flush_receiver.recv() => {
+                        self.finish_flush(flushed).await;
+                        continue;
+                    }
+                },
+            };
+            match cmd {
+                LsmCommand::Append(append) => {
+                    // 组提交：把已经到达的Append命令合并为一次fsync
+                    let mut group = vec![append];
+                    while group.len() < sync_policy.max_batch() {
+                        match self.receiver.try_recv() {
+                            Ok(LsmCommand::Append(append)) => group.push(append),
+                            Ok(cmd) => {
+                                pending = Some(cmd);
+                                break;
+                            }
+                            Err(_) => break,
                         }
                     }
-                    LsmCommand::Query((query, response)) => {
-                        if let Ok(table) = self.memtable.query(query.as_str()).await {
-                            let resp_table = table.first().unwrap().clone();
-                            let _ = response.send(Some(resp_table));
-                        } else {
+                    self.append_group(group).await;
+                    self.memtable.seal_memtables();
+                    self.schedule_flush().await;
+                }
+                LsmCommand::Apply((entry, response)) => {
+                    let resp = self.apply(entry).await;
+                    let committed = self
+                        .wal_service
+                        .commit()
+                        .await
+                        .map_err(|e| LsmError::WalSync(e.to_string()));
+                    let _ = response.send(committed.and(resp));
+                }
+                LsmCommand::OffsetList((file_name, response)) => {
+                    let resp = self.wal_service.offsets(file_name.as_str());
+                    let _ = response.send(resp);
+                }
+                LsmCommand::LastLsn(response) => {
+                    let _ = response.send(self.wal_service.last_lsn());
+                }
+                LsmCommand::ReadFrom((lsn, limit, response)) => {
+                    let resp = self.wal_service.read_from(lsn, limit).await;
+                    let _ = response.send(resp);
+                }
+                LsmCommand::Table((table_name, response)) => {
+                    match self.memtable.table_batch(table_name.as_str()).await {
+                        Ok(batch) => {
+                            let _ = response.send(Some(batch));
+                        }
+                        Err(e) => {
+                            println!("查询表【{}】失败：{:?}", table_name, e);
                             let _ = response.send(None);
                         }
                     }
-                    LsmCommand::TableList(response) => {
-                        if let Ok(table) = self.memtable.tables().await {
-                            let _ = response.send(Some(table));
-                        } else {
+                }
+                LsmCommand::Query((query, response)) => {
+                    match self.memtable.query_batch(query.as_str()).await {
+                        Ok(batch) => {
+                            let _ = response.send(Some(batch));
+                        }
+                        Err(e) => {
+                            println!("查询失败：{:?}", e);
                             let _ = response.send(None);
                         }
                     }
-                    _ => (),
                 }
+                LsmCommand::TableList(response) => match self.memtable.tables().await {
+                    Ok(tables) => {
+                        let _ = response.send(Some(tables));
+                    }
+                    Err(e) => {
+                        println!("查询表列表失败：{:?}", e);
+                        let _ = response.send(None);
+                    }
+                },
+            }
+        }
+        // 所有的client都已经关闭，退出之前把数据落盘，并等待正在执行的写入sstable的任务
+        let _ = self.wal_service.sync().await;
+        if self.flushing {
+            if let Some(flushed) = self.flush_receiver.recv().await {
+                if let Err(e) = self.apply_flush(flushed).await {
+                    println!("sstable 登记失败：{:?}", e);
+                }
+            }
+        }
+    }
+
+    /**
+     * 把immutable memtable交给后台任务写入sstable，写入过程中不阻塞append和查询
+     */
+    async fn schedule_flush(&mut self) {
+        if self.flushing {
+            return;
+        }
+        let tasks = match self.memtable.flush_tasks(self.manifest.path()).await {
+            Ok(tasks) => tasks,
+            Err(e) => {
+                println!("生成sstable写入任务失败：{:?}", e);
+                return;
+            }
+        };
+        if tasks.is_empty() {
+            return;
+        }
+        self.flushing = true;
+        let sender = self.flush_sender.clone();
+        tokio::spawn(async move {
+            let _ = sender.send(flush(tasks).await).await;
+        });
+    }
+
+    /**
+     * 后台任务完成之后登记sstable，并继续写入这期间新产生的immutable memtable，
+     * 没有任何sstable写入成功时不立即重试，等待下一次写入再触发
+     */
+    async fn finish_flush(&mut self, flushed: Flushed) {
+        self.flushing = false;
+        let retry = !flushed.is_empty();
+        if let Err(e) = self.apply_flush(flushed).await {
+            println!("sstable 登记失败：{:?}", e);
+            return;
+        }
+        if retry {
+            self.schedule_flush().await;
+        }
+    }
+
+    /**
+     * 已经落盘的sstable：
+     *  1、登记到manifest
+     *  2、从SessionContext中注销对应的memtable，改为查询sstable
+     *  3、推进wal的checkpoint，清理不再需要的wal文件，
+     *     PerTable 模式下每个表的wal按照这个表的数据单独推进，不受其他表中还在内存中的数据影响
+     */
+    async fn apply_flush(&mut self, flushed: Flushed) -> Result<()> {
+        if flushed.is_empty() {
+            return Ok(());
+        }
+        // 写入过程中被删除的表，sstable不再需要
+        let (flushed, dropped): (Flushed, Flushed) = flushed
+            .into_iter()
+            .partition(|(memtable, _)| self.memtable.is_flushing(memtable));
+        for (_, sstable) in dropped {
+            let _ = tokio::fs::remove_file(sstable.file_path()).await;
+        }
+        self.manifest
+            .add(flushed.iter().map(|(_, sstable)| sstable.clone()))
+            .await?;
+        let mut prefixes = Vec::new();
+        for (memtable, sstable) in flushed {
+            let prefix = memtable.name().get_prefix_name();
+            if !prefixes.contains(&prefix) {
+                prefixes.push(prefix);
+            }
+            self.memtable.finish_flush(&memtable, sstable).await?;
+        }
+        if let Some(seq) = self.memtable.checkpoint_lsn(self.wal_service.last_lsn()) {
+            self.wal_service.checkpoint(seq).await?;
+        }
+        if self.wal_service.mode().is_per_table() {
+            for prefix in prefixes {
+                if let Some(seq) = self.memtable.table_checkpoint_lsn(&prefix) {
+                    self.wal_service.checkpoint_table(&prefix, seq).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * 写入一组数据：
+     *  1、数据写入到 WAL
+     *  2、数据写入到 MemTable
+     *  3、按照落盘策略提交(fsync)之后才返回这次写入的结果
+     */
+    async fn append_group(
+        &mut self,
+        group: Vec<(Vec<FlightData>, oneshot::Sender<LsmResult<AppendResult>>)>,
+    ) {
+        let mut resps = Vec::with_capacity(group.len());
+        for (fds, response) in group {
+            let resp = self.append(fds).await;
+            resps.push((resp, response));
+        }
+        let committed = self
+            .wal_service
+            .commit()
+            .await
+            .map_err(|e| LsmError::WalSync(e.to_string()));
+        for (resp, response) in resps {
+            let resp = match &committed {
+                Ok(_) => resp,
+                Err(e) => resp.and(Err(e.clone())),
+            };
+            let _ = response.send(resp);
+        }
+    }
+
+    /**
+     * 写入一条数据，在写入wal之前完成解码和schema校验，
+     * 避免无法写入memtable的数据进入wal
+     */
+    async fn append(&mut self, fds: Vec<FlightData>) -> LsmResult<AppendResult> {
+        let batches = flight_data_to_batches(&fds).map_err(|e| LsmError::Decode(e.to_string()))?;
+        let schema = batches
+            .first()
+            .ok_or(LsmError::Decode(
+                "no record batch in flight data".to_string(),
+            ))?
+            .schema();
+        let table_name = schema
+            .metadata()
+            .get(TABLE_NAME)
+            .ok_or(LsmError::MissingTableName(TABLE_NAME.to_string()))?
+            .clone();
+        for batch in batches.iter() {
+            self.memtable.check_schema(batch).await?;
+        }
+        let offset = self.wal_service.append(fds).await?;
+        let rows = batches.iter().map(|batch| batch.num_rows()).sum();
+        for batch in batches.iter() {
+            self.memtable
+                .insert_batch(batch, offset.lsn())
+                .await
+                .map_err(|e| LsmError::MemTable(e.to_string()))?;
+        }
+        Ok(AppendResult {
+            lsn: offset.lsn(),
+            table_name,
+            rows,
+            bytes: offset.len + RECORD_HEADER_LEN,
+        })
+    }
+
+    /**
+     * 写入一条数据之外的操作，和恢复时的重放(recovery::replay)保持一致：
+     *  1、SchemaChange: 写入wal之前按照schema的演进规则检查，写入之后修改表的schema
+     *  2、DropTable: 写入之后删除表的memtable和sstable
+     * DeleteRange 还不能重放，写入wal时被拒绝；Checkpoint 只能由wal内部写入
+     */
+    async fn apply(&mut self, entry: WalEntry) -> LsmResult<Lsn> {
+        match &entry {
+            WalEntry::InsertBatch(fds) => return Ok(self.append(fds.clone()).await?.lsn),
+            WalEntry::SchemaChange { table, schema } => {
+                self.memtable.check_change_schema(table, schema)?;
+            }
+            WalEntry::DropTable { .. } | WalEntry::DeleteRange { .. } => {}
+            WalEntry::Checkpoint { .. } => {
+                return Err(LsmError::UnsupportedEntry(format!("{:?}", entry.kind())));
             }
         }
+        let lsn = self.wal_service.append(entry.clone()).await?.lsn();
+        match entry {
+            WalEntry::SchemaChange { table, schema } => {
+                self.memtable.change_schema(&table, &schema)?;
+            }
+            WalEntry::DropTable { table } => {
+                self.memtable
+                    .drop_table(&table)
+                    .map_err(|e| LsmError::MemTable(e.to_string()))?;
+                let memtable = &self.memtable;
+                self.manifest
+                    .retain(|sstable| memtable.contains_sstable(sstable))
+                    .await
+                    .map_err(|e| LsmError::MemTable(e.to_string()))?;
+            }
+            _ => {}
+        }
+        Ok(lsn)
     }
 }
 
@@ -128,18 +446,51 @@ impl LsmServer {
  * 构建一个 LSM 存储服务
  */
 pub async fn server(path: impl Into<String>, wal_size: usize) -> Result<LsmClient> {
+    server_with_options(path, LsmOptions::default().with_wal_size(wal_size)).await
+}
+
+/**
+ * 使用指定的配置构建一个 LSM 存储服务
+ */
+pub async fn server_with_options(path: impl Into<String>, opts: LsmOptions) -> Result<LsmClient> {
     let (sender, receiver) = mpsc::channel(1024);
-    let wal_service = WalService::init(path, wal_size).await;
-    match wal_service {
-        Ok(service) => {
-            let server = LsmServer {
-                wal_service: service,
-                memtable: MemTableService::new(), // 这里可能会有问题，因为内存表是空的
-                receiver,
-            };
-            tokio::spawn(async move { server.run().await });
-            Ok(LsmClient::new(sender))
-        }
-        Err(e) => Err(e.into()),
-    }
+    let path = path.into();
+    let data_path =
+        opts.data_path
+            .clone()
+            .unwrap_or(format!("{}/{}", path.trim_end_matches('/'), SSTABLE_DIR));
+    let mut service = WalService::init(path, opts.wal_size)
+        .await?
+        .with_sync_policy(opts.sync_policy)
+        .with_retention(opts.wal_retention)
+        .with_compression(opts.wal_compression)
+        .with_segment(opts.wal_segment)
+        .await?
+        .with_mode(opts.wal_mode)
+        .await?;
+    // 清理上次运行时已经超出保留范围的wal文件
+    service.purge().await?;
+    // 在接收命令之前，先加载sstable，再通过wal文件恢复memtable
+    let mut manifest = Manifest::load(data_path).await?;
+    let memtable = MemTableService::new()
+        .with_limits(opts.memtable_limits)
+        .with_time_columns(opts.time_columns)
+        .with_primary_keys(opts.primary_keys);
+    let memtable = recover_with(&service, &manifest, memtable).await?;
+    // 恢复时被删除的表，对应的sstable也不再需要
+    manifest
+        .retain(|sstable| memtable.contains_sstable(sstable))
+        .await?;
+    let (flush_sender, flush_receiver) = mpsc::channel(1);
+    let server = LsmServer {
+        wal_service: service,
+        memtable,
+        receiver,
+        manifest,
+        flush_sender,
+        flush_receiver,
+        flushing: false,
+    };
+    tokio::spawn(async move { server.run().await });
+    Ok(LsmClient::new(sender))
 }
@@ -1,11 +1,14 @@
 use anyhow::Result;
-use arrow::array::RecordBatch;
+use arrow::{array::RecordBatch, datatypes::SchemaRef};
 use arrow_flight::FlightData;
 use tokio::sync::{mpsc::Sender, oneshot};
 
 use crate::{
-    utils::{data_utils::batch_to_flight_data, table_name::TableName},
-    LsmCommand,
+    error::{LsmError, LsmResult},
+    memtable::catalog::TableInfo,
+    utils::data_utils::batch_to_flight_data,
+    wal::{wal_entry::WalEntry, Lsn},
+    AppendResult, LsmCommand,
 };
 
 #[derive(Debug, Clone)]
@@ -20,24 +23,89 @@ impl LsmClient {
 }
 
 impl LsmClient {
-    pub async fn append_batch(&self, batch: RecordBatch) -> Result<bool> {
-        let (sender, receiver) = oneshot::channel();
-        let resp = batch_to_flight_data(batch)?;
-        let cmd = LsmCommand::Append((resp, sender));
-        self.cli.send(cmd).await?;
-        let response = receiver.await?;
-        Ok(response)
+    /**
+     * 写入一个RecordBatch，返回分配给这次写入的LSN等信息
+     */
+    pub async fn append_batch(&self, batch: RecordBatch) -> LsmResult<AppendResult> {
+        let fds = batch_to_flight_data(batch).map_err(|e| LsmError::Decode(e.to_string()))?;
+        self.append_fds(fds).await
     }
 
-    pub async fn append_fds(&self, batch: Vec<FlightData>) -> Result<bool> {
+    /**
+     * 写入一组FlightData，返回分配给这次写入的LSN等信息
+     */
+    pub async fn append_fds(&self, batch: Vec<FlightData>) -> LsmResult<AppendResult> {
         let (sender, receiver) = oneshot::channel();
         let cmd = LsmCommand::Append((batch, sender));
+        self.cli.send(cmd).await.map_err(|_| LsmError::Closed)?;
+        receiver.await.map_err(|_| LsmError::Closed)?
+    }
+
+    /**
+     * 按照schema的演进规则修改表的schema，返回分配给这次修改的LSN
+     */
+    pub async fn change_schema(&self, table: &str, schema: SchemaRef) -> LsmResult<Lsn> {
+        self.apply(WalEntry::SchemaChange {
+            table: table.to_string(),
+            schema,
+        })
+        .await
+    }
+
+    /**
+     * 删除表的所有数据，返回分配给这次删除的LSN
+     */
+    pub async fn drop_table(&self, table: &str) -> LsmResult<Lsn> {
+        self.apply(WalEntry::DropTable {
+            table: table.to_string(),
+        })
+        .await
+    }
+
+    /**
+     * 写入一条数据之外的操作，写入wal之后立即生效
+     */
+    pub async fn apply(&self, entry: WalEntry) -> LsmResult<Lsn> {
+        let (sender, receiver) = oneshot::channel();
+        let cmd = LsmCommand::Apply((entry, sender));
+        self.cli.send(cmd).await.map_err(|_| LsmError::Closed)?;
+        receiver.await.map_err(|_| LsmError::Closed)?
+    }
+
+    /**
+     * 最近一次写入的LSN，还没有写入过数据时返回None
+     */
+    pub async fn last_lsn(&self) -> Result<Option<Lsn>> {
+        let (sender, receiver) = oneshot::channel();
+        let cmd = LsmCommand::LastLsn(sender);
         self.cli.send(cmd).await?;
         let response = receiver.await?;
         Ok(response)
     }
 
-    pub async fn table_list(&self) -> Result<Option<Vec<TableName>>> {
+    /**
+     * 读取LSN大于等于lsn的WalEntry，最多返回limit条，
+     * 使用返回的最后一个LSN + 1作为下一次读取的游标
+     */
+    pub async fn read_from(&self, lsn: Lsn, limit: usize) -> LsmResult<Vec<(Lsn, WalEntry)>> {
+        let (sender, receiver) = oneshot::channel();
+        let cmd = LsmCommand::ReadFrom((lsn, limit, sender));
+        self.cli.send(cmd).await.map_err(|_| LsmError::Closed)?;
+        let records = receiver.await.map_err(|_| LsmError::Closed)??;
+        records
+            .iter()
+            .map(|(lsn, wal_msg)| {
+                let entry = WalEntry::from_wal_msg(wal_msg)
+                    .map_err(|e| LsmError::WalRead(e.to_string()))?;
+                Ok((*lsn, entry))
+            })
+            .collect()
+    }
+
+    /**
+     * 所有的表，以及每个表的schema、行数、大小、时间范围和每一层sstable文件的数量
+     */
+    pub async fn table_list(&self) -> Result<Option<Vec<TableInfo>>> {
         let (sender, receiver) = oneshot::channel();
         let cmd = LsmCommand::TableList(sender);
         self.cli.send(cmd).await?;
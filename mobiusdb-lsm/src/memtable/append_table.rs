@@ -0,0 +1,218 @@
+use std::{
+    any::Any,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::Result;
+use arrow::{
+    array::RecordBatch,
+    compute::concat_batches,
+    datatypes::{Field, Schema, SchemaRef},
+};
+use async_trait::async_trait;
+use datafusion::{
+    datasource::{TableProvider, TableType},
+    error::{DataFusionError, Result as DataFusionResult},
+    execution::context::SessionState,
+    logical_expr::Expr,
+    physical_plan::{memory::MemoryExec, ExecutionPlan},
+};
+
+use super::{array_data_utils::adapt_batch, dedup::dedup_batch};
+
+/**
+ * memtable中的数据，只追加不合并：
+ *  1、写入时只处理新写入的batch，和memtable中已有的数据量无关
+ *  2、查询时获取当前所有batch的快照(只复制batch的引用)，之后的写入不影响这次查询
+ *  3、新写入的batch缺少的字段补null；出现新的字段或者字段的类型放宽时扩展表的schema，
+ *     已有的batch按新的schema重新组织(只在schema变化时发生)
+ *  4、声明了主键的表，查询时按主键去重，同一个主键保留最后写入的一行
+ */
+#[derive(Debug)]
+pub struct AppendTable {
+    inner: RwLock<Inner>,
+    // 主键字段
+    key: Option<Vec<String>>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    schema: SchemaRef,
+    batches: Vec<RecordBatch>,
+}
+
+impl AppendTable {
+    pub fn try_new(batch: &RecordBatch) -> Result<Self> {
+        Ok(Self {
+            inner: RwLock::new(Inner {
+                schema: batch.schema(),
+                batches: vec![batch.clone()],
+            }),
+            key: None,
+        })
+    }
+
+    pub fn with_key(mut self, key: Option<Vec<String>>) -> Self {
+        self.key = key;
+        self
+    }
+
+    /**
+     * 追加一个batch
+     */
+    pub fn append(&self, batch: &RecordBatch) -> Result<()> {
+        let mut inner = self
+            .inner
+            .write()
+            .map_err(|e| anyhow::Error::msg(e.to_string()))?;
+        let batch_schema = batch.schema();
+        // 同名字段类型不一致时使用batch中的类型(由SchemaRegistry保证是放宽之后的类型)
+        let mut changed = false;
+        let mut fields: Vec<Arc<Field>> = inner
+            .schema
+            .fields()
+            .iter()
+            .map(|field| match batch_schema.field_with_name(field.name()) {
+                Ok(new_field) if new_field.data_type() != field.data_type() => {
+                    changed = true;
+                    Arc::new(new_field.clone())
+                }
+                _ => field.clone(),
+            })
+            .collect();
+        for field in batch_schema.fields() {
+            if inner.schema.field_with_name(field.name()).is_err() {
+                changed = true;
+                fields.push(field.clone());
+            }
+        }
+        if changed {
+            let schema = Arc::new(Schema::new_with_metadata(
+                fields,
+                inner.schema.metadata().clone(),
+            ));
+            inner.batches = inner
+                .batches
+                .iter()
+                .map(|batch| adapt_batch(batch, &schema))
+                .collect::<Result<_>>()?;
+            inner.schema = schema;
+        }
+        let batch = adapt_batch(batch, &inner.schema)?;
+        inner.batches.push(batch);
+        Ok(())
+    }
+
+    /**
+     * 当前数据的快照
+     */
+    pub fn snapshot(&self) -> (SchemaRef, Vec<RecordBatch>) {
+        let inner = self.inner.read().unwrap_or_else(|e| e.into_inner());
+        (inner.schema.clone(), inner.batches.clone())
+    }
+}
+
+#[async_trait]
+impl TableProvider for AppendTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.snapshot().0
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(
+        &self,
+        _state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        let (schema, mut batches) = self.snapshot();
+        if let Some(key) = self.key.as_ref() {
+            let batch = concat_batches(&schema, &batches)?;
+            let batch =
+                dedup_batch(&batch, key, None).map_err(|e| DataFusionError::External(e.into()))?;
+            batches = vec![batch];
+        }
+        let exec = MemoryExec::try_new(&[batches], schema, projection.cloned())?;
+        Ok(Arc::new(exec))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{Int32Array, RecordBatch, StringArray},
+        datatypes::{DataType, Field, Schema},
+    };
+    use datafusion::prelude::SessionContext;
+
+    use super::AppendTable;
+
+    fn create_batch(fields: Vec<&str>, rows: i32) -> RecordBatch {
+        let schema = Schema::new(
+            fields
+                .iter()
+                .map(|name| match *name {
+                    "age" => Field::new("age", DataType::Int32, true),
+                    name => Field::new(name, DataType::Utf8, true),
+                })
+                .collect::<Vec<_>>(),
+        );
+        let columns = fields
+            .iter()
+            .map(|name| match *name {
+                "age" => Arc::new(Int32Array::from_iter_values(0..rows)) as _,
+                name => Arc::new(StringArray::from(vec![name.to_string(); rows as usize])) as _,
+            })
+            .collect();
+        RecordBatch::try_new(Arc::new(schema), columns).unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn append_table_should_keep_snapshot() {
+        let table = Arc::new(AppendTable::try_new(&create_batch(vec!["name", "age"], 2)).unwrap());
+        let ctx = SessionContext::new();
+        ctx.register_table("t", table.clone()).unwrap();
+        // 获取快照之后写入的数据不影响快照
+        let (_, snapshot) = table.snapshot();
+        table.append(&create_batch(vec!["age", "name"], 3)).unwrap();
+        assert_eq!(snapshot.len(), 1);
+        // 新的字段扩展schema，之前的数据补null
+        table
+            .append(&create_batch(vec!["name", "address"], 1))
+            .unwrap();
+        let (schema, batches) = table.snapshot();
+        let names: Vec<&String> = schema.fields().iter().map(|f| f.name()).collect();
+        assert_eq!(names, vec!["name", "age", "address"]);
+        assert_eq!(batches.len(), 3);
+        assert_eq!(batches[0].column(2).null_count(), 2);
+        assert_eq!(batches[2].column(1).null_count(), 1);
+        let resp = ctx
+            .sql("select count(age), count(address) from t")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let counts = resp[0]
+            .columns()
+            .iter()
+            .map(|c| {
+                c.as_any()
+                    .downcast_ref::<arrow::array::Int64Array>()
+                    .unwrap()
+                    .value(0)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![5, 1]);
+    }
+}
@@ -1,26 +1,30 @@
 use anyhow::Result;
 use arrow::{
-    array::{make_array, Array, ArrayData, RecordBatch, RecordBatchOptions},
-    compute::concat,
+    array::{make_array, Array, ArrayData, ArrayRef, RecordBatch, RecordBatchOptions},
+    compute::{cast, concat},
     datatypes::{Field, Schema, SchemaRef},
     error::ArrowError,
 };
-use std::{collections::HashSet, sync::Arc};
+use std::{collections::HashMap, sync::Arc};
 
+use super::schema_registry::widen;
+
+/**
+ * 合并多个batch，schema按 merge_schemas 的规则合并
+ * time_column: 表的时间字段，合并之后排在第一位
+ */
 pub fn merge_batches<'a>(
     input_batches: impl IntoIterator<Item = &'a RecordBatch> + Clone,
+    time_column: &str,
 ) -> Result<RecordBatch> {
     let schemas: Vec<Arc<Schema>> = input_batches
         .clone()
         .into_iter()
         .map(|batch| batch.schema())
         .collect();
-    if let Ok(schema) = merge_schemas(schemas.clone()) {
-        let resp = merge_batches_with_schema(&Arc::new(schema), input_batches)?;
-        Ok(resp)
-    } else {
-        Err(anyhow::Error::msg("message"))
-    }
+    let schema = merge_schemas(schemas, time_column)?;
+    let resp = merge_batches_with_schema(&Arc::new(schema), input_batches)?;
+    Ok(resp)
 }
 
 /**
@@ -48,7 +52,7 @@ pub fn merge_batches_with_schema<'a>(
     let mut arrays = Vec::with_capacity(field_num);
     for i in 0..field_num {
         let ctype = fields[i].clone();
-        let arr = array(&batches, ctype);
+        let arr = array(&batches, ctype)?;
         let array = arr
             .iter()
             .map(|arc| arc.as_ref() as &dyn Array)
@@ -60,22 +64,44 @@ pub fn merge_batches_with_schema<'a>(
     batch
 }
 
-fn array(batches: &Vec<&RecordBatch>, ctype: Arc<Field>) -> Vec<Arc<dyn Array>> {
-    let arr = batches
+/**
+ * 每个batch中和ctype同名的列，类型不一致时转换为ctype的类型，batch中没有的列补null
+ */
+fn array(batches: &[&RecordBatch], ctype: Arc<Field>) -> Result<Vec<ArrayRef>, ArrowError> {
+    batches
         .iter()
-        .map(|batch| {
-            let arr3 = if let Some(index) = batch.schema().fields().iter().position(|f| f == &ctype)
-            {
-                let arr1 = batch.column(index).to_owned();
-                arr1
-            } else {
-                let arr2 = build_null_array(&ctype, batch.num_rows());
-                arr2
-            };
-            arr3
+        .map(|batch| match batch.column_by_name(ctype.name()) {
+            Some(column) if column.data_type() == ctype.data_type() => Ok(column.clone()),
+            Some(column) => cast(column, ctype.data_type()),
+            None => Ok(build_null_array(&ctype, batch.num_rows())),
+        })
+        .collect()
+}
+
+/**
+ * 按照schema重新组织batch中的列：
+ *  1、batch中没有的字段补null
+ *  2、类型不一致的字段转换为schema中的类型
+ */
+pub fn adapt_batch(batch: &RecordBatch, schema: &SchemaRef) -> Result<RecordBatch> {
+    if batch.schema() == *schema {
+        return Ok(batch.clone());
+    }
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| match batch.column_by_name(field.name()) {
+            Some(column) if column.data_type() == field.data_type() => Ok(column.clone()),
+            Some(column) => cast(column, field.data_type()),
+            None => Ok(build_null_array(field, batch.num_rows())),
         })
-        .collect::<Vec<_>>();
-    arr
+        .collect::<Result<Vec<ArrayRef>, ArrowError>>()?;
+    let options = RecordBatchOptions::new().with_row_count(Some(batch.num_rows()));
+    Ok(RecordBatch::try_new_with_options(
+        schema.clone(),
+        columns,
+        &options,
+    )?)
 }
 
 /**
@@ -91,21 +117,65 @@ pub fn build_null_array(ctype: &Arc<Field>, length: usize) -> Arc<dyn Array> {
 /**
  * 合并两个schema
  */
-pub fn merge_schema(s1: &Schema, s2: &Schema) -> Schema {
-    let schema1 = s1.fields().to_vec();
-    let schema2 = s2.fields().to_vec();
-    let mut combined_set: HashSet<_> = schema1.into_iter().collect();
-    combined_set.extend(schema2.into_iter());
-    let combined_schema: Vec<Arc<Field>> = combined_set.into_iter().collect();
-    Schema::new(combined_schema)
+pub fn merge_schema(s1: &Schema, s2: &Schema, time_column: &str) -> Result<Schema> {
+    merge_schemas([Arc::new(s1.clone()), Arc::new(s2.clone())], time_column)
 }
 
-pub fn merge_schemas(schemas: impl IntoIterator<Item = SchemaRef>) -> Result<Schema> {
-    let mut hash_set = HashSet::new();
-    schemas.into_iter().for_each(|schema| {
-        let vecs: HashSet<_> = schema.fields().to_vec().into_iter().collect();
-        hash_set.extend(vecs);
-    });
-    let combined_schema: Vec<Arc<Field>> = hash_set.into_iter().collect();
-    Ok(Schema::new(combined_schema))
+/**
+ * 合并多个schema，表的schema演进(SchemaRegistry)、逻辑表的schema都按这个规则合并，
+ * 结果和输入的顺序有关，和运行的次数无关：
+ *  1、字段按第一次出现的先后顺序排列，表的时间字段(time_column)排在第一位
+ *  2、同名字段只保留一个：类型按 SchemaRegistry 的规则放宽，不兼容时返回错误；
+ *     任意一个schema中可以为null或者缺少这个字段时，合并之后可以为null；字段的metadata合并
+ *  3、schema的metadata合并，同名的key保留第一次出现的值
+ */
+pub fn merge_schemas(
+    schemas: impl IntoIterator<Item = SchemaRef>,
+    time_column: &str,
+) -> Result<Schema> {
+    let schemas: Vec<SchemaRef> = schemas.into_iter().collect();
+    let mut fields: Vec<Field> = Vec::new();
+    let mut metadata = HashMap::new();
+    for schema in schemas.iter() {
+        for (key, value) in schema.metadata() {
+            metadata.entry(key.clone()).or_insert(value.clone());
+        }
+        for field in schema.fields() {
+            let Some(merged) = fields.iter_mut().find(|f| f.name() == field.name()) else {
+                fields.push(field.as_ref().clone());
+                continue;
+            };
+            let data_type =
+                widen(merged.data_type(), field.data_type()).ok_or(anyhow::Error::msg(format!(
+                    "field 【{}】 expect {} but got {}",
+                    field.name(),
+                    merged.data_type(),
+                    field.data_type()
+                )))?;
+            let mut field_metadata = merged.metadata().clone();
+            for (key, value) in field.metadata() {
+                field_metadata.entry(key.clone()).or_insert(value.clone());
+            }
+            *merged = merged
+                .clone()
+                .with_data_type(data_type)
+                .with_nullable(merged.is_nullable() || field.is_nullable())
+                .with_metadata(field_metadata);
+        }
+    }
+    let mut fields: Vec<Field> = fields
+        .into_iter()
+        .map(|field| {
+            let missing = schemas
+                .iter()
+                .any(|schema| schema.field_with_name(field.name()).is_err());
+            let nullable = field.is_nullable() || missing;
+            field.with_nullable(nullable)
+        })
+        .collect();
+    if let Some(index) = fields.iter().position(|field| field.name() == time_column) {
+        let field = fields.remove(index);
+        fields.insert(0, field);
+    }
+    Ok(Schema::new_with_metadata(fields, metadata))
 }
@@ -0,0 +1,68 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use arrow::datatypes::{Schema, SchemaRef};
+
+use crate::{sstable::parquet::ParquetSsTable, utils::file_utils::Level};
+
+use super::memory::MemTable;
+
+/**
+ * 一个逻辑表(TABLE_NAME 对应的前缀)的信息，包括内存中的memtable和已经写入的sstable
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableInfo {
+    // 表名(前缀)
+    pub name: String,
+    // 表的schema(SchemaRegistry中登记的)，字段按出现的先后顺序排列
+    pub schema: SchemaRef,
+    // 行数
+    pub rows: usize,
+    // 大小: memtable占用的内存 + sstable文件的大小
+    pub size: usize,
+    // 数据的时间范围
+    pub start: u64,
+    pub end: u64,
+    // 还在内存中的memtable(mutable和immutable)的数量
+    pub memtables: usize,
+    // 每一层sstable文件的数量
+    pub files: BTreeMap<Level, usize>,
+}
+
+impl TableInfo {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            schema: Arc::new(Schema::empty()),
+            rows: 0,
+            size: 0,
+            start: u64::MAX,
+            end: 0,
+            memtables: 0,
+            files: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn add_sstable(&mut self, sstable: &ParquetSsTable) {
+        self.add(sstable.rows, sstable.size, sstable.start, sstable.end);
+        *self.files.entry(sstable.level()).or_default() += 1;
+    }
+
+    pub(crate) fn add_memtable(&mut self, memtable: &MemTable) {
+        self.add(memtable.rows, memtable.size, memtable.start, memtable.end);
+        self.memtables += 1;
+    }
+
+    fn add(&mut self, rows: usize, size: usize, start: u64, end: u64) {
+        self.rows += rows;
+        self.size += size;
+        self.start = self.start.min(start);
+        self.end = self.end.max(end);
+    }
+
+    /**
+     * sstable文件的总数
+     */
+    pub fn file_count(&self) -> usize {
+        self.files.values().sum()
+    }
+}
@@ -0,0 +1,263 @@
+use std::{any::Any, collections::HashMap, sync::Arc};
+
+use anyhow::Result;
+use arrow::{
+    array::{AsArray, RecordBatch, UInt32Array},
+    compute::{cast, concat_batches, take},
+    datatypes::{DataType, Schema, SchemaRef, UInt64Type},
+    row::{RowConverter, SortField},
+};
+use datafusion::{
+    error::{DataFusionError, Result as DataFusionResult},
+    execution::TaskContext,
+    physical_expr::EquivalenceProperties,
+    physical_plan::{
+        common::collect, stream::RecordBatchStreamAdapter, DisplayAs, DisplayFormatType,
+        Distribution, ExecutionMode, ExecutionPlan, Partitioning, PlanProperties,
+        SendableRecordBatchStream,
+    },
+};
+
+/**
+ * 按主键去重，同一个主键只保留一行：
+ *  1、version为None时保留最后出现的一行(写入顺序)
+ *  2、否则保留version(UInt64)最大的一行，version相同时保留最后出现的一行
+ * 保留的行按原来的顺序排列，batch中缺少的主键字段按null处理
+ */
+pub fn dedup_batch(
+    batch: &RecordBatch,
+    key: &[String],
+    version: Option<&str>,
+) -> Result<RecordBatch> {
+    let columns = key
+        .iter()
+        .filter_map(|name| batch.column_by_name(name).cloned())
+        .collect::<Vec<_>>();
+    if columns.is_empty() || batch.num_rows() == 0 {
+        return Ok(batch.clone());
+    }
+    let converter = RowConverter::new(
+        columns
+            .iter()
+            .map(|column| SortField::new(column.data_type().clone()))
+            .collect(),
+    )?;
+    let rows = converter.convert_columns(&columns)?;
+    let versions = match version {
+        Some(name) => {
+            let column = batch
+                .column_by_name(name)
+                .ok_or(anyhow::Error::msg(format!(
+                    "missing version column {}",
+                    name
+                )))?;
+            Some(cast(column, &DataType::UInt64)?)
+        }
+        None => None,
+    };
+    let versions = versions
+        .as_ref()
+        .map(|column| column.as_primitive::<UInt64Type>());
+    let version_of = |index: usize| versions.map(|v| v.value(index)).unwrap_or_default();
+    let mut latest: HashMap<_, usize> = HashMap::with_capacity(batch.num_rows());
+    for index in 0..batch.num_rows() {
+        latest
+            .entry(rows.row(index))
+            .and_modify(|current| {
+                if version_of(index) >= version_of(*current) {
+                    *current = index;
+                }
+            })
+            .or_insert(index);
+    }
+    if latest.len() == batch.num_rows() {
+        return Ok(batch.clone());
+    }
+    let mut indices: Vec<u32> = latest.into_values().map(|index| index as u32).collect();
+    indices.sort_unstable();
+    let indices = UInt32Array::from(indices);
+    let columns = batch
+        .columns()
+        .iter()
+        .map(|column| take(column, &indices, None))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(RecordBatch::try_new(batch.schema(), columns)?)
+}
+
+/**
+ * 查询时合并多个memtable和sstable之后按主键去重：
+ *  1、输入中每一行带有version字段(数据所在部分按wal序列号排序之后的顺序)，version大的数据是后写入的
+ *  2、输入合并为一个分区之后去重，输出中去掉version字段
+ */
+#[derive(Debug)]
+pub struct DedupExec {
+    input: Arc<dyn ExecutionPlan>,
+    key: Vec<String>,
+    version: String,
+    schema: SchemaRef,
+    properties: PlanProperties,
+}
+
+impl DedupExec {
+    pub fn try_new(
+        input: Arc<dyn ExecutionPlan>,
+        key: Vec<String>,
+        version: impl Into<String>,
+    ) -> DataFusionResult<Self> {
+        let version = version.into();
+        let input_schema = input.schema();
+        input_schema.index_of(&version)?;
+        let fields = input_schema
+            .fields()
+            .iter()
+            .filter(|field| *field.name() != version)
+            .cloned()
+            .collect::<Vec<_>>();
+        let schema = Arc::new(Schema::new_with_metadata(
+            fields,
+            input_schema.metadata().clone(),
+        ));
+        let properties = PlanProperties::new(
+            EquivalenceProperties::new(schema.clone()),
+            Partitioning::UnknownPartitioning(1),
+            ExecutionMode::Bounded,
+        );
+        Ok(Self {
+            input,
+            key,
+            version,
+            schema,
+            properties,
+        })
+    }
+}
+
+impl DisplayAs for DedupExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "DedupExec: key=[{}]", self.key.join(", "))
+    }
+}
+
+impl ExecutionPlan for DedupExec {
+    fn name(&self) -> &'static str {
+        "DedupExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        &self.properties
+    }
+
+    fn required_input_distribution(&self) -> Vec<Distribution> {
+        vec![Distribution::SinglePartition]
+    }
+
+    fn benefits_from_input_partitioning(&self) -> Vec<bool> {
+        vec![false]
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![&self.input]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        let input = children
+            .into_iter()
+            .next()
+            .ok_or(DataFusionError::Internal(
+                "DedupExec has no input".to_string(),
+            ))?;
+        Ok(Arc::new(Self::try_new(
+            input,
+            self.key.clone(),
+            self.version.clone(),
+        )?))
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> DataFusionResult<SendableRecordBatchStream> {
+        if partition != 0 {
+            return Err(DataFusionError::Internal(format!(
+                "DedupExec has only one partition, got {}",
+                partition
+            )));
+        }
+        let stream = self.input.execute(0, context)?;
+        let input_schema = self.input.schema();
+        let schema = self.schema.clone();
+        let key = self.key.clone();
+        let version = self.version.clone();
+        let fut = async move {
+            let batches = collect(stream).await?;
+            let batch = concat_batches(&input_schema, &batches)?;
+            let batch = dedup_batch(&batch, &key, Some(&version))
+                .map_err(|e| DataFusionError::External(e.into()))?;
+            let index = input_schema.index_of(&version)?;
+            let mut columns = batch.columns().to_vec();
+            columns.remove(index);
+            Ok(RecordBatch::try_new(schema, columns)?)
+        };
+        Ok(Box::pin(RecordBatchStreamAdapter::new(
+            self.schema.clone(),
+            futures::stream::once(fut),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{Int32Array, RecordBatch, StringArray, UInt64Array},
+        datatypes::{DataType, Field, Schema},
+    };
+
+    use super::dedup_batch;
+
+    #[test]
+    fn dedup_batch_should_keep_last_write() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("host", DataType::Utf8, true),
+            Field::new("timestamp", DataType::UInt64, true),
+            Field::new("value", DataType::Int32, true),
+            Field::new("version", DataType::UInt64, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringArray::from(vec!["a", "b", "a", "a", "b"])),
+                Arc::new(UInt64Array::from(vec![1, 1, 1, 2, 1])),
+                Arc::new(Int32Array::from(vec![10, 20, 11, 12, 21])),
+                Arc::new(UInt64Array::from(vec![2, 1, 1, 1, 1])),
+            ],
+        )
+        .unwrap();
+        let key = vec!["host".to_string(), "timestamp".to_string()];
+        // 按写入顺序，后写入的覆盖之前的
+        let resp = dedup_batch(&batch, &key, None).unwrap();
+        let values = resp
+            .column(2)
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap();
+        assert_eq!(values.values(), &[11, 12, 21]);
+        // 按version，version大的覆盖version小的
+        let resp = dedup_batch(&batch, &key, Some("version")).unwrap();
+        let values = resp
+            .column(2)
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap();
+        assert_eq!(values.values(), &[10, 12, 21]);
+    }
+}
@@ -40,6 +40,36 @@ impl Immutables {
         true
     }
 
+    pub fn remove(&self, prefix: &str) -> Vec<TableName> {
+        self.tables.remove(prefix);
+        self.tables_name
+            .remove(prefix)
+            .map(|(_, table_names)| table_names)
+            .unwrap_or_default()
+    }
+
+    /**
+     * 删除一个已经写入sstable的memtable
+     */
+    pub fn remove_table(&self, table_name: &TableName) -> Option<MemTable> {
+        let prefix = table_name.get_prefix_name();
+        if let Some(mut vs) = self.tables_name.get_mut(prefix.as_str()) {
+            vs.retain(|name| name != table_name);
+        }
+        self.tables_name
+            .remove_if(prefix.as_str(), |_, vs| vs.is_empty());
+        let memtable = match self.tables.get_mut(prefix.as_str()) {
+            Some(mut vs) => {
+                let index = vs.iter().position(|memtable| memtable.name() == table_name);
+                index.map(|index| vs.remove(index))
+            }
+            None => None,
+        };
+        self.tables
+            .remove_if(prefix.as_str(), |_, vs| vs.is_empty());
+        memtable
+    }
+
     /**
      *  true: 可写数据
      *  false: 不可写数据
@@ -65,6 +95,16 @@ impl Immutables {
 }
 
 impl Immutables {
+    /**
+     * 所有的immutable memtable，同一个前缀的memtable按写入顺序排列
+     */
+    pub fn all(&self) -> Vec<MemTable> {
+        self.tables
+            .iter()
+            .flat_map(|entry| entry.value().clone())
+            .collect()
+    }
+
     pub fn get_tables(&self, prefix: &str) -> Vec<TableName> {
         let tables = self.tables_name.get(prefix);
         match tables {
@@ -0,0 +1,462 @@
+use std::{
+    any::Any,
+    sync::{Arc, RwLock},
+};
+
+use arrow::datatypes::{Schema, SchemaRef};
+use async_trait::async_trait;
+use datafusion::{
+    common::{project_schema, ScalarValue},
+    datasource::{TableProvider, TableType},
+    error::Result as DataFusionResult,
+    execution::context::SessionState,
+    logical_expr::Expr,
+    logical_expr::TableProviderFilterPushDown,
+    physical_expr::PhysicalExpr,
+    physical_plan::{
+        coalesce_partitions::CoalescePartitionsExec,
+        empty::EmptyExec,
+        expressions::{cast, Column, Literal},
+        projection::ProjectionExec,
+        union::UnionExec,
+        ExecutionPlan,
+    },
+};
+
+use crate::{wal::offset::Lsn, TIMESTAMP};
+
+use super::{array_data_utils::merge_schemas, dedup::DedupExec, time_range::TimeRange};
+
+// 查询声明了主键的表时，每一行所在部分按wal序列号排序之后的顺序
+const PART_VERSION: &str = "__part";
+
+/**
+ * 一个逻辑表(TABLE_NAME 对应的前缀)，以前缀为表名注册到SessionContext中，
+ * 查询时合并这个前缀下所有的数据：sstable、immutable和mutable memtable
+ *  1、每个部分(part)是一个已经注册的物理表(memtable或sstable)，
+ *     写入新的memtable、memtable写入sstable时更新
+ *  2、schema是所有部分的schema按字段出现的先后顺序合并之后的结果，
+ *     某个部分缺少的字段查询时补null
+ *  3、查询时获取当前所有部分的快照，分别scan之后union
+ *  4、时间字段上的条件下推到scan，跳过时间范围[start, end]和条件不重叠的部分
+ *  5、声明了主键的表，union之后按主键去重，每个主键只返回一行：
+ *     部分中数据的最大wal序列号大的覆盖小的，和部分的添加顺序无关(例如恢复时先注册sstable再重放wal)
+ */
+pub struct LogicalTable {
+    prefix: String,
+    // 时间字段
+    time_column: String,
+    // 主键字段
+    key: Option<Vec<String>>,
+    // 按写入顺序排列
+    parts: RwLock<Vec<Part>>,
+}
+
+/**
+ * 逻辑表的一部分: 物理表名、物理表、其中数据的时间范围和最大wal序列号
+ */
+#[derive(Clone)]
+struct Part {
+    name: String,
+    provider: Arc<dyn TableProvider>,
+    range: TimeRange,
+    lsn: Lsn,
+}
+
+impl LogicalTable {
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+            time_column: TIMESTAMP.to_string(),
+            key: None,
+            parts: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub fn with_time_column(mut self, time_column: impl Into<String>) -> Self {
+        self.time_column = time_column.into();
+        self
+    }
+
+    pub fn with_key(mut self, key: Option<Vec<String>>) -> Self {
+        self.key = key;
+        self
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /**
+     * 添加一个部分，同名的部分会被替换(位置不变)，例如mutable memtable写入新数据之后更新时间范围和序列号
+     */
+    pub fn add(
+        &self,
+        name: impl Into<String>,
+        provider: Arc<dyn TableProvider>,
+        range: TimeRange,
+        lsn: Lsn,
+    ) {
+        let part = Part {
+            name: name.into(),
+            provider,
+            range,
+            lsn,
+        };
+        let mut parts = self.parts.write().unwrap_or_else(|e| e.into_inner());
+        match parts.iter_mut().find(|p| p.name == part.name) {
+            Some(p) => *p = part,
+            None => parts.push(part),
+        }
+    }
+
+    /**
+     * 删除一个部分，返回是否存在
+     */
+    pub fn remove(&self, name: &str) -> bool {
+        let mut parts = self.parts.write().unwrap_or_else(|e| e.into_inner());
+        let len = parts.len();
+        parts.retain(|part| part.name != name);
+        parts.len() != len
+    }
+
+    pub fn part_names(&self) -> Vec<String> {
+        self.snapshot().into_iter().map(|part| part.name).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_empty()
+    }
+
+    /**
+     * 满足条件的数据可能所在的部分，返回物理表名
+     */
+    pub fn prune(&self, filters: &[Expr]) -> Vec<String> {
+        self.pruned_parts(filters)
+            .into_iter()
+            .map(|part| part.name)
+            .collect()
+    }
+
+    fn pruned_parts(&self, filters: &[Expr]) -> Vec<Part> {
+        let range = TimeRange::from_filters(filters, &self.time_column);
+        self.snapshot()
+            .into_iter()
+            .filter(|part| part.range.overlaps(&range))
+            .collect()
+    }
+
+    fn snapshot(&self) -> Vec<Part> {
+        self.parts.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl std::fmt::Debug for LogicalTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LogicalTable")
+            .field("prefix", &self.prefix)
+            .field("time_column", &self.time_column)
+            .field("key", &self.key)
+            .field("parts", &self.part_names())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl TableProvider for LogicalTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    /**
+     * 按照和SchemaRegistry相同的规则(merge_schemas)合并所有部分的schema，时间字段排在第一位，
+     * 写入时已经由SchemaRegistry保证兼容，不兼容的部分保留之前的类型；
+     * 从空的schema开始合并，不同部分的同一个字段可能缺失，合并之后的字段都可以为null
+     */
+    fn schema(&self) -> SchemaRef {
+        self.snapshot()
+            .iter()
+            .fold(Arc::new(Schema::empty()), |merged, part| {
+                merge_schemas([merged.clone(), part.provider.schema()], &self.time_column)
+                    .map(Arc::new)
+                    .unwrap_or(merged)
+            })
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    /**
+     * 时间字段上的条件用于裁剪，但每个部分中仍然可能有不满足条件的数据
+     */
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> DataFusionResult<Vec<TableProviderFilterPushDown>> {
+        Ok(filters
+            .iter()
+            .map(
+                |filter| match TimeRange::from_expr(filter, &self.time_column) {
+                    Some(_) => TableProviderFilterPushDown::Inexact,
+                    None => TableProviderFilterPushDown::Unsupported,
+                },
+            )
+            .collect())
+    }
+
+    async fn scan(
+        &self,
+        state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        // schema由所有部分决定，裁剪之后的部分只用于scan
+        let schema = self.schema();
+        let parts = self.pruned_parts(filters);
+        // 去重需要读取主键字段，去重之后再按需要的字段输出
+        let key = self.key.as_ref().map(|key| {
+            key.iter()
+                .filter_map(|name| schema.index_of(name).ok())
+                .collect::<Vec<usize>>()
+        });
+        let scan_projection = match (projection, key.as_ref()) {
+            (Some(projection), Some(key)) => {
+                let mut scan_projection = projection.clone();
+                scan_projection.extend(key.iter().filter(|index| !projection.contains(index)));
+                Some(scan_projection)
+            }
+            (projection, _) => projection.cloned(),
+        };
+        let projected = project_schema(&schema, scan_projection.as_ref())?;
+        // 按最大wal序列号排序之后的位置作为version，
+        // 序列号相同(同一条wal记录写入了多个memtable)时按添加顺序
+        let mut order: Vec<usize> = (0..parts.len()).collect();
+        order.sort_by_key(|index| parts[*index].lsn);
+        let mut versions = vec![0u64; parts.len()];
+        for (version, index) in order.into_iter().enumerate() {
+            versions[index] = version as u64;
+        }
+        let mut inputs: Vec<Arc<dyn ExecutionPlan>> = Vec::with_capacity(parts.len());
+        for (Part { provider: part, .. }, version) in parts.into_iter().zip(versions) {
+            // 只读取这个部分中存在的字段，缺少的字段补null
+            let part_schema = part.schema();
+            let part_projection: Vec<usize> = projected
+                .fields()
+                .iter()
+                .filter_map(|field| part_schema.index_of(field.name()).ok())
+                .collect();
+            let input = part.scan(state, Some(&part_projection), &[], None).await?;
+            let input_schema = input.schema();
+            let mut exprs: Vec<(Arc<dyn PhysicalExpr>, String)> = Vec::new();
+            for field in projected.fields() {
+                let position = part_projection
+                    .iter()
+                    .position(|index| part_schema.field(*index).name() == field.name());
+                let expr: Arc<dyn PhysicalExpr> = match position {
+                    // 类型放宽之前写入的数据转换为放宽之后的类型
+                    Some(index) => cast(
+                        Arc::new(Column::new(field.name(), index)),
+                        &input_schema,
+                        field.data_type().clone(),
+                    )?,
+                    None => Arc::new(Literal::new(ScalarValue::try_from(field.data_type())?)),
+                };
+                exprs.push((expr, field.name().clone()));
+            }
+            if key.is_some() {
+                let version = Arc::new(Literal::new(ScalarValue::UInt64(Some(version))));
+                exprs.push((version, PART_VERSION.to_string()));
+            }
+            inputs.push(Arc::new(ProjectionExec::try_new(exprs, input)?));
+        }
+        if inputs.is_empty() {
+            return Ok(Arc::new(EmptyExec::new(project_schema(
+                &schema, projection,
+            )?)));
+        }
+        let union: Arc<dyn ExecutionPlan> = Arc::new(UnionExec::new(inputs));
+        let Some(key) = key else {
+            return Ok(union);
+        };
+        let key = key.iter().map(|index| schema.field(*index).name().clone());
+        let dedup = Arc::new(DedupExec::try_new(
+            Arc::new(CoalescePartitionsExec::new(union)),
+            key.collect(),
+            PART_VERSION,
+        )?);
+        // 去掉只为了去重读取的主键字段
+        let Some(projection) = projection else {
+            return Ok(dedup);
+        };
+        let exprs = (0..projection.len())
+            .map(|index| {
+                let name = projected.field(index).name().clone();
+                let expr: Arc<dyn PhysicalExpr> = Arc::new(Column::new(&name, index));
+                (expr, name)
+            })
+            .collect();
+        Ok(Arc::new(ProjectionExec::try_new(exprs, dedup)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{Int32Array, Int64Array, RecordBatch, StringArray, UInt64Array},
+        datatypes::{DataType, Field, Schema},
+    };
+    use datafusion::{
+        datasource::{MemTable, TableProvider},
+        prelude::{col, lit, SessionContext},
+    };
+
+    use super::{LogicalTable, TimeRange};
+
+    fn create_table(fields: Vec<&str>, rows: i32) -> Arc<MemTable> {
+        let schema = Arc::new(Schema::new(
+            fields
+                .iter()
+                .map(|name| match *name {
+                    "age" => Field::new("age", DataType::Int32, false),
+                    "timestamp" => Field::new("timestamp", DataType::UInt64, true),
+                    name => Field::new(name, DataType::Utf8, true),
+                })
+                .collect::<Vec<_>>(),
+        ));
+        let columns = fields
+            .iter()
+            .map(|name| match *name {
+                "age" => Arc::new(Int32Array::from_iter_values(0..rows)) as _,
+                "timestamp" => Arc::new(UInt64Array::from_iter_values(25..25 + rows as u64)) as _,
+                name => Arc::new(StringArray::from(vec![name.to_string(); rows as usize])) as _,
+            })
+            .collect();
+        let batch = RecordBatch::try_new(schema.clone(), columns).unwrap();
+        Arc::new(MemTable::try_new(schema, vec![vec![batch]]).unwrap())
+    }
+
+    async fn count(ctx: &SessionContext, sql: &str) -> Vec<i64> {
+        let resp = ctx.sql(sql).await.unwrap().collect().await.unwrap();
+        resp[0]
+            .columns()
+            .iter()
+            .map(|c| c.as_any().downcast_ref::<Int64Array>().unwrap().value(0))
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn logical_table_should_union_parts() {
+        let table = Arc::new(LogicalTable::new("cpu"));
+        let ctx = SessionContext::new();
+        ctx.register_table("cpu", table.clone()).unwrap();
+        // 没有数据时返回空的结果
+        assert_eq!(count(&ctx, "select count(*) from cpu").await, vec![0]);
+        table.add(
+            "cpu-1",
+            create_table(vec!["name", "age"], 2),
+            TimeRange::new(0, 10),
+            1,
+        );
+        table.add(
+            "cpu-2",
+            create_table(vec!["age", "address"], 3),
+            TimeRange::new(20, 30),
+            2,
+        );
+        let names: Vec<String> = table
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| f.name().clone())
+            .collect();
+        assert_eq!(names, vec!["name", "age", "address"]);
+        assert_eq!(
+            count(
+                &ctx,
+                "select count(*), count(name), count(address) from cpu where age < 2"
+            )
+            .await,
+            vec![4, 2, 2]
+        );
+        // 删除之后不再查询到这部分数据
+        assert!(table.remove("cpu-1"));
+        assert!(!table.remove("cpu-1"));
+        assert_eq!(table.part_names(), vec!["cpu-2"]);
+        assert_eq!(count(&ctx, "select count(*) from cpu").await, vec![3]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn logical_table_should_prune_parts_by_time() {
+        let table = Arc::new(LogicalTable::new("cpu"));
+        let ctx = SessionContext::new();
+        ctx.register_table("cpu", table.clone()).unwrap();
+        // 两个部分中的数据时间都是从25开始，cpu-1登记的时间范围用来验证是否被跳过
+        table.add(
+            "cpu-1",
+            create_table(vec!["name", "timestamp"], 2),
+            TimeRange::new(0, 10),
+            1,
+        );
+        table.add(
+            "cpu-2",
+            create_table(vec!["name", "timestamp"], 3),
+            TimeRange::new(20, 30),
+            2,
+        );
+        let filters = vec![col("timestamp").gt_eq(lit(20u64))];
+        assert_eq!(table.prune(&filters), vec!["cpu-2"]);
+        assert_eq!(
+            count(&ctx, "select count(*) from cpu where timestamp >= 20").await,
+            vec![3]
+        );
+        assert_eq!(
+            count(
+                &ctx,
+                "select count(*) from cpu where timestamp between 0 and 5 or timestamp > 100"
+            )
+            .await,
+            vec![0]
+        );
+        // 其他字段上的条件不能裁剪
+        assert_eq!(
+            count(&ctx, "select count(*) from cpu where name = 'name'").await,
+            vec![5]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn logical_table_should_dedup_by_lsn() {
+        let table = Arc::new(LogicalTable::new("cpu").with_key(Some(vec!["age".to_string()])));
+        let ctx = SessionContext::new();
+        ctx.register_table("cpu", table.clone()).unwrap();
+        // 序列号大的部分先添加，例如恢复时先注册sstable，再重放wal中序列号更小的数据
+        table.add(
+            "cpu-1",
+            create_table(vec!["age", "name"], 2),
+            TimeRange::new(0, 10),
+            9,
+        );
+        table.add(
+            "cpu-2",
+            create_table(vec!["age", "address"], 3),
+            TimeRange::new(0, 10),
+            3,
+        );
+        // age为0、1的行来自cpu-1，age为2的行只在cpu-2中
+        assert_eq!(
+            count(
+                &ctx,
+                "select count(*), count(name), count(address) from cpu"
+            )
+            .await,
+            vec![3, 2, 1]
+        );
+    }
+}
@@ -1,12 +1,14 @@
-use crate::utils::{
-    data_utils::{self, get_timestamp_from_batch},
-    table_name::TableName,
+use crate::{
+    utils::{
+        data_utils::{self, get_timestamp_from_batch},
+        table_name::TableName,
+        time_utils::now,
+    },
+    wal::offset::Lsn,
 };
 use anyhow::Result;
 use arrow::array::RecordBatch;
 
-const MAX_BATCH_SIZE: usize = 1024 * 2;
-
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MemTable {
     pub(crate) name: TableName,
@@ -14,25 +16,40 @@ pub struct MemTable {
     pub(crate) mutable: bool,
     // 文件大小
     pub(crate) size: usize,
+    // 行数
+    pub(crate) rows: usize,
+    // 第一条数据写入的时间(微秒)，合并数据时沿用之前的memtable的时间
+    pub(crate) created: u64,
     // 开始时间
     pub(crate) start: u64,
     // 结束时间
     pub(crate) end: u64,
+    // memtable中数据对应的最小、最大的wal序列号，写入sstable之后用于推进checkpoint
+    pub(crate) min_lsn: Lsn,
+    pub(crate) max_lsn: Lsn,
 }
 
 impl MemTable {
-    pub async fn new_with_batch(name: &TableName, batch: &RecordBatch) -> Result<Self> {
-        let (start, end) = get_timestamp_from_batch(batch).await?;
+    /**
+     * 新建的memtable都是可写的，由 MemTableLimit 判断是否已经写满
+     */
+    pub async fn new_with_batch(
+        name: &TableName,
+        batch: &RecordBatch,
+        time_column: &str,
+    ) -> Result<Self> {
+        let (start, end) = get_timestamp_from_batch(batch, time_column)?;
         let batch_size = data_utils::batch_size(batch);
-        println!("batch_size:{}", batch_size);
-        let mutable = !(batch_size > MAX_BATCH_SIZE);
-        println!("mutable:{}", mutable);
         Ok(Self {
             name: name.clone(),
-            mutable,
+            mutable: true,
             size: batch_size,
+            rows: batch.num_rows(),
+            created: now() as u64,
             start,
             end,
+            min_lsn: 0,
+            max_lsn: 0,
         })
     }
     pub fn new(name: TableName, start: u64, end: u64) -> Self {
@@ -40,11 +57,39 @@ impl MemTable {
             name,
             mutable: true,
             size: 0,
+            rows: 0,
+            created: now() as u64,
             start,
             end,
+            min_lsn: 0,
+            max_lsn: 0,
         }
     }
 
+    pub fn with_lsn(mut self, min_lsn: Lsn, max_lsn: Lsn) -> Self {
+        self.min_lsn = min_lsn;
+        self.max_lsn = max_lsn;
+        self
+    }
+
+    /**
+     * 追加一个batch之后更新memtable的统计信息
+     */
+    pub async fn append_batch(
+        &mut self,
+        batch: &RecordBatch,
+        time_column: &str,
+        lsn: Lsn,
+    ) -> Result<()> {
+        let (start, end) = get_timestamp_from_batch(batch, time_column)?;
+        self.size += data_utils::batch_size(batch);
+        self.rows += batch.num_rows();
+        self.start = self.start.min(start);
+        self.end = self.end.max(end);
+        self.max_lsn = lsn;
+        Ok(())
+    }
+
     pub fn name(&self) -> &TableName {
         &self.name
     }
@@ -0,0 +1,145 @@
+use std::{collections::HashMap, time::Duration};
+
+use super::memory::MemTable;
+
+// 默认memtable大小: 16M
+pub const DEFAULT_MEMTABLE_SIZE: usize = 16 * 1024 * 1024;
+
+/**
+ * 一个memtable从可写(mutable)变为不可写(immutable)的条件，满足任意一个即可
+ *  max_bytes: memtable中数据占用的内存大小
+ *  max_rows: memtable中数据的行数
+ *  max_age: memtable中第一条数据写入之后经过的时间，没有新数据写入时也会按时检查
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemTableLimit {
+    pub(crate) max_bytes: usize,
+    pub(crate) max_rows: Option<usize>,
+    pub(crate) max_age: Option<Duration>,
+}
+
+impl Default for MemTableLimit {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_MEMTABLE_SIZE,
+            max_rows: None,
+            max_age: None,
+        }
+    }
+}
+
+impl MemTableLimit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn with_max_rows(mut self, max_rows: usize) -> Self {
+        self.max_rows = Some(max_rows);
+        self
+    }
+
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /**
+     * memtable是否已经写满，now: 当前时间(微秒)
+     */
+    pub fn is_full(&self, memtable: &MemTable, now: u64) -> bool {
+        memtable.size >= self.max_bytes
+            || self
+                .max_rows
+                .is_some_and(|max_rows| memtable.rows >= max_rows)
+            || self.max_age.is_some_and(|max_age| {
+                now.saturating_sub(memtable.created) >= max_age.as_micros() as u64
+            })
+    }
+}
+
+/**
+ * memtable的限制
+ *  limit: 默认的限制
+ *  tables: 为每个表(TABLE_NAME 对应的前缀)设置单独的限制，没有设置的表使用默认的限制
+ *  memory_budget: 所有memtable(包括还没有写入sstable的immutable)占用内存的上限，
+ *                 超出时最大的几个mutable memtable会被强制变为immutable并写入sstable
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemTableLimits {
+    pub(crate) limit: MemTableLimit,
+    pub(crate) tables: HashMap<String, MemTableLimit>,
+    pub(crate) memory_budget: Option<usize>,
+}
+
+impl MemTableLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_limit(mut self, limit: MemTableLimit) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn with_table_limit(mut self, prefix: impl Into<String>, limit: MemTableLimit) -> Self {
+        self.tables.insert(prefix.into(), limit);
+        self
+    }
+
+    pub fn with_memory_budget(mut self, memory_budget: usize) -> Self {
+        self.memory_budget = Some(memory_budget);
+        self
+    }
+
+    pub fn limit(&self, prefix: &str) -> MemTableLimit {
+        self.tables.get(prefix).copied().unwrap_or(self.limit)
+    }
+
+    /**
+     * 所有限制中最短的 max_age，用于定时检查
+     */
+    pub fn min_age(&self) -> Option<Duration> {
+        std::iter::once(&self.limit)
+            .chain(self.tables.values())
+            .filter_map(|limit| limit.max_age)
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{memtable::memory::MemTable, utils::table_name::TableName};
+
+    use super::{MemTableLimit, MemTableLimits};
+
+    #[test]
+    fn memtable_limit_should_be_work() {
+        let limits = MemTableLimits::new()
+            .with_limit(MemTableLimit::new().with_max_bytes(1024))
+            .with_table_limit(
+                "cpu",
+                MemTableLimit::new()
+                    .with_max_rows(10)
+                    .with_max_age(Duration::from_secs(2)),
+            );
+        assert_eq!(limits.min_age(), Some(Duration::from_secs(2)));
+        let mut memtable = MemTable::new(TableName::new_with_time("cpu", 0), 0, 0);
+        memtable.size = 2048;
+        memtable.rows = 5;
+        memtable.created = 1_000_000;
+        // cpu表只限制行数和时间
+        assert!(!limits.limit("cpu").is_full(&memtable, 1_000_000));
+        assert!(limits.limit("mem").is_full(&memtable, 1_000_000));
+        memtable.rows = 10;
+        assert!(limits.limit("cpu").is_full(&memtable, 1_000_000));
+        memtable.rows = 5;
+        assert!(limits.limit("cpu").is_full(&memtable, 3_000_000));
+    }
+}
@@ -1,39 +1,76 @@
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
 use anyhow::Result;
-use array_data_utils::merge_batches;
-use arrow::{array::RecordBatch, datatypes::Schema};
+use append_table::AppendTable;
+use array_data_utils::adapt_batch;
+use arrow::{
+    array::RecordBatch,
+    compute::concat_batches,
+    datatypes::{Schema, SchemaRef},
+};
+use catalog::TableInfo;
 use dashmap::DashMap;
 use datafusion::{
-    dataframe::DataFrameWriteOptions, error::DataFusionError, prelude::SessionContext,
+    error::DataFusionError,
+    physical_plan::collect,
+    prelude::{DataFrame, ParquetReadOptions, SessionContext},
+    sql::TableReference,
 };
+use logical_table::LogicalTable;
 use memory::MemTable;
+use memtable_limit::MemTableLimits;
+use primary_key::PrimaryKeys;
+use schema_registry::SchemaRegistry;
 use table_index::TableIndexs;
-use table_size::TableSize;
+use time_column::TimeColumns;
+use time_range::TimeRange;
 
 use crate::{
+    error::{LsmError, LsmResult},
+    flush::FlushTask,
+    sstable::parquet::ParquetSsTable,
     utils::{
-        file_utils::{create_sstable_path, Level, SSTABLE_FILE_SUFFIX},
-        table_name::TableName,
+        data_utils::get_timestamp_from_batch, file_utils::SSTABLE_FILE_SUFFIX,
+        table_name::TableName, time_utils::now,
     },
+    wal::offset::Lsn,
     TABLE_NAME,
 };
 
+pub mod append_table;
 pub mod array_data_utils;
+pub mod catalog;
+pub mod dedup;
 pub mod immtables;
+pub mod logical_table;
 pub mod memory;
+pub mod memtable_limit;
 pub mod mutables;
+pub mod primary_key;
+pub mod schema_registry;
 pub mod sql_utils;
 pub mod table_index;
 pub mod table_size;
+pub mod time_column;
+pub mod time_range;
 
 #[derive(Clone)]
 pub struct MemTableService {
     ctx: SessionContext,
     // table_names: DashSet<TableName>,
-    table_size: TableSize, // 每100行合并一次
-    table_opts: DashMap<TableName, Arc<Schema>>,
+    // memtable的大小、行数、时间限制和总内存上限
+    limits: MemTableLimits,
+    // 每个表的时间字段
+    time_columns: TimeColumns,
+    // 每个表的主键，声明了主键的表按主键去重
+    primary_keys: PrimaryKeys,
+    // 每个表的schema和演进规则
+    schemas: SchemaRegistry,
     table_indexs: TableIndexs,
+    // 已经写入sstable的数据: <前缀、sstable列表>，按写入顺序排列
+    sstables: DashMap<String, Vec<ParquetSsTable>>,
+    // 逻辑表: <前缀、逻辑表>，以前缀为表名注册，可以直接用SQL查询
+    logical_tables: DashMap<String, Arc<LogicalTable>>,
 }
 
 impl MemTableService {}
@@ -43,66 +80,145 @@ impl MemTableService {
         Self {
             ctx: SessionContext::new(),
             // table_names: DashSet::new(),
-            table_size: TableSize::default(),
-            table_opts: DashMap::new(),
+            limits: MemTableLimits::default(),
+            time_columns: TimeColumns::default(),
+            primary_keys: PrimaryKeys::new(),
+            schemas: SchemaRegistry::new(),
             table_indexs: TableIndexs::new(),
+            sstables: DashMap::new(),
+            logical_tables: DashMap::new(),
         }
     }
 
-    pub async fn batch_insert(&mut self, batches: Vec<RecordBatch>) {
+    pub fn with_limits(mut self, limits: MemTableLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn limits(&self) -> &MemTableLimits {
+        &self.limits
+    }
+
+    pub fn with_time_columns(mut self, time_columns: TimeColumns) -> Self {
+        self.time_columns = time_columns;
+        self
+    }
+
+    pub fn time_columns(&self) -> &TimeColumns {
+        &self.time_columns
+    }
+
+    pub fn with_primary_keys(mut self, primary_keys: PrimaryKeys) -> Self {
+        self.primary_keys = primary_keys;
+        self
+    }
+
+    pub fn primary_keys(&self) -> &PrimaryKeys {
+        &self.primary_keys
+    }
+
+    pub fn schemas(&self) -> &SchemaRegistry {
+        &self.schemas
+    }
+
+    pub fn table_indexs(&self) -> &TableIndexs {
+        &self.table_indexs
+    }
+
+    /**
+     * 写入同一个lsn的多个batch，任意一个batch写入失败时返回错误
+     */
+    pub async fn batch_insert(&mut self, batches: Vec<RecordBatch>, lsn: Lsn) -> LsmResult<()> {
         for batch in batches {
-            let _resp = self.insert_batch(&batch).await;
+            self.insert_batch(&batch, lsn)
+                .await
+                .map_err(|e| LsmError::MemTable(e.to_string()))?;
         }
+        Ok(())
     }
 
     /**
      * todo:
      *  1、从batch中获取表名 prefix
      *  2、根据前缀，获取对应的mutable_table
-     *  3、将数据合并到mutableTable
-     *  4、判断合并之后的mutabletable的大小，如果过大就转换为immutable_table
+     *  3、将数据追加到mutableTable(AppendTable)，只处理新写入的batch
+     *  4、判断追加之后的mutabletable的大小，如果过大就转换为immutable_table
+     *     (MemTableLimit: 大小、行数、时间)
+     *
+     * lsn: 这条数据在wal中的序列号，已经写入sstable的数据(恢复时重放)会被跳过
      */
-    pub async fn insert_batch(&mut self, batch: &RecordBatch) -> Result<bool> {
+    pub async fn insert_batch(&mut self, batch: &RecordBatch, lsn: Lsn) -> Result<bool> {
         // 1、首先生成相应的table_name
         if let Some(prefix) = batch.schema().metadata().get(TABLE_NAME) {
-            let b = self.table_indexs.get_mutables().contains_key(prefix);
-
-            println!("prefix:{:?} 是否存在于mutables: {:?}", prefix, b);
-            let new_batch = match b {
-                true => {
-                    let mem_table = self.table_indexs.get_mutables().get_table(prefix).unwrap();
-                    if mem_table.mutable {
-                        let table_name = self
-                            .table_indexs
-                            .get_mutables()
-                            .get_table_name(prefix)
-                            .unwrap();
-                        let old_mem_table_name = table_name.get_memtable_name();
-                        // println!("old_mem_table_name: {:?}", old_mem_table_name);
-                        let mut old_batch = self.query_with_table(&old_mem_table_name).await?;
-                        old_batch.push(batch.clone());
-                        let new_batch = merge_batches(&old_batch)?;
-                        new_batch
-                    } else {
-                        let schema = batch.schema();
-                        let empty_batch = RecordBatch::new_empty(schema);
-                        let new_batch = merge_batches(vec![batch, &empty_batch])?;
-                        new_batch
-                    }
+            if self
+                .flushed_lsn(prefix)
+                .is_some_and(|flushed| lsn <= flushed)
+            {
+                return Ok(false);
+            }
+            // 按照表的schema演进规则登记，batch转换为表当前的schema
+            let schema =
+                self.schemas
+                    .register(prefix, &batch.schema(), self.time_columns.column(prefix))?;
+            let batch = &adapt_batch(batch, &schema)?;
+            let mutable = self
+                .table_indexs
+                .get_mutables()
+                .get_table(prefix)
+                .filter(|mem_table| mem_table.mutable);
+            let mut mem_table = match mutable {
+                Some(mut mem_table) => {
+                    let provider = self
+                        .ctx
+                        .table_provider(mem_table.name().get_memtable_name())
+                        .await?;
+                    let table = provider.as_any().downcast_ref::<AppendTable>().ok_or(
+                        anyhow::Error::msg(format!(
+                            "memtable: 【{}】 is not appendable",
+                            mem_table.name().get_memtable_name()
+                        )),
+                    )?;
+                    mem_table
+                        .append_batch(batch, self.time_columns.column(prefix), lsn)
+                        .await?;
+                    // 先扩大时间范围再写入数据，查询时不会错误地跳过新写入的数据
+                    self.logical_table(prefix)?.add(
+                        mem_table.name().get_memtable_name(),
+                        provider.clone(),
+                        TimeRange::new(mem_table.start, mem_table.end),
+                        mem_table.max_lsn,
+                    );
+                    table.append(batch)?;
+                    mem_table
                 }
-                false => {
-                    let schema = batch.schema();
-                    let empty_batch = RecordBatch::new_empty(schema);
-                    let new_batch = merge_batches(vec![batch, &empty_batch])?;
-                    new_batch
+                None => {
+                    let new_table_name = TableName::new_mem_name(prefix);
+                    let time_column = self.time_columns.column(prefix);
+                    let mem_table = MemTable::new_with_batch(&new_table_name, batch, time_column)
+                        .await?
+                        .with_lsn(lsn, lsn);
+                    let key = self.primary_keys.key(prefix, time_column);
+                    let table = Arc::new(AppendTable::try_new(batch)?.with_key(key));
+                    self.ctx.register_table(
+                        new_table_name.get_memtable_name().as_str(),
+                        table.clone(),
+                    )?;
+                    self.logical_table(prefix)?.add(
+                        new_table_name.get_memtable_name(),
+                        table,
+                        TimeRange::new(mem_table.start, mem_table.end),
+                        lsn,
+                    );
+                    mem_table
                 }
             };
-            let new_table_name = TableName::new_mem_name(prefix);
-            let mem_table = MemTable::new_with_batch(&new_table_name, &new_batch).await?;
-            let _ = self
-                .ctx
-                .register_batch(&new_table_name.get_memtable_name(), new_batch);
+            mem_table.mutable = !self.limits.limit(prefix).is_full(&mem_table, now() as u64);
+            let mutable = mem_table.mutable;
             self.table_indexs.insert(mem_table);
+            // 写满的memtable直接转移到immutables中，等待写入sstable
+            if !mutable {
+                self.table_indexs.seal(prefix);
+            }
             Ok(true)
         } else {
             Ok(false)
@@ -110,14 +226,143 @@ impl MemTableService {
     }
 }
 
+/**
+ * 删除相关的方法
+ */
+impl MemTableService {
+    /**
+     * 删除前缀对应的所有memtable和sstable，同时从SessionContext中注销
+     */
+    pub fn drop_table(&mut self, prefix: &str) -> Result<Vec<TableName>> {
+        let mut table_names: Vec<TableName> = self
+            .sstables
+            .remove(prefix)
+            .map(|(_, sstables)| sstables.iter().map(|s| s.get_table_name()).collect())
+            .unwrap_or_default();
+        table_names.extend(self.table_indexs.remove(prefix));
+        for table_name in table_names.iter() {
+            self.ctx.deregister_table(table_name.get_memtable_name())?;
+        }
+        self.schemas.remove(prefix);
+        if self.logical_tables.remove(prefix).is_some() {
+            self.ctx.deregister_table(TableReference::bare(prefix))?;
+        }
+        Ok(table_names)
+    }
+}
+
+/**
+ * 修改表结构相关的方法
+ */
+impl MemTableService {
+    /**
+     * 按照schema的演进规则修改表的schema，之后写入的batch按照新的schema组织，返回修改之后的schema
+     */
+    pub fn change_schema(&self, prefix: &str, schema: &Schema) -> LsmResult<SchemaRef> {
+        self.schemas
+            .register(prefix, schema, self.time_columns.column(prefix))
+    }
+
+    /**
+     * 修改之后表的schema，只检查不修改，不兼容时返回错误
+     */
+    pub fn check_change_schema(&self, prefix: &str, schema: &Schema) -> LsmResult<SchemaRef> {
+        self.schemas
+            .check(prefix, schema, self.time_columns.column(prefix))
+    }
+}
+
+/**
+ * 逻辑表相关的方法
+ */
+impl MemTableService {
+    /**
+     * 前缀对应的逻辑表，不存在时创建并以前缀为表名注册到SessionContext中
+     */
+    fn logical_table(&self, prefix: &str) -> Result<Arc<LogicalTable>> {
+        if let Some(table) = self.logical_tables.get(prefix) {
+            return Ok(table.clone());
+        }
+        let time_column = self.time_columns.column(prefix);
+        let table = Arc::new(
+            LogicalTable::new(prefix)
+                .with_time_column(time_column)
+                .with_key(self.primary_keys.key(prefix, time_column)),
+        );
+        self.ctx
+            .register_table(TableReference::bare(prefix), table.clone())?;
+        self.logical_tables
+            .insert(prefix.to_string(), table.clone());
+        Ok(table)
+    }
+
+    /**
+     * 所有逻辑表的表名(前缀)，按表名排序
+     */
+    pub fn logical_table_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .logical_tables
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect();
+        names.sort();
+        names
+    }
+}
+
+/**
+ * 写入之前的校验
+ */
+impl MemTableService {
+    /**
+     * 检查batch和表中已有的数据是否兼容：
+     *  1、符合表的schema演进规则(SchemaRegistry)
+     *  2、必须包含表的时间字段，并且类型支持、有有效的值
+     */
+    pub async fn check_schema(&self, batch: &RecordBatch) -> LsmResult<()> {
+        let schema = batch.schema();
+        let prefix = schema
+            .metadata()
+            .get(TABLE_NAME)
+            .ok_or(LsmError::MissingTableName(TABLE_NAME.to_string()))?;
+        self.schemas
+            .check(prefix, &schema, self.time_columns.column(prefix))?;
+        get_timestamp_from_batch(batch, self.time_columns.column(prefix))?;
+        Ok(())
+    }
+}
+
 /**
  * Query接口
  */
 impl MemTableService {
-    // 获取所有表名
-    pub async fn tables(&self) -> Result<Vec<TableName>, DataFusionError> {
-        // Ok(self.table_names.clone().into_iter().collect())
-        todo!()
+    /**
+     * 所有的逻辑表(前缀)，包括mutables、immutables和sstable中的数据，按表名排序
+     */
+    pub async fn tables(&self) -> Result<Vec<TableInfo>> {
+        let mut tables: BTreeMap<String, TableInfo> = BTreeMap::new();
+        for entry in self.sstables.iter() {
+            for sstable in entry.value() {
+                tables
+                    .entry(entry.key().clone())
+                    .or_insert_with(|| TableInfo::new(entry.key()))
+                    .add_sstable(sstable);
+            }
+        }
+        for memtable in self.table_indexs.memtables() {
+            let prefix = memtable.name().get_prefix_name();
+            tables
+                .entry(prefix.clone())
+                .or_insert_with(|| TableInfo::new(prefix))
+                .add_memtable(&memtable);
+        }
+        // schema以SchemaRegistry中登记的为准，字段按写入的先后顺序排列
+        for table in tables.values_mut() {
+            if let Some(schema) = self.schemas.get(&table.name) {
+                table.schema = schema;
+            }
+        }
+        Ok(tables.into_values().collect())
     }
 
     pub async fn query(&self, sql: &str) -> Result<Vec<RecordBatch>, DataFusionError> {
@@ -133,21 +378,34 @@ impl MemTableService {
 
     /**
      * 查询指定表(所有数据，多用于测试，一般不能这么使用，类似select * from table_name)
+     * 通过逻辑表查询，包括sstable、immutables和mutable中的数据，表不存在时返回空
      */
     pub async fn query_with_table_prefix(
         &self,
         table_prefix_name: &str,
     ) -> Result<Vec<RecordBatch>, DataFusionError> {
-        let mut resp = Vec::new();
-        let table_names = self.table_indexs.get_tables_with_prefix(table_prefix_name);
-        for table in table_names {
-            let table_name = table.get_memtable_name();
-            if let Ok(df) = self.ctx.table(table_name).await {
-                let fs = df.collect().await?;
-                resp.extend(fs);
-            }
+        if !self.logical_tables.contains_key(table_prefix_name) {
+            return Ok(Vec::new());
         }
-        Ok(resp)
+        self.ctx
+            .table(TableReference::bare(table_prefix_name))
+            .await?
+            .collect()
+            .await
+    }
+
+    /**
+     * 执行查询语句，所有的结果合并为一个batch，没有结果时返回按查询计划的schema构建的空batch
+     */
+    pub async fn query_batch(&self, sql: &str) -> Result<RecordBatch> {
+        collect_batch(self.ctx.sql(sql).await?).await
+    }
+
+    /**
+     * 查询指定表(逻辑表或者物理表)的所有数据，合并为一个batch
+     */
+    pub async fn table_batch(&self, table_name: &str) -> Result<RecordBatch> {
+        collect_batch(self.ctx.table(table_name).await?).await
     }
 
     pub async fn query_with_table(&self, table_mem_name: &str) -> Result<Vec<RecordBatch>> {
@@ -160,33 +418,194 @@ impl MemTableService {
     }
 }
 
+/**
+ * 执行DataFrame，按照查询计划的schema合并所有的结果
+ */
+async fn collect_batch(df: DataFrame) -> Result<RecordBatch> {
+    let task_ctx = Arc::new(df.task_ctx());
+    let plan = df.create_physical_plan().await?;
+    let schema = plan.schema();
+    let batches = collect(plan, task_ctx).await?;
+    Ok(concat_batches(&schema, &batches)?)
+}
+
+/**
+ * memtable写入sstable相关的方法
+ */
 impl MemTableService {
-    // 判断指定memtable是否可写
-    fn mutable(&self, table_name: &TableName) -> Result<bool> {
-        self.table_indexs.mutable(table_name)
-    }
-
-    async fn flush(&self, memtable_name: impl AsRef<str>) -> Result<()> {
-        let table_name = TableName::new_with_mem_name(memtable_name.as_ref());
-        let mutable = self.mutable(&table_name)?;
-        if mutable {
-            let sstable_name = format!("{}{}", memtable_name.as_ref(), SSTABLE_FILE_SUFFIX);
-            let sql = format!("select * from {}", memtable_name.as_ref());
-            let prefix_name = TableName::get_prefix_name_with_name(&sstable_name);
-            let path = create_sstable_path(&prefix_name, Level::L0);
-            let _df = self
-                .ctx
-                .sql(sql.as_str())
-                .await?
-                .write_parquet(path.as_str(), DataFrameWriteOptions::new(), None)
-                .await?;
-            Ok(())
-        } else {
-            let msg = format!(
-                "the table: 【{}】 is mutable!",
-                table_name.get_memtable_name()
-            );
-            Err(anyhow::Error::msg(msg))
+    /**
+     * 为所有的immutable memtable生成写入sstable的任务，
+     * 任务完成之前memtable仍然保留在immutables中，可以正常查询
+     */
+    pub async fn flush_tasks(&self, data_path: &str) -> Result<Vec<FlushTask>> {
+        let mut tasks = Vec::new();
+        for memtable in self.table_indexs.get_immutables().all() {
+            let table_name = memtable.name().get_memtable_name();
+            let schema = self.ctx.table_provider(table_name.as_str()).await?.schema();
+            let batches = self.query_with_table(&table_name).await?;
+            let batch = concat_batches(&schema, &batches)?;
+            let fields: Vec<String> = batch
+                .schema()
+                .fields()
+                .iter()
+                .map(|field| field.name().clone())
+                .collect();
+            let sstable =
+                ParquetSsTable::new_with_memtable(&memtable, &fields).with_path(data_path);
+            tasks.push(FlushTask {
+                memtable,
+                sstable,
+                batch,
+            });
+        }
+        Ok(tasks)
+    }
+
+    /**
+     * memtable已经写入sstable并登记到manifest之后：
+     *  1、从immutables中删除memtable，并从SessionContext中注销，释放内存
+     *  2、在SessionContext中注册sstable，数据仍然可以查询
+     * sstable和memtable同名，在逻辑表中替换memtable(位置不变)，主键去重时仍然按写入的先后顺序
+     */
+    pub async fn finish_flush(
+        &mut self,
+        memtable: &MemTable,
+        sstable: ParquetSsTable,
+    ) -> Result<()> {
+        self.table_indexs.remove_immutable(memtable.name());
+        let table_name = memtable.name().get_memtable_name();
+        self.ctx.deregister_table(table_name.as_str())?;
+        self.register_sstable(sstable).await
+    }
+
+    /**
+     * 在SessionContext中注册一个sstable，表名和写入它的memtable一致
+     */
+    pub async fn register_sstable(&mut self, sstable: ParquetSsTable) -> Result<()> {
+        let opts = ParquetReadOptions {
+            file_extension: SSTABLE_FILE_SUFFIX,
+            ..Default::default()
+        };
+        let table_name = sstable.get_table_name().get_memtable_name();
+        self.ctx
+            .register_parquet(&table_name, &sstable.file_path(), opts)
+            .await?;
+        let prefix = sstable.get_table_name().get_prefix_name();
+        let provider = self.ctx.table_provider(table_name.as_str()).await?;
+        self.schemas.register(
+            &prefix,
+            &provider.schema(),
+            self.time_columns.column(&prefix),
+        )?;
+        let range = TimeRange::new(sstable.start, sstable.end);
+        self.logical_table(&prefix)?
+            .add(table_name, provider, range, sstable.lsn());
+        self.sstables.entry(prefix).or_default().push(sstable);
+        Ok(())
+    }
+
+    /**
+     * memtable是否还在等待写入sstable，写入过程中表被删除时返回false
+     */
+    pub fn is_flushing(&self, memtable: &MemTable) -> bool {
+        self.table_indexs
+            .get_immutables()
+            .get_tables(&memtable.name().get_prefix_name())
+            .contains(memtable.name())
+    }
+
+    pub fn contains_sstable(&self, sstable: &ParquetSsTable) -> bool {
+        let prefix = sstable.get_table_name().get_prefix_name();
+        self.sstables
+            .get(&prefix)
+            .is_some_and(|sstables| sstables.contains(sstable))
+    }
+
+    /**
+     * 指定表已经写入sstable的最大序列号
+     */
+    fn flushed_lsn(&self, prefix: &str) -> Option<Lsn> {
+        self.sstables
+            .get(prefix)
+            .and_then(|sstables| sstables.iter().map(|s| s.lsn()).max())
+    }
+
+    /**
+     * 所有还没有写入sstable的memtable占用的内存
+     */
+    pub fn memory_usage(&self) -> usize {
+        self.table_indexs
+            .memtables()
+            .iter()
+            .map(|memtable| memtable.size)
+            .sum()
+    }
+
+    /**
+     * 检查所有的mutable memtable，以下两种情况会把memtable转移到immutables中，返回被转移的memtable：
+     *  1、超过了 max_age
+     *  2、所有memtable占用的内存超过了 memory_budget，从最大的memtable开始，直到释放足够的内存
+     */
+    pub fn seal_memtables(&mut self) -> Vec<TableName> {
+        let now = now() as u64;
+        let mut mutables = self.table_indexs.get_mutables().all();
+        let mut sealed = Vec::new();
+        mutables.retain(|memtable| {
+            let prefix = memtable.name().get_prefix_name();
+            if self.limits.limit(&prefix).is_full(memtable, now) {
+                sealed.extend(self.table_indexs.seal(&prefix).map(|m| m.name));
+                false
+            } else {
+                true
+            }
+        });
+        if let Some(memory_budget) = self.limits.memory_budget {
+            let mut excess = self.memory_usage().saturating_sub(memory_budget);
+            mutables.sort_by_key(|memtable| std::cmp::Reverse(memtable.size));
+            for memtable in mutables {
+                if excess == 0 {
+                    break;
+                }
+                excess = excess.saturating_sub(memtable.size);
+                let prefix = memtable.name().get_prefix_name();
+                sealed.extend(self.table_indexs.seal(&prefix).map(|m| m.name));
+            }
+        }
+        sealed
+    }
+
+    /**
+     * PerTable 模式下一个表的wal可以推进到的checkpoint：这个表还在内存中的memtable的最小序列号 - 1，
+     * 没有memtable时为这个表已经写入sstable的最大序列号
+     */
+    pub fn table_checkpoint_lsn(&self, prefix: &str) -> Option<Lsn> {
+        match self
+            .table_indexs
+            .memtables()
+            .iter()
+            .filter(|memtable| memtable.name().get_prefix_name() == prefix)
+            .map(|memtable| memtable.min_lsn)
+            .min()
+        {
+            Some(min_lsn) => min_lsn.checked_sub(1),
+            None => self.flushed_lsn(prefix),
+        }
+    }
+
+    /**
+     * 可以推进到的checkpoint：所有还在内存中的memtable的最小序列号 - 1，
+     * 没有memtable时，所有的数据都已经写入sstable
+     */
+    pub fn checkpoint_lsn(&self, last_lsn: Option<Lsn>) -> Option<Lsn> {
+        match self
+            .table_indexs
+            .memtables()
+            .iter()
+            .map(|memtable| memtable.min_lsn)
+            .min()
+        {
+            Some(min_lsn) => min_lsn.checked_sub(1),
+            None => last_lsn,
         }
     }
 }
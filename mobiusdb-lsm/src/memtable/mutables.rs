@@ -40,6 +40,13 @@ impl Mutables {
         resp
     }
 
+    pub fn remove(&self, prefix: impl AsRef<str>) -> Option<TableName> {
+        self.tables.remove(prefix.as_ref());
+        self.tables_name
+            .remove(prefix.as_ref())
+            .map(|(_, table_name)| table_name)
+    }
+
     pub fn get_table(&self, prefix: impl AsRef<str>) -> Option<MemTable> {
         let r = self.tables.get(prefix.as_ref());
         match r {
@@ -48,6 +55,13 @@ impl Mutables {
         }
     }
 
+    pub fn all(&self) -> Vec<MemTable> {
+        self.tables
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect()
+    }
+
     pub fn get_table_name(&self, prefix: impl AsRef<str>) -> Option<TableName> {
         let r = self.tables_name.get(prefix.as_ref());
         match r {
@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+/**
+ * 每个表(TABLE_NAME 对应的前缀)的主键: 标签字段(tags) + 时间字段
+ *  1、声明了主键的表，同一个主键只保留最后写入的一行(last-write-wins)：
+ *     memtable中按写入顺序，memtable和sstable之间按wal序列号(写入的先后)
+ *  2、memtable写入sstable时去重，查询时合并所有memtable和sstable之后去重，每个主键只返回一行
+ *  3、没有声明主键的表不去重
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrimaryKeys {
+    pub(crate) tables: HashMap<String, Vec<String>>,
+}
+
+impl PrimaryKeys {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * 声明表的标签字段，主键为标签字段 + 表的时间字段
+     */
+    pub fn with_table_key<S: Into<String>>(
+        mut self,
+        prefix: impl Into<String>,
+        tags: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.tables
+            .insert(prefix.into(), tags.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /**
+     * 表的主键字段，time_column: 表的时间字段
+     */
+    pub fn key(&self, prefix: &str, time_column: &str) -> Option<Vec<String>> {
+        self.tables.get(prefix).map(|tags| {
+            let mut key = tags.clone();
+            if !key.iter().any(|tag| tag == time_column) {
+                key.push(time_column.to_string());
+            }
+            key
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PrimaryKeys;
+
+    #[test]
+    fn primary_keys_should_be_work() {
+        let keys = PrimaryKeys::new().with_table_key("cpu", ["host", "region"]);
+        assert_eq!(
+            keys.key("cpu", "timestamp"),
+            Some(vec![
+                "host".to_string(),
+                "region".to_string(),
+                "timestamp".to_string()
+            ])
+        );
+        assert_eq!(keys.key("mem", "timestamp"), None);
+    }
+}
@@ -0,0 +1,237 @@
+use std::sync::Arc;
+
+use arrow::datatypes::{DataType, Schema, SchemaRef};
+use dashmap::DashMap;
+
+use crate::error::{LsmError, LsmResult};
+
+use super::array_data_utils::merge_schemas;
+
+/**
+ * 每个表(TABLE_NAME 对应的前缀)的schema，写入的batch按照以下规则演进表的schema：
+ *  1、表的时间字段排在第一位，其他字段按第一次出现的先后顺序排列，新的字段追加在最后
+ *  2、新增的字段都可以为null(之前写入的数据中没有这个字段)，batch中缺少的字段也变为可以为null
+ *  3、同名字段的类型可以放宽: 整数 -> 更宽的整数，无符号整数 -> 更宽的有符号整数，
+ *     32位及以下的整数、浮点数 -> Float64，Utf8 -> LargeUtf8，Binary -> LargeBinary，
+ *     之前和之后写入的数据都按放宽之后的类型查询
+ *  4、其他类型的变化不兼容，写入wal之前被拒绝(LsmError::SchemaConflict)
+ * 表被删除之后schema也被删除
+ */
+#[derive(Debug, Clone, Default)]
+pub struct SchemaRegistry {
+    schemas: DashMap<String, SchemaRef>,
+}
+
+impl SchemaRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, prefix: &str) -> Option<SchemaRef> {
+        self.schemas.get(prefix).map(|schema| schema.clone())
+    }
+
+    /**
+     * 写入schema之后表的schema，只检查不登记
+     * time_column: 表的时间字段
+     */
+    pub fn check(&self, prefix: &str, schema: &Schema, time_column: &str) -> LsmResult<SchemaRef> {
+        let old = self.get(prefix).unwrap_or_else(|| Arc::new(schema.clone()));
+        evolve(&old, schema, time_column).map_err(|msg| LsmError::SchemaConflict {
+            table: prefix.to_string(),
+            msg,
+        })
+    }
+
+    /**
+     * 登记写入的schema，返回演进之后表的schema
+     * time_column: 表的时间字段
+     */
+    pub fn register(
+        &self,
+        prefix: &str,
+        schema: &Schema,
+        time_column: &str,
+    ) -> LsmResult<SchemaRef> {
+        let mut entry = self
+            .schemas
+            .entry(prefix.to_string())
+            .or_insert_with(|| Arc::new(schema.clone()));
+        let evolved =
+            evolve(&entry, schema, time_column).map_err(|msg| LsmError::SchemaConflict {
+                table: prefix.to_string(),
+                msg,
+            })?;
+        if evolved != *entry {
+            *entry = evolved.clone();
+        }
+        Ok(evolved)
+    }
+
+    pub fn remove(&self, prefix: &str) -> Option<SchemaRef> {
+        self.schemas.remove(prefix).map(|(_, schema)| schema)
+    }
+}
+
+/**
+ * 按照演进规则(merge_schemas)合并两个schema，不兼容时返回原因
+ */
+pub fn evolve(old: &Schema, new: &Schema, time_column: &str) -> Result<SchemaRef, String> {
+    merge_schemas([Arc::new(old.clone()), Arc::new(new.clone())], time_column)
+        .map(Arc::new)
+        .map_err(|e| e.to_string())
+}
+
+/**
+ * 两个类型放宽之后的类型，不兼容时返回None
+ */
+pub fn widen(a: &DataType, b: &DataType) -> Option<DataType> {
+    use DataType::*;
+    if a == b {
+        return Some(a.clone());
+    }
+    match (a, b) {
+        (Utf8, LargeUtf8) | (LargeUtf8, Utf8) => Some(LargeUtf8),
+        (Binary, LargeBinary) | (LargeBinary, Binary) => Some(LargeBinary),
+        _ if is_float(a) || is_float(b) => {
+            // 超过32位的整数转换为浮点数会丢失精度
+            let lossless =
+                |t: &DataType| is_float(t) || integer(t).is_some_and(|(_, bits)| bits <= 32);
+            (lossless(a) && lossless(b)).then_some(Float64)
+        }
+        _ => {
+            let (a_signed, a_bits) = integer(a)?;
+            let (b_signed, b_bits) = integer(b)?;
+            let (signed, bits) = match (a_signed, b_signed) {
+                (true, true) | (false, false) => (a_signed, a_bits.max(b_bits)),
+                // 有符号整数必须比无符号整数更宽
+                (true, false) if a_bits > b_bits => (true, a_bits),
+                (false, true) if b_bits > a_bits => (true, b_bits),
+                _ => (true, (a_bits.max(b_bits) * 2)),
+            };
+            integer_type(signed, bits)
+        }
+    }
+}
+
+fn is_float(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Float16 | DataType::Float32 | DataType::Float64
+    )
+}
+
+fn integer(data_type: &DataType) -> Option<(bool, u8)> {
+    match data_type {
+        DataType::Int8 => Some((true, 8)),
+        DataType::Int16 => Some((true, 16)),
+        DataType::Int32 => Some((true, 32)),
+        DataType::Int64 => Some((true, 64)),
+        DataType::UInt8 => Some((false, 8)),
+        DataType::UInt16 => Some((false, 16)),
+        DataType::UInt32 => Some((false, 32)),
+        DataType::UInt64 => Some((false, 64)),
+        _ => None,
+    }
+}
+
+fn integer_type(signed: bool, bits: u8) -> Option<DataType> {
+    match (signed, bits) {
+        (true, 8) => Some(DataType::Int8),
+        (true, 16) => Some(DataType::Int16),
+        (true, 32) => Some(DataType::Int32),
+        (true, 64) => Some(DataType::Int64),
+        (false, 8) => Some(DataType::UInt8),
+        (false, 16) => Some(DataType::UInt16),
+        (false, 32) => Some(DataType::UInt32),
+        (false, 64) => Some(DataType::UInt64),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::datatypes::{DataType, Field, Schema};
+
+    use crate::{error::LsmError, TIMESTAMP};
+
+    use super::{widen, SchemaRegistry};
+
+    #[test]
+    fn widen_should_follow_rules() {
+        use DataType::*;
+        assert_eq!(widen(&Int32, &Int64), Some(Int64));
+        assert_eq!(widen(&Int64, &Int8), Some(Int64));
+        assert_eq!(widen(&UInt8, &UInt32), Some(UInt32));
+        assert_eq!(widen(&UInt16, &Int8), Some(Int32));
+        assert_eq!(widen(&UInt8, &Int16), Some(Int16));
+        assert_eq!(widen(&UInt64, &Int64), None);
+        assert_eq!(widen(&Int32, &Float32), Some(Float64));
+        assert_eq!(widen(&Int64, &Float64), None);
+        assert_eq!(widen(&Utf8, &LargeUtf8), Some(LargeUtf8));
+        assert_eq!(widen(&Utf8, &Int32), None);
+    }
+
+    #[test]
+    fn schema_registry_should_evolve_schema() {
+        let registry = SchemaRegistry::new();
+        let schema = Schema::new(vec![
+            Field::new("name", DataType::Utf8, false),
+            Field::new("age", DataType::Int32, false),
+        ]);
+        registry.register("cpu", &schema, TIMESTAMP).unwrap();
+        // 新增字段、放宽类型、缺少字段
+        let schema = Schema::new(vec![
+            Field::new("address", DataType::Utf8, false),
+            Field::new("age", DataType::Int64, false),
+        ]);
+        let evolved = registry.register("cpu", &schema, TIMESTAMP).unwrap();
+        assert_eq!(
+            evolved
+                .fields()
+                .iter()
+                .map(|f| f.as_ref().clone())
+                .collect::<Vec<_>>(),
+            vec![
+                Field::new("name", DataType::Utf8, true),
+                Field::new("age", DataType::Int64, false),
+                Field::new("address", DataType::Utf8, true),
+            ]
+        );
+        // 较窄的类型按已有的类型写入
+        let schema = Schema::new(vec![Field::new("age", DataType::Int16, true)]);
+        let checked = registry.check("cpu", &schema, TIMESTAMP).unwrap();
+        assert_eq!(
+            checked.field_with_name("age").unwrap().data_type(),
+            &DataType::Int64
+        );
+        // 不兼容的类型
+        let schema = Schema::new(vec![Field::new("age", DataType::Utf8, true)]);
+        let resp = registry.check("cpu", &schema, TIMESTAMP);
+        assert!(matches!(resp, Err(LsmError::SchemaConflict { .. })));
+        assert!(registry.register("cpu", &schema, TIMESTAMP).is_err());
+        assert_eq!(registry.get("cpu"), Some(evolved));
+        assert!(registry.remove("cpu").is_some());
+        assert!(registry.register("cpu", &schema, TIMESTAMP).is_ok());
+    }
+
+    #[test]
+    fn schema_registry_should_put_time_column_first() {
+        let registry = SchemaRegistry::new();
+        let schema = Schema::new(vec![
+            Field::new("value", DataType::Int32, false),
+            Field::new("ts", DataType::Int64, false),
+        ]);
+        let checked = registry.check("cpu", &schema, "ts").unwrap();
+        assert_eq!(checked.field(0).name(), "ts");
+        registry.register("cpu", &schema, "ts").unwrap();
+        let schema = Schema::new(vec![
+            Field::new("host", DataType::Utf8, false),
+            Field::new("ts", DataType::Int64, false),
+        ]);
+        let evolved = registry.register("cpu", &schema, "ts").unwrap();
+        let names: Vec<&str> = evolved.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, vec!["ts", "value", "host"]);
+        assert!(!evolved.field(0).is_nullable());
+    }
+}
@@ -53,6 +53,45 @@ impl TableIndexs {
         }
     }
 
+    /**
+     * 删除前缀对应的所有memtable，返回被删除的表名
+     */
+    pub fn remove(&mut self, prefix: &str) -> Vec<TableName> {
+        let mut table_names = self.immutables.remove(prefix);
+        if let Some(table_name) = self.mutables.remove(prefix) {
+            table_names.push(table_name);
+        }
+        table_names
+    }
+
+    /**
+     * 把前缀对应的mutable memtable转移到immutables中，等待写入sstable，
+     * 之后这个前缀的数据写入新的memtable
+     */
+    pub fn seal(&mut self, prefix: &str) -> Option<MemTable> {
+        let mut memtable = self.mutables.get_table(prefix)?;
+        self.mutables.remove(prefix);
+        memtable.mutable = false;
+        self.immutables.insert(memtable.clone());
+        Some(memtable)
+    }
+
+    /**
+     * memtable写入sstable之后，从immutables中删除
+     */
+    pub fn remove_immutable(&mut self, table_name: &TableName) -> Option<MemTable> {
+        self.immutables.remove_table(table_name)
+    }
+
+    /**
+     * 所有还没有写入sstable的memtable
+     */
+    pub fn memtables(&self) -> Vec<MemTable> {
+        let mut memtables = self.immutables.all();
+        memtables.extend(self.mutables.all());
+        memtables
+    }
+
     pub fn contains_key(&self, prefix: impl AsRef<str>) -> bool {
         self.mutables.contains_key(prefix)
     }
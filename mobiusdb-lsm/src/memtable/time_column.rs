@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use crate::TIMESTAMP;
+
+/**
+ * 每个表(TABLE_NAME 对应的前缀)的时间字段，memtable和sstable按这个字段记录数据的时间范围
+ *  column: 默认的时间字段，默认为 timestamp
+ *  tables: 为每个表设置单独的时间字段，没有设置的表使用默认的时间字段
+ *
+ * 时间字段支持 UInt64、Int64、Timestamp(unit, tz)、Date32、Date64，
+ * 时间范围使用字段本身的单位，缺少时间字段或者时间字段类型不支持的数据在写入wal之前被拒绝
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeColumns {
+    pub(crate) column: String,
+    pub(crate) tables: HashMap<String, String>,
+}
+
+impl Default for TimeColumns {
+    fn default() -> Self {
+        Self {
+            column: TIMESTAMP.to_string(),
+            tables: HashMap::new(),
+        }
+    }
+}
+
+impl TimeColumns {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_column(mut self, column: impl Into<String>) -> Self {
+        self.column = column.into();
+        self
+    }
+
+    pub fn with_table_column(
+        mut self,
+        prefix: impl Into<String>,
+        column: impl Into<String>,
+    ) -> Self {
+        self.tables.insert(prefix.into(), column.into());
+        self
+    }
+
+    pub fn column(&self, prefix: &str) -> &str {
+        self.tables.get(prefix).unwrap_or(&self.column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TimeColumns;
+
+    #[test]
+    fn time_columns_should_be_work() {
+        let columns = TimeColumns::new().with_table_column("cpu", "ts");
+        assert_eq!(columns.column("cpu"), "ts");
+        assert_eq!(columns.column("mem"), "timestamp");
+        let columns = columns.with_column("time");
+        assert_eq!(columns.column("mem"), "time");
+    }
+}
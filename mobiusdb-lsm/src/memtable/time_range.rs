@@ -0,0 +1,268 @@
+use datafusion::{
+    arrow::datatypes::DataType,
+    common::ScalarValue,
+    logical_expr::{expr::Cast, expr::TryCast, Between, BinaryExpr, Expr, Operator},
+};
+
+/**
+ * 数据的时间范围(闭区间)，memtable和sstable都记录了自己的[start, end]，
+ * 查询时把时间字段上的条件转换为时间范围，跳过时间范围不重叠的memtable和sstable
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl Default for TimeRange {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl TimeRange {
+    pub fn new(start: u64, end: u64) -> Self {
+        Self { start, end }
+    }
+
+    /**
+     * 不限制时间
+     */
+    pub fn all() -> Self {
+        Self::new(0, u64::MAX)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start > self.end
+    }
+
+    pub fn overlaps(&self, other: &TimeRange) -> bool {
+        !self.is_empty() && !other.is_empty() && self.start <= other.end && other.start <= self.end
+    }
+
+    pub fn intersect(&self, other: &TimeRange) -> TimeRange {
+        TimeRange::new(self.start.max(other.start), self.end.min(other.end))
+    }
+
+    /**
+     * 包含两个范围的最小范围
+     */
+    pub fn union(&self, other: &TimeRange) -> TimeRange {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        TimeRange::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    /**
+     * 所有条件(AND)对应的时间范围，无法转换的条件不限制时间
+     */
+    pub fn from_filters(filters: &[Expr], column: &str) -> TimeRange {
+        filters
+            .iter()
+            .filter_map(|filter| Self::from_expr(filter, column))
+            .fold(TimeRange::all(), |range, other| range.intersect(&other))
+    }
+
+    /**
+     * 把一个条件转换为时间范围，支持:
+     *  1、时间字段和常量的比较: =、<、<=、>、>=，常量可以在左边
+     *  2、BETWEEN ... AND ...
+     *  3、AND / OR 组合的条件
+     * 其他条件返回None(不能用于裁剪)
+     */
+    pub fn from_expr(expr: &Expr, column: &str) -> Option<TimeRange> {
+        match expr {
+            Expr::BinaryExpr(BinaryExpr { left, op, right }) => match op {
+                Operator::And => {
+                    match (
+                        Self::from_expr(left, column),
+                        Self::from_expr(right, column),
+                    ) {
+                        (Some(l), Some(r)) => Some(l.intersect(&r)),
+                        (l, r) => l.or(r),
+                    }
+                }
+                Operator::Or => {
+                    let l = Self::from_expr(left, column)?;
+                    let r = Self::from_expr(right, column)?;
+                    Some(l.union(&r))
+                }
+                _ => {
+                    if is_column(left, column) {
+                        compare(*op, to_i128(right)?)
+                    } else if is_column(right, column) {
+                        compare(swap(*op)?, to_i128(left)?)
+                    } else {
+                        None
+                    }
+                }
+            },
+            Expr::Between(Between {
+                expr,
+                negated: false,
+                low,
+                high,
+            }) if is_column(expr, column) => {
+                let low = compare(Operator::GtEq, to_i128(low)?)?;
+                let high = compare(Operator::LtEq, to_i128(high)?)?;
+                Some(low.intersect(&high))
+            }
+            _ => None,
+        }
+    }
+}
+
+/**
+ * 是否是时间字段，只允许不改变数值的类型转换(转换为64位整数)，
+ * 例如 cast(ts as date) 会改变单位和数值，不能用于裁剪
+ */
+fn is_column(expr: &Expr, column: &str) -> bool {
+    match expr {
+        Expr::Column(col) => col.name == column,
+        Expr::Cast(Cast { expr, data_type }) | Expr::TryCast(TryCast { expr, data_type }) => {
+            keeps_value(data_type) && is_column(expr, column)
+        }
+        _ => false,
+    }
+}
+
+/**
+ * 整数和时间类型转换为64位整数时数值不变(单位不变)
+ */
+fn keeps_value(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::Int64 | DataType::UInt64)
+}
+
+/**
+ * 把常量转换为i128，负数在比较时需要单独处理
+ */
+fn to_i128(expr: &Expr) -> Option<i128> {
+    match expr {
+        Expr::Literal(value) => match value {
+            ScalarValue::UInt64(Some(v)) => Some(*v as i128),
+            ScalarValue::UInt32(Some(v)) => Some(*v as i128),
+            ScalarValue::UInt16(Some(v)) => Some(*v as i128),
+            ScalarValue::UInt8(Some(v)) => Some(*v as i128),
+            ScalarValue::Int64(Some(v)) => Some(*v as i128),
+            ScalarValue::Int32(Some(v)) => Some(*v as i128),
+            ScalarValue::Int16(Some(v)) => Some(*v as i128),
+            ScalarValue::Int8(Some(v)) => Some(*v as i128),
+            ScalarValue::Decimal128(Some(v), _, 0) => Some(*v),
+            // 时间类型使用字段本身的单位，和memtable、sstable记录的时间范围一致
+            ScalarValue::TimestampSecond(Some(v), _)
+            | ScalarValue::TimestampMillisecond(Some(v), _)
+            | ScalarValue::TimestampMicrosecond(Some(v), _)
+            | ScalarValue::TimestampNanosecond(Some(v), _)
+            | ScalarValue::Date64(Some(v)) => Some(*v as i128),
+            ScalarValue::Date32(Some(v)) => Some(*v as i128),
+            _ => None,
+        },
+        Expr::Cast(Cast { expr, data_type }) | Expr::TryCast(TryCast { expr, data_type })
+            if keeps_value(data_type) =>
+        {
+            to_i128(expr)
+        }
+        _ => None,
+    }
+}
+
+/**
+ * column op value 对应的时间范围
+ */
+fn compare(op: Operator, value: i128) -> Option<TimeRange> {
+    let max = u64::MAX as i128;
+    let clamp = |v: i128| v.clamp(0, max) as u64;
+    let range = match op {
+        Operator::Eq if (0..=max).contains(&value) => TimeRange::new(value as u64, value as u64),
+        Operator::Eq => TimeRange::new(1, 0),
+        Operator::Gt => TimeRange::new(clamp(value + 1), u64::MAX),
+        Operator::GtEq => TimeRange::new(clamp(value), u64::MAX),
+        // value <= 0 时没有满足条件的数据
+        Operator::Lt if value <= 0 => TimeRange::new(1, 0),
+        Operator::Lt => TimeRange::new(0, clamp(value - 1)),
+        Operator::LtEq if value < 0 => TimeRange::new(1, 0),
+        Operator::LtEq => TimeRange::new(0, clamp(value)),
+        _ => return None,
+    };
+    Some(range)
+}
+
+/**
+ * value op column 转换为 column op value
+ */
+fn swap(op: Operator) -> Option<Operator> {
+    match op {
+        Operator::Eq => Some(Operator::Eq),
+        Operator::Lt => Some(Operator::Gt),
+        Operator::LtEq => Some(Operator::GtEq),
+        Operator::Gt => Some(Operator::Lt),
+        Operator::GtEq => Some(Operator::LtEq),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::{
+        arrow::datatypes::DataType,
+        logical_expr::{cast, try_cast},
+        prelude::{col, lit},
+    };
+
+    use super::TimeRange;
+
+    #[test]
+    fn time_range_should_parse_filters() {
+        let ts = || col("timestamp");
+        let range = TimeRange::from_filters(
+            &[
+                ts().gt_eq(lit(100u64)),
+                lit(200i64).gt(ts()),
+                col("age").gt(lit(1)),
+            ],
+            "timestamp",
+        );
+        assert_eq!(range, TimeRange::new(100, 199));
+        assert!(range.overlaps(&TimeRange::new(150, 300)));
+        assert!(!range.overlaps(&TimeRange::new(200, 300)));
+        // BETWEEN 和 OR
+        let filter = ts().between(lit(10), lit(20)).or(ts().eq(lit(50u64)));
+        assert_eq!(
+            TimeRange::from_expr(&filter, "timestamp"),
+            Some(TimeRange::new(10, 50))
+        );
+        // OR 的一边不能转换时不能裁剪
+        let filter = ts().lt(lit(10)).or(col("age").eq(lit(1)));
+        assert_eq!(TimeRange::from_expr(&filter, "timestamp"), None);
+        // 不可能满足的条件
+        let range = TimeRange::from_filters(&[ts().lt(lit(0))], "timestamp");
+        assert!(range.is_empty());
+        assert!(!range.overlaps(&TimeRange::all()));
+        assert_eq!(
+            TimeRange::from_filters(&[col("age").gt(lit(1))], "timestamp"),
+            TimeRange::all()
+        );
+    }
+
+    #[test]
+    fn time_range_should_ignore_value_changing_casts() {
+        let ts = || col("timestamp");
+        // 转换为日期之后单位和数值都变了，不能裁剪
+        let filter = cast(ts(), DataType::Date32).eq(lit("1970-01-02"));
+        assert_eq!(TimeRange::from_expr(&filter, "timestamp"), None);
+        let filter = try_cast(ts(), DataType::Date32).gt(cast(lit(1), DataType::Date32));
+        assert_eq!(TimeRange::from_expr(&filter, "timestamp"), None);
+        let filter = ts().gt(cast(lit("2024-01-01"), DataType::Date32));
+        assert_eq!(TimeRange::from_expr(&filter, "timestamp"), None);
+        // 转换为64位整数时数值不变
+        let filter = cast(ts(), DataType::Int64).gt_eq(cast(lit(10u32), DataType::Int64));
+        assert_eq!(
+            TimeRange::from_expr(&filter, "timestamp"),
+            Some(TimeRange::new(10, u64::MAX))
+        );
+    }
+}
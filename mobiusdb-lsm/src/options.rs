@@ -0,0 +1,112 @@
+use crate::{
+    memtable::{
+        memtable_limit::MemTableLimits, primary_key::PrimaryKeys, time_column::TimeColumns,
+    },
+    wal::{
+        compression::Compression, retention::WalRetention, segment::WalSegment,
+        sync_policy::SyncPolicy, wal_mode::WalMode,
+    },
+};
+
+// 默认wal文件大小: 1G
+pub const DEFAULT_WAL_SIZE: usize = 1024 * 1024 * 1024;
+
+/**
+ * LSM 存储服务的配置
+ */
+#[derive(Debug, Clone)]
+pub struct LsmOptions {
+    // 单个wal文件的最大大小
+    pub(crate) wal_size: usize,
+    // wal的落盘策略
+    pub(crate) sync_policy: SyncPolicy,
+    // 已经写入sstable的wal文件的保留策略
+    pub(crate) wal_retention: WalRetention,
+    // wal记录的压缩算法
+    pub(crate) wal_compression: Compression,
+    // wal的组织方式
+    pub(crate) wal_mode: WalMode,
+    // wal文件的预分配和复用策略
+    pub(crate) wal_segment: WalSegment,
+    // sstable和manifest所在的数据目录，默认为wal目录下的 sstable 目录
+    pub(crate) data_path: Option<String>,
+    // memtable的大小、行数、时间限制和总内存上限
+    pub(crate) memtable_limits: MemTableLimits,
+    // 每个表的时间字段
+    pub(crate) time_columns: TimeColumns,
+    // 每个表的主键，声明了主键的表按主键去重
+    pub(crate) primary_keys: PrimaryKeys,
+}
+
+impl Default for LsmOptions {
+    fn default() -> Self {
+        Self {
+            wal_size: DEFAULT_WAL_SIZE,
+            sync_policy: SyncPolicy::default(),
+            wal_retention: WalRetention::default(),
+            wal_compression: Compression::default(),
+            wal_mode: WalMode::default(),
+            wal_segment: WalSegment::default(),
+            data_path: None,
+            memtable_limits: MemTableLimits::default(),
+            time_columns: TimeColumns::default(),
+            primary_keys: PrimaryKeys::default(),
+        }
+    }
+}
+
+impl LsmOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_wal_size(mut self, wal_size: usize) -> Self {
+        self.wal_size = wal_size;
+        self
+    }
+
+    pub fn with_sync_policy(mut self, sync_policy: SyncPolicy) -> Self {
+        self.sync_policy = sync_policy;
+        self
+    }
+
+    pub fn with_wal_retention(mut self, wal_retention: WalRetention) -> Self {
+        self.wal_retention = wal_retention;
+        self
+    }
+
+    pub fn with_wal_compression(mut self, wal_compression: Compression) -> Self {
+        self.wal_compression = wal_compression;
+        self
+    }
+
+    pub fn with_wal_mode(mut self, wal_mode: WalMode) -> Self {
+        self.wal_mode = wal_mode;
+        self
+    }
+
+    pub fn with_wal_segment(mut self, wal_segment: WalSegment) -> Self {
+        self.wal_segment = wal_segment;
+        self
+    }
+
+    pub fn with_data_path(mut self, data_path: impl Into<String>) -> Self {
+        self.data_path = Some(data_path.into());
+        self
+    }
+
+    pub fn with_memtable_limits(mut self, memtable_limits: MemTableLimits) -> Self {
+        self.memtable_limits = memtable_limits;
+        self
+    }
+
+    pub fn with_time_columns(mut self, time_columns: TimeColumns) -> Self {
+        self.time_columns = time_columns;
+        self
+    }
+
+    pub fn with_primary_keys(mut self, primary_keys: PrimaryKeys) -> Self {
+        self.primary_keys = primary_keys;
+        self
+    }
+}
@@ -0,0 +1,301 @@
+use anyhow::Result;
+use arrow_flight::utils::flight_data_to_batches;
+
+use crate::{
+    memtable::{memtable_limit::MemTableLimits, MemTableService},
+    sstable::manifest::Manifest,
+    wal::{offset::Lsn, wal_entry::WalEntry, wal_msg::WalMsg, WalService},
+};
+
+/**
+ * 崩溃恢复：
+ *  1、按创建顺序逐条读取所有的wal文件(PerTable 模式下包括每个表的wal)
+ *  2、将WalMsg还原为WalEntry
+ *  3、按照WalEntry的类型重放到MemTableService，重建mutable/immutable memtable
+ *
+ * manifest中登记的sstable先注册到MemTableService，已经写入sstable的数据在重放时会被跳过，
+ * 重放时按照 MemTableLimits 重新划分memtable
+ */
+pub async fn recover(
+    wal_service: &WalService,
+    manifest: &Manifest,
+    limits: MemTableLimits,
+) -> Result<MemTableService> {
+    recover_with(
+        wal_service,
+        manifest,
+        MemTableService::new().with_limits(limits),
+    )
+    .await
+}
+
+/**
+ * 使用已经配置好(限制、时间字段等)的MemTableService恢复
+ */
+pub async fn recover_with(
+    wal_service: &WalService,
+    manifest: &Manifest,
+    mut memtable: MemTableService,
+) -> Result<MemTableService> {
+    for sstable in manifest.sstables() {
+        memtable.register_sstable(sstable.clone()).await?;
+    }
+    for stream in wal_service.all_streams() {
+        replay_stream(stream, &mut memtable).await?;
+    }
+    Ok(memtable)
+}
+
+/**
+ * 重放一个wal中还没有被checkpoint覆盖的数据，
+ * PerTable 模式下可以通过 WalService::table_stream 只恢复一个表
+ */
+pub async fn replay_stream(stream: &WalService, memtable: &mut MemTableService) -> Result<()> {
+    for file_path in stream.wal_file_paths().await? {
+        // 逐条读取wal记录，恢复时的内存占用和wal文件的大小无关，写满的wal文件通过mmap读取
+        let mut reader = stream.segment_reader(&file_path).await?;
+        let mut num = 0;
+        while let Some(record) = reader.next().await? {
+            if stream.is_checkpointed(record.seq()) {
+                continue;
+            }
+            let wal_msg = WalMsg::decode(record.data()?)?;
+            replay(memtable, record.seq(), WalEntry::from_wal_msg(&wal_msg)?).await?;
+            num += 1;
+        }
+        println!("wal文件:【{}】,恢复数据 {} 条", file_path, num);
+    }
+    Ok(())
+}
+
+/**
+ * 重放一条WalEntry
+ */
+async fn replay(memtable: &mut MemTableService, lsn: Lsn, entry: WalEntry) -> Result<()> {
+    match entry {
+        WalEntry::InsertBatch(fds) => {
+            let batches = flight_data_to_batches(&fds)?;
+            memtable.batch_insert(batches, lsn).await?;
+        }
+        WalEntry::SchemaChange { table, schema } => {
+            memtable.change_schema(&table, &schema)?;
+        }
+        WalEntry::DropTable { table } => {
+            memtable.drop_table(&table)?;
+        }
+        // checkpoint之前的数据在加载wal时已经被过滤掉了
+        WalEntry::Checkpoint { .. } => {}
+        // 写入wal时已经被拒绝(WalService::append)，跳过会导致重启前后的状态不一致
+        entry @ WalEntry::DeleteRange { .. } => {
+            let msg = format!("wal entry {:?} can not be replayed", entry.kind());
+            return Err(anyhow::Error::msg(msg));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use arrow::{
+        array::{Int32Array, RecordBatch, StringArray, UInt64Array},
+        datatypes::*,
+    };
+    use arrow_flight::{utils::batches_to_flight_data, FlightData};
+
+    use crate::{
+        error::LsmError,
+        memtable::memtable_limit::MemTableLimits,
+        sstable::manifest::Manifest,
+        utils::time_utils::now,
+        wal::{wal_entry::WalEntry, Append, WalService},
+        TABLE_NAME,
+    };
+
+    use super::recover;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn recover_should_replay_all_wal_files() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-recover-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let path = path.to_str().unwrap().to_string();
+        // wal文件很小，保证数据写入到多个wal文件中
+        let mut service = WalService::init(&path, 1500).await.unwrap();
+        for i in 0..5 {
+            assert!(service.append(create_data("class_r", i)).await.is_ok());
+        }
+        // 模拟重启
+        let service = WalService::init(&path, 1500).await.unwrap();
+        let memtable = recover(&service, &Manifest::new(&path), MemTableLimits::default())
+            .await
+            .unwrap();
+        let batches = memtable.query_with_table_prefix("class_r").await.unwrap();
+        let rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+        assert_eq!(rows, 15);
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn recover_should_replay_drop_table() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-recover-drop-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let mut service = WalService::init(&path, 1024 * 1024).await.unwrap();
+        let entries = vec![
+            WalEntry::InsertBatch(create_data("class_d", 1)),
+            WalEntry::InsertBatch(create_data("class_k", 1)),
+            WalEntry::DropTable {
+                table: "class_d".to_string(),
+            },
+        ];
+        for entry in entries {
+            assert!(service.append(entry).await.is_ok());
+        }
+        service.sync().await.unwrap();
+        let service = WalService::init(&path, 1024 * 1024).await.unwrap();
+        let memtable = recover(&service, &Manifest::new(&path), MemTableLimits::default())
+            .await
+            .unwrap();
+        let dropped = memtable.query_with_table_prefix("class_d").await.unwrap();
+        assert!(dropped.is_empty());
+        let kept = memtable.query_with_table_prefix("class_k").await.unwrap();
+        assert_eq!(kept.iter().map(|batch| batch.num_rows()).sum::<usize>(), 3);
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn recover_should_replay_schema_change() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-recover-schema-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let mut service = WalService::init(&path, 1024 * 1024).await.unwrap();
+        // 新增level字段，age放宽为Int64
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("age", DataType::Int64, true),
+            Field::new("level", DataType::Utf8, true),
+        ]));
+        let entries = vec![
+            WalEntry::InsertBatch(create_data("class_s", 1)),
+            WalEntry::SchemaChange {
+                table: "class_s".to_string(),
+                schema,
+            },
+        ];
+        for entry in entries {
+            assert!(service.append(entry).await.is_ok());
+        }
+        service.sync().await.unwrap();
+        let service = WalService::init(&path, 1024 * 1024).await.unwrap();
+        let memtable = recover(&service, &Manifest::new(&path), MemTableLimits::default())
+            .await
+            .unwrap();
+        let schema = memtable.schemas().get("class_s").unwrap();
+        let age = schema.field_with_name("age").unwrap();
+        assert_eq!(age.data_type(), &DataType::Int64);
+        assert!(schema.field_with_name("level").is_ok());
+        let tables = memtable.tables().await.unwrap();
+        assert_eq!(tables[0].schema, schema);
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn recover_should_not_lose_rejected_delete_range() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-recover-delete-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let mut service = WalService::init(&path, 1024 * 1024).await.unwrap();
+        assert!(service
+            .append(WalEntry::InsertBatch(create_data("class_x", 1)))
+            .await
+            .is_ok());
+        // 恢复时还不能重放，写入wal之前被拒绝
+        let resp = service
+            .append(WalEntry::DeleteRange {
+                table: "class_x".to_string(),
+                start: 0,
+                end: u64::MAX,
+            })
+            .await;
+        assert!(matches!(resp, Err(LsmError::UnsupportedEntry(_))));
+        service.sync().await.unwrap();
+        // 重启前后的数据一致
+        let service = WalService::init(&path, 1024 * 1024).await.unwrap();
+        let memtable = recover(&service, &Manifest::new(&path), MemTableLimits::default())
+            .await
+            .unwrap();
+        let batches = memtable.query_with_table_prefix("class_x").await.unwrap();
+        assert_eq!(
+            batches.iter().map(|batch| batch.num_rows()).sum::<usize>(),
+            3
+        );
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn recover_should_fail_on_insert_error() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-recover-conflict-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let mut service = WalService::init(&path, 1024 * 1024).await.unwrap();
+        // age的类型和已有数据不兼容，恢复时无法写入memtable
+        let schema = Arc::new(
+            Schema::new(vec![
+                Field::new("age", DataType::Utf8, true),
+                Field::new("timestamp", DataType::UInt64, true),
+            ])
+            .with_metadata(HashMap::from([(
+                TABLE_NAME.to_string(),
+                "class_c".to_string(),
+            )])),
+        );
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec!["18"])),
+                Arc::new(UInt64Array::from(vec![now() as u64])),
+            ],
+        )
+        .unwrap();
+        let entries = vec![
+            WalEntry::InsertBatch(create_data("class_c", 1)),
+            WalEntry::InsertBatch(batches_to_flight_data(&schema, vec![batch]).unwrap()),
+        ];
+        for entry in entries {
+            assert!(service.append(entry).await.is_ok());
+        }
+        service.sync().await.unwrap();
+        let service = WalService::init(&path, 1024 * 1024).await.unwrap();
+        let resp = recover(&service, &Manifest::new(&path), MemTableLimits::default()).await;
+        assert!(resp.is_err());
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+
+    fn create_data(table_name: &str, age: i32) -> Vec<FlightData> {
+        let schema = Arc::new(
+            Schema::new(vec![
+                Field::new("age", DataType::Int32, true),
+                Field::new("name", DataType::Utf8, true),
+                Field::new("timestamp", DataType::UInt64, true),
+            ])
+            .with_metadata(HashMap::from([(
+                TABLE_NAME.to_string(),
+                table_name.to_string(),
+            )])),
+        );
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![age, 19, 20])),
+                Arc::new(StringArray::from(vec!["James", "Michael", "David"])),
+                Arc::new(UInt64Array::from(vec![
+                    now() as u64,
+                    (now() + 5) as u64,
+                    (now() + 7) as u64,
+                ])),
+            ],
+        )
+        .unwrap();
+        batches_to_flight_data(&schema, vec![batch]).unwrap()
+    }
+}
@@ -0,0 +1,224 @@
+use anyhow::Result;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio::io::AsyncWriteExt;
+
+use crate::utils::{file_utils::Level, table_name::TableName};
+
+use super::parquet::ParquetSsTable;
+
+pub const MANIFEST_FILE_NAME: &str = "MANIFEST";
+
+/**
+ * manifest: 记录数据目录下所有已经落盘的sstable
+ *  1、memtable写入sstable并fsync之后，才会登记到manifest
+ *  2、启动时只加载manifest中登记的sstable，没有登记的文件(写入过程中崩溃)会被忽略，
+ *     它们的数据还没有被checkpoint覆盖，会从wal中重新恢复
+ *
+ * 文件格式: | count(4) | sstable ... | crc(4) |
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Manifest {
+    // 数据目录
+    path: String,
+    sstables: Vec<ParquetSsTable>,
+}
+
+impl Manifest {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            sstables: Vec::new(),
+        }
+    }
+
+    fn file_path(path: &str) -> String {
+        format!("{}/{}", path.trim_end_matches('/'), MANIFEST_FILE_NAME)
+    }
+
+    /**
+     * 读取数据目录下的manifest文件，文件不存在时返回空的manifest
+     */
+    pub async fn load(path: impl Into<String>) -> Result<Self> {
+        let path = path.into();
+        match tokio::fs::read(Self::file_path(&path)).await {
+            Ok(buf) => Self::decode(path, Bytes::from(buf)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::new(path)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn sstables(&self) -> &[ParquetSsTable] {
+        &self.sstables
+    }
+
+    /**
+     * 登记新写入的sstable
+     */
+    pub async fn add(&mut self, sstables: impl IntoIterator<Item = ParquetSsTable>) -> Result<()> {
+        self.sstables.extend(sstables);
+        self.save().await
+    }
+
+    /**
+     * 只保留满足条件的sstable，其余的从manifest中删除，同时删除对应的文件
+     */
+    pub async fn retain(
+        &mut self,
+        f: impl Fn(&ParquetSsTable) -> bool,
+    ) -> Result<Vec<ParquetSsTable>> {
+        let (retained, removed): (Vec<_>, Vec<_>) = self.sstables.drain(..).partition(|s| f(s));
+        self.sstables = retained;
+        if removed.is_empty() {
+            return Ok(removed);
+        }
+        self.save().await?;
+        for sstable in removed.iter() {
+            let _ = tokio::fs::remove_file(sstable.file_path()).await;
+            println!("sstable文件:【{}】已删除", sstable.file_path());
+        }
+        Ok(removed)
+    }
+
+    /**
+     * 保存manifest，先写临时文件再重命名
+     */
+    async fn save(&self) -> Result<()> {
+        tokio::fs::create_dir_all(&self.path).await?;
+        let file_path = Self::file_path(&self.path);
+        let tmp_path = format!("{}.tmp", file_path);
+        let buf = self.encode();
+        let mut file = tokio::fs::File::create(&tmp_path).await?;
+        file.write_all(&buf).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&tmp_path, &file_path).await?;
+        Ok(())
+    }
+
+    fn encode(&self) -> BytesMut {
+        let mut buf = BytesMut::new();
+        buf.put_u32(self.sstables.len() as u32);
+        for sstable in self.sstables.iter() {
+            put_str(&mut buf, &sstable.name.prefix);
+            buf.put_u64(sstable.name.time.unwrap_or_default());
+            put_str(&mut buf, sstable.name.suffix.as_deref().unwrap_or_default());
+            buf.put_u8(sstable.level.into());
+            buf.put_u64(sstable.size as u64);
+            buf.put_u64(sstable.rows as u64);
+            buf.put_u64(sstable.start);
+            buf.put_u64(sstable.end);
+            buf.put_u64(sstable.lsn);
+            buf.put_u32(sstable.fields.len() as u32);
+            for field in sstable.fields.iter() {
+                put_str(&mut buf, field);
+            }
+        }
+        let crc = crc32c::crc32c(&buf);
+        buf.put_u32(crc);
+        buf
+    }
+
+    fn decode(path: String, mut bytes: Bytes) -> Result<Self> {
+        if bytes.len() < 8 {
+            return Err(anyhow::anyhow!("invalid manifest length: {}", bytes.len()));
+        }
+        let body = bytes.split_to(bytes.len() - 4);
+        if bytes.get_u32() != crc32c::crc32c(&body) {
+            return Err(anyhow::Error::msg("manifest checksum mismatch"));
+        }
+        let mut bytes = body;
+        let count = bytes.get_u32() as usize;
+        let mut sstables = Vec::with_capacity(count);
+        for _ in 0..count {
+            let prefix = get_str(&mut bytes)?;
+            let time = get_u64(&mut bytes)?;
+            let suffix = get_str(&mut bytes)?;
+            if bytes.remaining() < 1 + 8 * 5 + 4 {
+                return Err(anyhow::Error::msg("manifest is truncated"));
+            }
+            let level = Level::try_from(bytes.get_u8())?;
+            let size = bytes.get_u64() as usize;
+            let rows = bytes.get_u64() as usize;
+            let start = bytes.get_u64();
+            let end = bytes.get_u64();
+            let lsn = bytes.get_u64();
+            let fields_len = bytes.get_u32() as usize;
+            let mut fields = Vec::with_capacity(fields_len);
+            for _ in 0..fields_len {
+                fields.push(get_str(&mut bytes)?);
+            }
+            let name = TableName::new_with_opts(prefix, time, suffix);
+            sstables.push(ParquetSsTable {
+                name,
+                fields,
+                level,
+                size,
+                rows,
+                start,
+                end,
+                lsn,
+                path: path.clone(),
+            });
+        }
+        Ok(Self { path, sstables })
+    }
+}
+
+fn put_str(buf: &mut BytesMut, value: &str) {
+    buf.put_u32(value.len() as u32);
+    buf.put_slice(value.as_bytes());
+}
+
+fn get_u64(bytes: &mut Bytes) -> Result<u64> {
+    if bytes.remaining() < 8 {
+        return Err(anyhow::Error::msg("manifest is truncated"));
+    }
+    Ok(bytes.get_u64())
+}
+
+fn get_str(bytes: &mut Bytes) -> Result<String> {
+    if bytes.remaining() < 4 {
+        return Err(anyhow::Error::msg("manifest is truncated"));
+    }
+    let len = bytes.get_u32() as usize;
+    if bytes.remaining() < len {
+        return Err(anyhow::Error::msg("manifest is truncated"));
+    }
+    Ok(String::from_utf8(bytes.split_to(len).to_vec())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{memtable::memory::MemTable, utils::table_name::TableName, utils::time_utils::now};
+
+    use super::{Manifest, ParquetSsTable};
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn manifest_should_save_and_load() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-manifest-{}", now()));
+        let path = path.to_str().unwrap().to_string();
+        assert!(Manifest::load(&path).await.unwrap().sstables().is_empty());
+        let mut manifest = Manifest::new(&path);
+        let fields = vec!["name".to_string(), "timestamp".to_string()];
+        let sstables: Vec<ParquetSsTable> = (0..3)
+            .map(|i| {
+                let memtable = MemTable::new(TableName::new_with_time("cpu", i), i, i + 10)
+                    .with_lsn(i * 2, i * 2 + 1);
+                ParquetSsTable::new_with_memtable(&memtable, &fields).with_path(&path)
+            })
+            .collect();
+        manifest.add(sstables.clone()).await.unwrap();
+        let loaded = Manifest::load(&path).await.unwrap();
+        assert_eq!(loaded.sstables(), sstables.as_slice());
+        assert_eq!(loaded.sstables()[2].lsn(), 5);
+        // 删除之后重新加载
+        let removed = manifest.retain(|s| s.lsn() > 1).await.unwrap();
+        assert_eq!(removed, vec![sstables[0].clone()]);
+        let loaded = Manifest::load(&path).await.unwrap();
+        assert_eq!(loaded.sstables(), &sstables[1..]);
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+}
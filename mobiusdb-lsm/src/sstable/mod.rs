@@ -1,7 +1,11 @@
 use anyhow::Result;
 use arrow::array::RecordBatch;
+pub mod manifest;
 pub mod parquet;
 
+// 没有指定数据目录时，sstable保存在wal目录下的这个目录中
+pub const SSTABLE_DIR: &str = "sstable";
+
 // 1、sstable是一个分层的文件结构，每一层都是多个sstable文件，一张表是一个sstable文件，
 // 2、每个sstable文件都是一个完整的Parquet数据文件，可以使用Parquet工具查看。
 // 3、每一层的文件大小是固定的，每个sstable文件的大小是固定的。每个文件都有一个索引(时间序列)，通过索引可以快速确认数据是否在文件中
@@ -1,14 +1,19 @@
-use crate::utils::{
-    file_utils::{get_sstable_path, Level},
-    table_name::TableName,
+use crate::{
+    memtable::memory::MemTable,
+    utils::{
+        file_utils::{sstable_dir, sstable_path, Level, SSTABLE_FILE_SUFFIX, SSTABLE_PATH},
+        table_name::TableName,
+    },
+    wal::offset::Lsn,
 };
 use anyhow::Result;
 use arrow::array::RecordBatch;
-use datafusion::{dataframe::DataFrameWriteOptions, prelude::SessionContext};
+use datafusion::parquet::arrow::ArrowWriter;
+use tokio::io::AsyncWriteExt;
 
 use super::SsTable;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ParquetSsTable {
     // sstable文件名称
     pub(crate) name: TableName,
@@ -18,10 +23,16 @@ pub struct ParquetSsTable {
     pub(crate) level: Level,
     // 文件大小
     pub(crate) size: usize,
+    // 行数
+    pub(crate) rows: usize,
     // 开始时间
     pub(crate) start: u64,
     // 结束时间
     pub(crate) end: u64,
+    // 文件中数据对应的最大wal序列号
+    pub(crate) lsn: Lsn,
+    // 数据目录，文件保存在 {path}/{level}/ 下
+    pub(crate) path: String,
 }
 
 impl ParquetSsTable {
@@ -32,10 +43,55 @@ impl ParquetSsTable {
             fields: Vec::new(),
             level: Level::L0,
             size: 0,
+            rows: 0,
             start: 0,
             end: 0,
+            lsn: 0,
+            path: SSTABLE_PATH.to_string(),
         }
     }
+
+    /**
+     * memtable写入到L0的sstable，文件名称和memtable名称一致
+     */
+    pub fn new_with_memtable(memtable: &MemTable, fields: &[String]) -> Self {
+        let name = memtable.name();
+        Self {
+            name: TableName {
+                prefix: name.prefix.clone(),
+                time: name.time,
+                suffix: Some(SSTABLE_FILE_SUFFIX.to_string()),
+            },
+            fields: fields.to_vec(),
+            level: Level::L0,
+            size: memtable.size,
+            rows: memtable.rows,
+            start: memtable.start,
+            end: memtable.end,
+            lsn: memtable.max_lsn,
+            path: SSTABLE_PATH.to_string(),
+        }
+    }
+
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = path.into();
+        self
+    }
+
+    /**
+     * sstable文件的路径
+     */
+    pub fn file_path(&self) -> String {
+        sstable_path(&self.path, &self.name.get_sstable_name(), self.level)
+    }
+
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    pub fn lsn(&self) -> Lsn {
+        self.lsn
+    }
     pub fn get_sstable_name(&self) -> String {
         self.name.get_sstable_name()
     }
@@ -57,8 +113,11 @@ impl ParquetSsTable {
             fields: fields.clone(),
             level,
             size,
+            rows: 0,
             start,
             end,
+            lsn: 0,
+            path: SSTABLE_PATH.to_string(),
         }
     }
 
@@ -67,14 +126,24 @@ impl ParquetSsTable {
      * 默认：
      *  1、文件只能写入L0层级
      *  2、RecordBatch默认已排序(以时间)
+     *
+     * 先写临时文件，fsync之后再重命名，返回时文件已经落盘
      */
     pub async fn write(&self, batch: &RecordBatch) -> Result<bool> {
-        let ctx = SessionContext::new();
-        let df = ctx.read_batch(batch.clone())?;
-        let path = get_sstable_path(&self.name.get_sstable_name(), Level::L0);
-        let _ = df
-            .write_parquet(&path, DataFrameWriteOptions::new(), None)
-            .await?;
+        let mut buf = Vec::new();
+        let mut writer = ArrowWriter::try_new(&mut buf, batch.schema(), None)?;
+        writer.write(batch)?;
+        writer.close()?;
+        let path = self.file_path();
+        let dir = sstable_dir(&self.path, self.level);
+        tokio::fs::create_dir_all(&dir).await?;
+        let tmp_path = format!("{}.tmp", path);
+        let mut file = tokio::fs::File::create(&tmp_path).await?;
+        file.write_all(&buf).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+        // 重命名之后目录也需要落盘
+        tokio::fs::File::open(&dir).await?.sync_all().await?;
         Ok(true)
     }
 }
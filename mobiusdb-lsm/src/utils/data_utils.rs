@@ -1,15 +1,20 @@
 use anyhow::Result;
 use arrow::{
-    array::{ArrayRef, RecordBatch, UInt64Array},
-    compute::{sort_to_indices, SortOptions},
+    array::{AsArray, RecordBatch},
+    compute::{cast, max, min, sort_to_indices, SortOptions},
+    datatypes::{DataType, Int64Type, UInt64Type},
 };
 use arrow_flight::{
     utils::{batches_to_flight_data, flight_data_to_batches},
     FlightData,
 };
-use datafusion::prelude::SessionContext;
 
-use crate::memtable::array_data_utils::merge_batches;
+use crate::{
+    error::{LsmError, LsmResult},
+    memtable::array_data_utils::merge_batches,
+    wal::{wal_entry::EntryKind, wal_msg::WalMsg},
+    TABLE_NAME, TIMESTAMP,
+};
 
 pub fn batch_to_flight_data(batch: RecordBatch) -> Result<Vec<FlightData>> {
     let mut vecs = Vec::new();
@@ -24,22 +29,68 @@ pub fn batch_to_flight_data(batch: RecordBatch) -> Result<Vec<FlightData>> {
  */
 pub fn flight_data_to_batch(fds: &Vec<FlightData>) -> Result<RecordBatch> {
     let batches = flight_data_to_batches(&fds)?;
-    let batch = merge_batches(&batches)?;
+    let batch = merge_batches(&batches, TIMESTAMP)?;
     let schema = batch.schema();
     let empty_batch = RecordBatch::new_empty(schema);
-    merge_batches(&vec![batch, empty_batch])
+    merge_batches(&vec![batch, empty_batch], TIMESTAMP)
+}
+
+/**
+ * 将wal中的一条WalMsg还原为RecordBatch
+ */
+pub fn wal_msg_to_batches(wal_msg: &WalMsg) -> Result<Vec<RecordBatch>> {
+    if wal_msg.kind() != EntryKind::InsertBatch {
+        let msg = format!("wal msg kind {:?} is not InsertBatch", wal_msg.kind());
+        return Err(anyhow::Error::msg(msg));
+    }
+    let fds = wal_msg.to_messages::<FlightData>()?;
+    let batches = flight_data_to_batches(&fds)?;
+    Ok(batches)
 }
 
-pub async fn get_timestamp_from_batch(batch: &RecordBatch) -> Result<(u64, u64)> {
-    let ctx = SessionContext::new();
-    let df = ctx.read_batch(batch.clone()).unwrap();
-    let fd = df.select_columns(&["timestamp"])?.collect().await?;
-    let batch = fd.first().unwrap();
-    let column_array: ArrayRef = batch.column(0).clone();
-    let u64_array = column_array.as_any().downcast_ref::<UInt64Array>().unwrap();
-    let mut timestamps: Vec<u64> = u64_array.values().into_iter().map(|x| x.clone()).collect();
-    timestamps.sort();
-    Ok((timestamps[0], timestamps[timestamps.len() - 1]))
+/**
+ * batch中时间字段的最小值和最大值(忽略null)，使用字段本身的单位：
+ *  1、支持 UInt64、Int64、Timestamp(unit, tz)、Date32、Date64
+ *  2、缺少时间字段、类型不支持、有负数或者没有非null的值时返回错误
+ */
+pub fn get_timestamp_from_batch(batch: &RecordBatch, column: &str) -> LsmResult<(u64, u64)> {
+    let schema = batch.schema();
+    let table = schema
+        .metadata()
+        .get(TABLE_NAME)
+        .cloned()
+        .unwrap_or_default();
+    let invalid = |msg: String| LsmError::InvalidTimeColumn {
+        table: table.clone(),
+        column: column.to_string(),
+        msg,
+    };
+    let array = batch
+        .column_by_name(column)
+        .ok_or(LsmError::MissingTimeColumn {
+            table: table.clone(),
+            column: column.to_string(),
+        })?;
+    let range = match array.data_type() {
+        DataType::UInt64 => {
+            let array = array.as_primitive::<UInt64Type>();
+            min(array).zip(max(array))
+        }
+        DataType::Int64 | DataType::Timestamp(_, _) | DataType::Date32 | DataType::Date64 => {
+            let array = cast(array, &DataType::Int64).map_err(|e| invalid(e.to_string()))?;
+            let array = array.as_primitive::<Int64Type>();
+            match min(array).zip(max(array)) {
+                Some((start, _)) if start < 0 => {
+                    return Err(invalid(format!("negative time value {}", start)));
+                }
+                range => range.map(|(start, end)| (start as u64, end as u64)),
+            }
+        }
+        data_type => {
+            return Err(invalid(format!("unsupported data type {}", data_type)));
+        }
+    };
+    range.ok_or(invalid("no valid time value".to_string()))
 }
 
 pub fn batch_size(batch: &RecordBatch) -> usize {
@@ -68,3 +119,46 @@ pub fn batch_sort(batch: &RecordBatch, sort_by: &str) -> Result<RecordBatch> {
     let sorted_batch = RecordBatch::try_new(schema, vs)?;
     Ok(sorted_batch)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use arrow::{
+        array::{ArrayRef, Int64Array, RecordBatch, StringArray, TimestampMillisecondArray},
+        datatypes::{Field, Schema},
+    };
+
+    use crate::{error::LsmError, TABLE_NAME};
+
+    use super::get_timestamp_from_batch;
+
+    fn create_batch(column: ArrayRef) -> RecordBatch {
+        let schema = Schema::new(vec![Field::new("ts", column.data_type().clone(), true)])
+            .with_metadata(HashMap::from([(TABLE_NAME.to_string(), "cpu".to_string())]));
+        RecordBatch::try_new(Arc::new(schema), vec![column]).unwrap()
+    }
+
+    #[test]
+    fn get_timestamp_should_support_time_types() {
+        let batch = create_batch(Arc::new(
+            TimestampMillisecondArray::from(vec![Some(30), None, Some(10)]).with_timezone("+08:00"),
+        ));
+        assert_eq!(get_timestamp_from_batch(&batch, "ts").unwrap(), (10, 30));
+        let batch = create_batch(Arc::new(Int64Array::from(vec![5, 1, 3])));
+        assert_eq!(get_timestamp_from_batch(&batch, "ts").unwrap(), (1, 5));
+        // 缺少时间字段
+        let resp = get_timestamp_from_batch(&batch, "timestamp");
+        assert!(matches!(resp, Err(LsmError::MissingTimeColumn { .. })));
+        // 类型不支持、负数、全部为null
+        let batch = create_batch(Arc::new(StringArray::from(vec!["1"])));
+        let resp = get_timestamp_from_batch(&batch, "ts");
+        assert!(matches!(resp, Err(LsmError::InvalidTimeColumn { .. })));
+        let batch = create_batch(Arc::new(Int64Array::from(vec![-1, 3])));
+        let resp = get_timestamp_from_batch(&batch, "ts");
+        assert!(matches!(resp, Err(LsmError::InvalidTimeColumn { .. })));
+        let batch = create_batch(Arc::new(Int64Array::from(vec![None, None])));
+        let resp = get_timestamp_from_batch(&batch, "ts");
+        assert!(matches!(resp, Err(LsmError::InvalidTimeColumn { .. })));
+    }
+}
@@ -5,10 +5,14 @@ use super::time_utils::now;
 
 pub const SSTABLE_PATH: &str = "/Users/firoly/Documents/code/rust/mobiusdb/mobiusdb-lsm/tmp/";
 pub const SSTABLE_FILE_SUFFIX: &'static str = ".sst";
+pub const WAL_FILE_SUFFIX: &str = ".wal";
+pub const INDEX_FILE_SUFFIX: &str = ".idx";
+// 等待复用的wal文件
+pub const RECYCLE_FILE_SUFFIX: &str = ".recycle";
 
 pub const WAL_PATH: &str = "/Users/firoly/Documents/code/rust/mobiusdb/mobiusdb-lsm/tmp/tmp/wal";
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Level {
     L0,
     L1,
@@ -30,6 +34,35 @@ impl From<Level> for String {
     }
 }
 
+impl From<Level> for u8 {
+    fn from(value: Level) -> Self {
+        match value {
+            Level::L0 => 0,
+            Level::L1 => 1,
+            Level::L2 => 2,
+            Level::L3 => 3,
+            Level::L4 => 4,
+            Level::L5 => 5,
+        }
+    }
+}
+
+impl TryFrom<u8> for Level {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Level::L0),
+            1 => Ok(Level::L1),
+            2 => Ok(Level::L2),
+            3 => Ok(Level::L3),
+            4 => Ok(Level::L4),
+            5 => Ok(Level::L5),
+            _ => Err(anyhow::anyhow!("invalid sstable level: {}", value)),
+        }
+    }
+}
+
 /**
  *
  */
@@ -45,13 +78,27 @@ pub fn get_sstable_file_name(table_path: &str) -> Option<String> {
  * 根据文件名称获取sstable文件的路径
  */
 pub fn get_sstable_path(table_name: &str, level: Level) -> String {
-    let new_path = match SSTABLE_PATH.ends_with("/") {
-        true => format!("{}{}/", SSTABLE_PATH, String::from(level)),
-        false => format!("{}/{}/", SSTABLE_PATH, String::from(level)),
-    };
+    sstable_path(SSTABLE_PATH, table_name, level)
+}
+
+/**
+ * 根据数据目录和文件名称获取sstable文件的路径: {data_path}/{level}/{table_name}.sst
+ */
+pub fn sstable_path(data_path: &str, table_name: &str, level: Level) -> String {
+    let new_path = sstable_dir(data_path, level);
     match table_name.ends_with(SSTABLE_FILE_SUFFIX) {
-        true => format!("{}/{}", new_path, table_name),
-        false => format!("{}/{}{}", new_path, table_name, SSTABLE_FILE_SUFFIX),
+        true => format!("{}{}", new_path, table_name),
+        false => format!("{}{}{}", new_path, table_name, SSTABLE_FILE_SUFFIX),
+    }
+}
+
+/**
+ * 数据目录下每一层sstable文件所在的目录: {data_path}/{level}/
+ */
+pub fn sstable_dir(data_path: &str, level: Level) -> String {
+    match data_path.ends_with("/") {
+        true => format!("{}{}/", data_path, String::from(level)),
+        false => format!("{}/{}/", data_path, String::from(level)),
     }
 }
 
@@ -108,6 +155,52 @@ pub async fn get_files_name(path: &str) -> Result<Vec<String>> {
     Ok(resp)
 }
 
+/**
+ * 获取指定路径下所有wal文件的名称，按文件名中的编号(segment id 或创建时间)升序排列
+ */
+pub async fn get_wal_files_name(path: &str) -> Result<Vec<String>> {
+    let mut files_name: Vec<String> = get_files_name(path)
+        .await?
+        .into_iter()
+        .filter(|name| name.ends_with(WAL_FILE_SUFFIX))
+        .collect();
+    files_name.sort_by_key(|name| (file_id(name, WAL_FILE_SUFFIX), name.clone()));
+    Ok(files_name)
+}
+
+/**
+ * 获取指定路径下所有等待复用的wal文件的名称
+ */
+pub async fn get_recycle_files_name(path: &str) -> Result<Vec<String>> {
+    let mut files_name: Vec<String> = get_files_name(path)
+        .await?
+        .into_iter()
+        .filter(|name| name.ends_with(RECYCLE_FILE_SUFFIX))
+        .collect();
+    files_name.sort_by_key(|name| (file_id(name, RECYCLE_FILE_SUFFIX), name.clone()));
+    Ok(files_name)
+}
+
+/**
+ * 文件名中的编号，例如 00000000000000000012.wal -> 12
+ */
+pub fn file_id(file_name: &str, suffix: &str) -> Option<u64> {
+    file_name.strip_suffix(suffix)?.parse().ok()
+}
+
+/**
+ * 异步打开文件，此时文件可以在任意位置读写(不是追加模式)
+ */
+pub async fn async_open_rw_file(path: &str) -> Result<tokio::fs::File> {
+    Ok(tokio::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .await?)
+}
+
 /**
  * 异步打开文件，此时文件只读
  */
@@ -1,7 +1,7 @@
 use std::{io::SeekFrom, sync::Arc};
 
 use anyhow::Result;
-use bytes::{Buf, BufMut, Bytes, BytesMut};
+use bytes::{Bytes, BytesMut};
 use tokio::{
     fs::File,
     io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
@@ -9,26 +9,24 @@ use tokio::{
 };
 
 use crate::{
-    utils::{
-        file_utils::{async_open_flie, async_open_only_read_flie},
-        time_utils::now,
-    },
+    utils::file_utils::{async_open_only_read_flie, async_open_rw_file, WAL_FILE_SUFFIX},
     wal::wal_msg::WalMsg,
 };
 
 use super::{
+    compression::Compression,
     offset::Offset,
+    reader::WalReader,
+    record::{RecoveryMode, WalFileHeader, WalRecord, FILE_HEADER_LEN, RECORD_HEADER_LEN},
+    segment::{next_segment_id, preallocate, segment_file_name},
     serialization::{Decoder, Encoder},
-    wal_message::{IntoWalMessage, WalMessage},
-    wal_msg::{walmsgs_to_offsets, IntoWalMsg},
+    wal_msg::IntoWalMsg,
     Append,
 };
 
 // 默认wal文件大小: 1G
 const MAX_SIZE: usize = 1024 * 1024 * 1024;
 
-const WAL: &'static str = ".wal";
-
 /**
  * 活动的wal文件，wal文件是顺序写入的
  */
@@ -39,59 +37,52 @@ pub struct ActiveWal {
     wal: Arc<Mutex<File>>,
     max_size: usize,
     size: usize,
+    // 下一条记录的序列号
+    next_seq: u64,
+    // 新写入记录使用的压缩算法
+    compression: Compression,
 }
 
 impl ActiveWal {
     pub async fn new(path: &str) -> Result<Self> {
-        let file_name = now().to_string() + WAL;
-        let path = if path.ends_with("/") {
-            format!("{}{}", path, file_name)
-        } else {
-            format!("{}/{}", path, file_name)
-        };
-        let file = async_open_flie(path.as_str()).await?;
-        let position = file.metadata().await?.len() as usize;
-        Ok(Self {
-            name: file_name,
-            write_enable: true,
-            wal: Arc::new(Mutex::new(file)),
-            max_size: MAX_SIZE,
-            size: position,
-        })
+        Self::with_size(path, MAX_SIZE).await
     }
 
     /**
      * 加载wal文件，此时wal文件为读写模式
-     * 加载wal文件时，会读取wal所有文件，并对其构建索引
+     * 加载wal文件时，会逐条读取wal文件中的记录，并对其构建索引
+     * 文件末尾写了一半的记录会被截断
      */
     pub async fn load(path: &str) -> Result<(Self, Vec<Offset>)> {
-        // let mut offsets = Vec::new();
         let file_name = path
             .split("/")
             .collect::<Vec<&str>>()
             .last()
             .unwrap()
             .to_string();
-        let mut file = async_open_flie(path).await?;
-
-        let position = file.metadata().await?.len() as usize;
-        // println!("position = {}",position);
-        let wal_msgs = {
-            let mut buf = Vec::new();
-            // println!("buf len = {}",buf.len());
-            file.read_to_end(&mut buf).await?;
-            let vs = Vec::<WalMsg>::decode(Bytes::from(buf));
-            vs.unwrap()
-        };
-        // println!("wal_msgs_len = {}",wal_msgs.len());
-        let offsets = walmsgs_to_offsets(&wal_msgs);
+        let mut reader = WalReader::open(path, RecoveryMode::TruncateTail).await?;
+        let mut offsets = Vec::new();
+        while let Some((offset, _)) = reader.next().await? {
+            offsets.push(offset);
+        }
+        let file = async_open_rw_file(path).await?;
+        if reader.is_torn() {
+            println!(
+                "wal文件:【{}】末尾数据不完整，截断到 {} 字节",
+                file_name,
+                reader.valid_len()
+            );
+            file.set_len(reader.valid_len() as u64).await?;
+        }
         Ok((
             Self {
                 name: file_name,
                 write_enable: false,
                 wal: Arc::new(Mutex::new(file)),
                 max_size: MAX_SIZE,
-                size: position,
+                size: reader.valid_len(),
+                next_seq: reader.next_seq(),
+                compression: Compression::default(),
             },
             offsets,
         ))
@@ -115,48 +106,138 @@ impl ActiveWal {
             wal: Arc::new(Mutex::new(file)),
             max_size: MAX_SIZE,
             size: position,
+            next_seq: 0,
+            compression: Compression::default(),
         })
     }
 
     pub async fn with_size(path: &str, max_size: usize) -> Result<Self> {
-        let file_name = now().to_string() + ".wal";
-        let path = if path.ends_with("/") {
-            format!("{}{}", path, file_name)
-        } else {
-            format!("{}/{}", path, file_name)
-        };
-        let file = async_open_flie(path.as_str()).await?;
-        let position = file.metadata().await?.len() as usize;
+        Self::with_start_seq(path, max_size, 0).await
+    }
+
+    /**
+     * 新建wal文件，文件中第一条记录的序列号为start_seq，segment id 为目录中最大的编号 + 1
+     */
+    pub async fn with_start_seq(path: &str, max_size: usize, start_seq: u64) -> Result<Self> {
+        let segment_id = next_segment_id(path).await?;
+        Self::with_segment_id(path, segment_id, max_size, start_seq).await
+    }
+
+    pub async fn with_segment_id(
+        path: &str,
+        segment_id: u64,
+        max_size: usize,
+        start_seq: u64,
+    ) -> Result<Self> {
+        Self::create(path, segment_file_name(segment_id), max_size, start_seq).await
+    }
+
+    /**
+     * 复用一个旧的wal文件：重命名为新的 segment id，并重写文件头，
+     * 文件中遗留的旧数据在读取时会被忽略
+     */
+    pub async fn recycle(
+        path: &str,
+        recycle_path: &str,
+        segment_id: u64,
+        max_size: usize,
+        start_seq: u64,
+    ) -> Result<Self> {
+        let file_name = segment_file_name(segment_id);
+        let file_path = format!("{}/{}", path.trim_end_matches('/'), file_name);
+        tokio::fs::rename(recycle_path, &file_path).await?;
+        let mut file = async_open_rw_file(&file_path).await?;
+        let mut buf = BytesMut::new();
+        let position = WalFileHeader::recycled(start_seq).encode(&mut buf)?;
+        file.seek(SeekFrom::Start(0)).await?;
+        file.write_all(&buf).await?;
+        file.flush().await?;
         Ok(Self {
             name: file_name,
             write_enable: true,
             wal: Arc::new(Mutex::new(file)),
             max_size,
             size: position,
+            next_seq: start_seq,
+            compression: Compression::default(),
         })
     }
 
     pub async fn with_name(path: &str, file_name: &str, max_size: usize) -> Result<Self> {
-        let file_name = file_name.to_string() + ".wal";
+        let file_name = file_name.to_string() + WAL_FILE_SUFFIX;
+        Self::create(path, file_name, max_size, 0).await
+    }
+
+    /**
+     * 打开(或新建)wal文件，空文件会先写入文件头
+     */
+    async fn create(
+        path: &str,
+        file_name: String,
+        max_size: usize,
+        start_seq: u64,
+    ) -> Result<Self> {
         let path = if path.ends_with("/") {
             format!("{}{}", path, file_name)
         } else {
             format!("{}/{}", path, file_name)
         };
-        let file = async_open_flie(path.as_str()).await?;
-        let position = file.metadata().await?.len() as usize;
+        let mut file = async_open_rw_file(path.as_str()).await?;
+        let mut position = file.metadata().await?.len() as usize;
+        if position == 0 {
+            let mut buf = BytesMut::new();
+            position = WalFileHeader::new(start_seq).encode(&mut buf)?;
+            file.write_all(&buf).await?;
+            file.flush().await?;
+        }
         Ok(Self {
             name: file_name,
             write_enable: true,
             wal: Arc::new(Mutex::new(file)),
             max_size,
             size: position,
+            next_seq: start_seq,
+            compression: Compression::default(),
         })
     }
 
     pub fn name(&self) -> String {
         self.name.clone()
     }
+
+    pub fn is_writeable(&self) -> bool {
+        self.write_enable
+    }
+
+    pub fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    /**
+     * 下一条记录的序列号至少为seq，用于多个wal共享全局递增的LSN
+     */
+    pub fn advance_seq(&mut self, seq: u64) {
+        self.next_seq = self.next_seq.max(seq);
+    }
+
+    /**
+     * 设置新写入记录使用的压缩算法，不影响已经写入的记录
+     */
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+    }
+
+    /**
+     * 为wal文件预分配 max_size 大小的磁盘空间
+     */
+    pub async fn preallocate(&self) -> Result<()> {
+        let file = self.wal.lock().await;
+        preallocate(&file, self.max_size).await
+    }
 }
 
 /**
@@ -171,8 +252,6 @@ impl ActiveWal {
             return Err(anyhow::Error::msg("wal file is not writeable"));
         }
         let wal_msg = WalMsg::from(fds);
-        // let wal_message = fds.into_wal_message();
-        // self.append_wal_message(wal_message).await
         self.append_wal_msg(wal_msg).await
     }
 
@@ -185,87 +264,117 @@ impl ActiveWal {
         self.append_bytes(wal_buf.freeze()).await
     }
 
-    async fn append_wal_message(&mut self, wal_msg: WalMessage) -> Result<Offset> {
-        let v_len = wal_msg.get_bytes_len();
-        let mut buff = BytesMut::new();
-        wal_msg.encode(&mut buff).unwrap();
-        if !self.write_enable {
-            return Err(anyhow::Error::msg("wal file is not writeable"));
-        }
-        let mut file = self.wal.lock().await;
-        // 当前文件的下标
-        if self.size > self.max_size {
-            self.write_enable = false;
-            return Err(anyhow::Error::msg("Wal file is full"));
-        }
-        let mut index = Offset::from((self.size + 4) as usize);
-        file.write_all(&buff.freeze())
-            .await
-            .expect("Failed to write");
-        index.update((v_len) as usize);
-        let add_size = (v_len + 4) as usize;
-        self.size += add_size;
-        Ok(index)
+    /**
+     * 将bytes封装为一条WalRecord写入文件，返回payload所在的位置
+     */
+    async fn append_bytes(&mut self, bytes: Bytes) -> Result<Offset> {
+        let record = self.build_record(bytes)?;
+        self.write_record(record).await
     }
 
-    async fn append_bytes(&mut self, bytes: Bytes) -> Result<Offset> {
+    /**
+     * 按照下一条记录的序列号和当前的压缩算法构建一条记录，
+     * 写入之前可以通过 encode_len 判断文件剩余的空间是否足够
+     */
+    pub fn build_record(&self, bytes: Bytes) -> Result<WalRecord> {
+        WalRecord::with_compression(self.next_seq, bytes, self.compression)
+    }
+
+    /**
+     * 文件剩余的空间是否可以写入len字节的记录
+     */
+    pub fn fits(&self, len: usize) -> bool {
+        self.size + len <= self.max_size
+    }
+
+    /**
+     * 写入一条记录，写入之后文件的大小不会超过 max_size，
+     * 剩余空间不足时文件不再可写，返回错误
+     */
+    pub async fn write_record(&mut self, record: WalRecord) -> Result<Offset> {
         if !self.write_enable {
             return Err(anyhow::Error::msg("wal file is not writeable"));
         }
+        if record.seq != self.next_seq {
+            let msg = format!(
+                "wal record seq {} does not match next seq {}",
+                record.seq, self.next_seq
+            );
+            return Err(anyhow::Error::msg(msg));
+        }
         let mut file = self.wal.lock().await;
-        // 当前文件的下标
-        if self.size > self.max_size {
+        if !self.fits(record.encode_len()) {
             self.write_enable = false;
             return Err(anyhow::Error::msg("Wal file is full"));
         }
-        let mut index = Offset::from((self.size + 4) as usize);
-        let v_len = bytes.len() as u32;
         let mut new_bytes = BytesMut::new();
-        new_bytes.put_u32(v_len); // 写入数据长度
-        new_bytes.put(bytes); // 写入数据
-        file.write_all(&new_bytes).await.expect("Failed to write");
-        index.update((v_len) as usize);
-        let add_size = (v_len + 4) as usize;
+        let add_size = record.encode(&mut new_bytes)?;
+        // 文件可能是预分配的，并且读取时会移动文件的位置，写入之前需要定位到有效数据的末尾
+        file.seek(SeekFrom::Start(self.size as u64)).await?;
+        file.write_all(&new_bytes).await?;
+        // tokio的File写入是在后台线程中完成的，flush保证数据已经交给操作系统
+        file.flush().await?;
+        let index = Offset {
+            offset: self.size + RECORD_HEADER_LEN,
+            len: record.payload.len(),
+            lsn: record.seq,
+        };
         self.size += add_size;
+        self.next_seq += 1;
         Ok(index)
     }
 }
 
+impl ActiveWal {
+    /**
+     * 将已经写入的数据fsync到磁盘
+     */
+    pub async fn sync(&self) -> Result<()> {
+        let file = self.wal.lock().await;
+        file.sync_data().await?;
+        Ok(())
+    }
+}
+
 /**
  * wal 文件读取相关的方法
  */
 impl ActiveWal {
     /**
-     * 根据index读取一条数据,这个offset是WalHeader + WalMsg的偏移量
+     * 根据位置读取一条数据,这个offset是记录头(RecordHeader + WalMsg)的起始位置
      */
     pub async fn read_with_offset(&self, offset: usize) -> Result<WalMsg> {
+        if offset < FILE_HEADER_LEN {
+            let msg = format!("invalid wal record position: {}", offset);
+            return Err(anyhow::Error::msg(msg));
+        }
         let mut file = self.wal.lock().await;
-        file.seek(SeekFrom::Start(offset as u64))
-            .await
-            .expect("Failed to seek");
-        let mut lens = vec![0; 4];
-        let _resp = file.read_exact(&mut lens).await;
-        let len = BytesMut::from(lens.as_slice()).get_u32();
-        let mut buf = vec![0; len as usize];
-        file.read_exact(&mut buf).await.expect("Failed to read");
-        let buf_mut = BytesMut::from(buf.as_slice());
-        let wal_msg = WalMsg::decode(buf_mut.freeze());
-        Ok(wal_msg)
+        file.seek(SeekFrom::Start(offset as u64)).await?;
+        let mut header = vec![0; RECORD_HEADER_LEN];
+        file.read_exact(&mut header).await?;
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let mut buf = BytesMut::from(header.as_slice());
+        buf.resize(RECORD_HEADER_LEN + len, 0);
+        file.read_exact(&mut buf[RECORD_HEADER_LEN..]).await?;
+        let record = WalRecord::decode(buf.freeze())?;
+        WalMsg::decode(record.data()?)
     }
 
     /**
-     * 读取一条数据,这个offset是WalHeader + WalMsg的偏移量
+     * 读取一条数据,这个offset是WalMsg(payload)的偏移量，会同时校验记录头中的crc
      */
     pub async fn read_with_index(&self, offset: Offset) -> Result<WalMsg> {
+        if offset.offset < FILE_HEADER_LEN + RECORD_HEADER_LEN {
+            let msg = format!("invalid wal offset: {:?}", offset);
+            return Err(anyhow::Error::msg(msg));
+        }
         let mut file = self.wal.lock().await;
-        file.seek(SeekFrom::Start(offset.offset as u64))
-            .await
-            .expect("Failed to seek");
-        let mut buf = vec![0; offset.len as usize];
-        file.read_exact(&mut buf).await.expect("Failed to read");
-        let buf_mut = BytesMut::from(buf.as_slice());
-        let wal_msg = WalMsg::decode(buf_mut.freeze());
-        Ok(wal_msg)
+        file.seek(SeekFrom::Start((offset.offset - RECORD_HEADER_LEN) as u64))
+            .await?;
+        let mut buf = vec![0; RECORD_HEADER_LEN + offset.len];
+        file.read_exact(&mut buf).await?;
+        let record = WalRecord::decode(Bytes::from(buf))?;
+        WalMsg::decode(record.data()?)
     }
 }
 
@@ -281,7 +390,7 @@ where
         if !self.write_enable {
             return Err(anyhow::Error::msg("wal file is not writeable"));
         }
-        self.append_wal_msg(data.into_wal_msg()).await
+        self.append_wal_msg(data.to_wal_msg()).await
     }
 }
 
@@ -300,8 +409,12 @@ mod tests {
     use prost::Message;
     use std::sync::Arc;
 
-    use crate::wal::{
-        active_wal::ActiveWal, offset::Offset, wal_message::WalMessage, wal_msg::WalMsg,
+    use crate::{
+        utils::time_utils::now,
+        wal::{
+            active_wal::ActiveWal, offset::Offset, record::RECORD_HEADER_LEN,
+            wal_message::WalMessage, wal_msg::WalMsg,
+        },
     };
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
@@ -325,7 +438,10 @@ mod tests {
         let fds = create_datas("test");
         let index = active_wal.append(fds).await.unwrap();
         println!("index: {:?}", index);
-        let wal_msg = active_wal.read_with_offset(index.offset - 4).await.unwrap();
+        let wal_msg = active_wal
+            .read_with_offset(index.offset - RECORD_HEADER_LEN)
+            .await
+            .unwrap();
         let resp = wal_msg_to_batch(wal_msg).unwrap();
         Ok(())
     }
@@ -361,6 +477,41 @@ mod tests {
         Ok(())
     }
 
+    /**
+     * 测试加载末尾数据不完整的wal文件
+     */
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn active_wal_load_should_truncate_torn_tail() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("mobiusdb-active-wal-{}", now()));
+        tokio::fs::create_dir_all(&path).await?;
+        let path = path.to_str().unwrap().to_string();
+        let mut active_wal = ActiveWal::with_size(&path, 1024 * 1024).await?;
+        let mut indexs = Vec::new();
+        for i in 0..3 {
+            indexs.push(active_wal.append(create_data(format!("test{}", i))).await?);
+        }
+        // 模拟最后一条记录只写入了一半
+        let file_path = format!("{}/{}", path, active_wal.name());
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(&file_path)
+            .await?;
+        let last = indexs.last().unwrap();
+        file.set_len((last.offset + last.len / 2) as u64).await?;
+
+        let (wal, offsets) = ActiveWal::load(&file_path).await?;
+        assert_eq!(offsets, indexs[..2].to_vec());
+        assert_eq!(wal.next_seq(), 2);
+        let wal_msg = wal.read_with_index(offsets[1].clone()).await?;
+        assert_eq!(wal_msg_to_batch(wal_msg)?.len(), 1);
+        assert_eq!(
+            tokio::fs::metadata(&file_path).await?.len() as usize,
+            offsets[1].offset + offsets[1].len
+        );
+        let _ = tokio::fs::remove_dir_all(&path).await;
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
     async fn file_load_test() {
         let file_path = "/Users/firoly/Documents/code/rust/mobiusdb/mobiusdb-lsm/tmp/test2.wal";
@@ -0,0 +1,105 @@
+use anyhow::Result;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use super::serialization::{Decoder, Encoder};
+
+pub const CHECKPOINT_FILE_NAME: &str = "CHECKPOINT";
+
+/**
+ * checkpoint: 已经写入到sstable中的最大wal序列号(包含)
+ * 序列号不大于checkpoint的记录在恢复时会被跳过，只包含这些记录的wal文件可以被清理
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub(crate) seq: u64,
+}
+
+impl Checkpoint {
+    pub fn new(seq: u64) -> Self {
+        Self { seq }
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    fn file_path(path: &str) -> String {
+        format!("{}/{}", path.trim_end_matches('/'), CHECKPOINT_FILE_NAME)
+    }
+
+    /**
+     * 读取wal目录下的checkpoint文件，文件不存在时返回None
+     */
+    pub async fn load(path: &str) -> Result<Option<Self>> {
+        let file_path = Self::file_path(path);
+        match tokio::fs::read(&file_path).await {
+            Ok(buf) => Ok(Some(Self::decode(Bytes::from(buf))?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /**
+     * 保存checkpoint，先写临时文件再重命名
+     */
+    pub async fn save(&self, path: &str) -> Result<()> {
+        let file_path = Self::file_path(path);
+        let tmp_path = format!("{}.tmp", file_path);
+        let mut buf = BytesMut::new();
+        self.encode(&mut buf)?;
+        let mut file = tokio::fs::File::create(&tmp_path).await?;
+        tokio::io::AsyncWriteExt::write_all(&mut file, &buf).await?;
+        file.sync_data().await?;
+        tokio::fs::rename(&tmp_path, &file_path).await?;
+        Ok(())
+    }
+}
+
+impl Encoder for Checkpoint {
+    type Error = anyhow::Error;
+
+    fn encode(&self, buffer: &mut BytesMut) -> Result<usize, Self::Error> {
+        buffer.put_u64(self.seq);
+        buffer.put_u32(crc32c::crc32c(&self.seq.to_be_bytes()));
+        Ok(12)
+    }
+}
+
+impl Decoder for Checkpoint {
+    type Error = anyhow::Error;
+
+    fn decode(mut bytes: Bytes) -> Result<Self, Self::Error> {
+        if bytes.len() != 12 {
+            return Err(anyhow::anyhow!(
+                "invalid checkpoint length: {}",
+                bytes.len()
+            ));
+        }
+        let seq = bytes.get_u64();
+        if bytes.get_u32() != crc32c::crc32c(&seq.to_be_bytes()) {
+            return Err(anyhow::Error::msg("checkpoint checksum mismatch"));
+        }
+        Ok(Self { seq })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::time_utils::now;
+
+    use super::Checkpoint;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn checkpoint_save_and_load() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-checkpoint-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let path = path.to_str().unwrap();
+        assert_eq!(Checkpoint::load(path).await.unwrap(), None);
+        Checkpoint::new(42).save(path).await.unwrap();
+        assert_eq!(
+            Checkpoint::load(path).await.unwrap(),
+            Some(Checkpoint::new(42))
+        );
+        let _ = tokio::fs::remove_dir_all(path).await;
+    }
+}
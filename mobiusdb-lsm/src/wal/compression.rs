@@ -0,0 +1,88 @@
+use anyhow::Result;
+use bytes::Bytes;
+
+// 记录头flags中表示压缩算法的位
+pub const COMPRESSION_MASK: u8 = 0b0000_0011;
+const FLAG_LZ4: u8 = 1;
+const FLAG_ZSTD: u8 = 2;
+
+/**
+ * wal记录的压缩算法，压缩算法写入在记录头的flags中，
+ * 所以同一个wal文件中可以同时存在压缩和未压缩的记录
+ *  None: 不压缩
+ *  Lz4: 速度优先
+ *  Zstd(level): 压缩率优先
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Lz4,
+    Zstd(i32),
+}
+
+impl Compression {
+    pub fn flag(&self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Lz4 => FLAG_LZ4,
+            Compression::Zstd(_) => FLAG_ZSTD,
+        }
+    }
+
+    /**
+     * 压缩数据，返回压缩之后的数据以及对应的flag，
+     * 压缩之后没有变小时直接保存原始数据
+     */
+    pub fn compress(&self, payload: Bytes) -> Result<(Bytes, u8)> {
+        let compressed = match self {
+            Compression::None => return Ok((payload, 0)),
+            Compression::Lz4 => lz4_flex::compress_prepend_size(&payload),
+            Compression::Zstd(level) => zstd::bulk::compress(&payload, *level)?,
+        };
+        if compressed.len() >= payload.len() {
+            return Ok((payload, 0));
+        }
+        Ok((Bytes::from(compressed), self.flag()))
+    }
+}
+
+/**
+ * 根据记录头中的flags解压数据
+ */
+pub fn decompress(flags: u8, payload: Bytes) -> Result<Bytes> {
+    match flags & COMPRESSION_MASK {
+        0 => Ok(payload),
+        FLAG_LZ4 => {
+            let data = lz4_flex::decompress_size_prepended(&payload)
+                .map_err(|e| anyhow::Error::msg(format!("lz4 decompress failed: {}", e)))?;
+            Ok(Bytes::from(data))
+        }
+        FLAG_ZSTD => Ok(Bytes::from(zstd::stream::decode_all(payload.as_ref())?)),
+        flag => Err(anyhow::Error::msg(format!(
+            "unknown wal compression flag: {}",
+            flag
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::{decompress, Compression};
+
+    #[test]
+    fn compress_and_decompress() {
+        let payload = Bytes::from("hello mobiusdb ! ".repeat(100));
+        for compression in [Compression::None, Compression::Lz4, Compression::Zstd(3)] {
+            let (data, flag) = compression.compress(payload.clone()).unwrap();
+            assert_eq!(flag, compression.flag());
+            assert_eq!(decompress(flag, data).unwrap(), payload);
+        }
+        // 压缩之后没有变小，保存原始数据
+        let payload = Bytes::from_static(b"abc");
+        let (data, flag) = Compression::Lz4.compress(payload.clone()).unwrap();
+        assert_eq!((data, flag), (payload, 0));
+    }
+}
@@ -1,29 +1,113 @@
-use super::offset::Offset;
+use anyhow::Result;
+use bytes::{Bytes, BytesMut};
+
+use crate::utils::file_utils::{INDEX_FILE_SUFFIX, WAL_FILE_SUFFIX};
+
+use super::{
+    offset::Offset,
+    serialization::{Decoder, Encoder},
+};
 
 /**
  * 索引文件，wal的索引落盘
+ * 每个wal文件对应一个索引文件：xxx.wal -> xxx.idx，内容是 Vec<Offset> 的编码
  */
 #[derive(Debug, Clone)]
-pub struct IndexFile;
+pub struct IndexFile {
+    path: String,
+}
 
 /**
  * 索引
  */
+#[derive(Debug, Clone, PartialEq)]
 pub struct Index {
-    name: String,
-    offsets: Vec<Offset>,
+    pub(crate) name: String,
+    pub(crate) offsets: Vec<Offset>,
+}
+
+impl Index {
+    pub fn new(name: impl Into<String>, offsets: Vec<Offset>) -> Self {
+        Self {
+            name: name.into(),
+            offsets,
+        }
+    }
+
+    pub fn offsets(&self) -> &Vec<Offset> {
+        &self.offsets
+    }
 }
 
 impl IndexFile {
-    async fn init(path: &str) -> Self {
-        todo!()
+    /**
+     * path为wal文件的路径
+     */
+    pub fn init(path: &str) -> Self {
+        let path = match path.strip_suffix(WAL_FILE_SUFFIX) {
+            Some(p) => format!("{}{}", p, INDEX_FILE_SUFFIX),
+            None => format!("{}{}", path, INDEX_FILE_SUFFIX),
+        };
+        Self { path }
     }
 
-    async fn load(&self) {
-        todo!()
+    pub fn path(&self) -> &str {
+        &self.path
     }
 
-    async fn save(&self) {
-        todo!()
+    /**
+     * 读取索引文件，索引文件不存在或者已损坏时返回错误
+     */
+    pub async fn load(&self) -> Result<Index> {
+        let buf = tokio::fs::read(&self.path).await?;
+        let offsets = Vec::<Offset>::decode(Bytes::from(buf))?;
+        let name = self
+            .path
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .replace(INDEX_FILE_SUFFIX, WAL_FILE_SUFFIX);
+        Ok(Index { name, offsets })
+    }
+
+    /**
+     * 保存索引文件，先写临时文件再重命名，避免留下写了一半的索引
+     */
+    pub async fn save(&self, index: &Index) -> Result<()> {
+        let mut buf = BytesMut::new();
+        index.offsets.encode(&mut buf)?;
+        let tmp_path = format!("{}.tmp", self.path);
+        tokio::fs::write(&tmp_path, &buf).await?;
+        tokio::fs::rename(&tmp_path, &self.path).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{utils::time_utils::now, wal::offset::Offset};
+
+    use super::{Index, IndexFile};
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn index_file_save_and_load() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-index-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let wal_path = format!("{}/123.wal", path.to_str().unwrap());
+        let index_file = IndexFile::init(&wal_path);
+        assert!(index_file.path().ends_with("/123.idx"));
+        assert!(index_file.load().await.is_err());
+
+        let offsets = (0..10)
+            .map(|i| Offset {
+                offset: i * 100,
+                len: 80,
+                lsn: i as u64,
+            })
+            .collect();
+        let index = Index::new("123.wal", offsets);
+        index_file.save(&index).await.unwrap();
+        assert_eq!(index_file.load().await.unwrap(), index);
+        let _ = tokio::fs::remove_dir_all(&path).await;
     }
 }
@@ -0,0 +1,431 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::Result;
+use arrow::{array::RecordBatch, datatypes::SchemaRef, ipc::writer::FileWriter};
+use arrow_flight::utils::flight_data_to_batches;
+use datafusion::parquet::arrow::ArrowWriter;
+
+use super::{
+    check_table_name,
+    offset::Lsn,
+    reader::WalReader,
+    record::{RecoveryMode, WalRecord},
+    wal_entry::{EntryKind, WalEntry},
+    wal_msg::{IntoWalMsg, WalMsg},
+    TABLE_WAL_DIR,
+};
+use crate::{
+    memtable::array_data_utils::merge_batches, utils::file_utils::get_wal_files_name, TIMESTAMP,
+};
+
+// 排查和修复wal文件的工具函数，供 wal_tool 使用
+//  segments: 列出目录下(包括 PerTable 模式下每个表的目录)所有的wal文件
+//  dump_segment: 解析wal文件中的每条记录
+//  verify_segment: 严格模式校验wal文件
+//  truncate_segment: 截断wal文件末尾不完整的记录
+//  export_segment: 将wal中写入的数据按表导出为 Arrow IPC 或 Parquet 文件
+
+/**
+ * 一个wal文件的概况
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SegmentInfo {
+    pub path: String,
+    pub file_len: usize,
+    // 文件头中记录的第一条记录的序列号
+    pub start_seq: Option<Lsn>,
+    pub records: usize,
+    pub first_lsn: Option<Lsn>,
+    pub last_lsn: Option<Lsn>,
+    // 文件中完整记录的长度
+    pub valid_len: usize,
+    // 文件末尾存在不完整的记录
+    pub torn: bool,
+    // 文件中间的损坏，或者严格模式下末尾的损坏
+    pub error: Option<String>,
+}
+
+impl SegmentInfo {
+    pub fn is_healthy(&self) -> bool {
+        !self.torn && self.error.is_none()
+    }
+}
+
+/**
+ * wal文件中一条记录的内容
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordInfo {
+    pub lsn: Lsn,
+    // payload 在文件中的位置和长度(压缩之后)
+    pub offset: usize,
+    pub len: usize,
+    // 解压之后的长度
+    pub data_len: usize,
+    pub flags: u8,
+    pub kind: EntryKind,
+    pub table: Option<String>,
+    pub schema: Option<SchemaRef>,
+    pub rows: usize,
+}
+
+/**
+ * 导出文件的格式
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    #[default]
+    Ipc,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &str {
+        match self {
+            ExportFormat::Ipc => "arrow",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+impl TryFrom<&str> for ExportFormat {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "ipc" | "arrow" => Ok(ExportFormat::Ipc),
+            "parquet" => Ok(ExportFormat::Parquet),
+            _ => Err(anyhow::Error::msg(format!(
+                "unknown export format: {}",
+                value
+            ))),
+        }
+    }
+}
+
+/**
+ * 目录下所有wal文件的路径，按照wal所属的表和创建顺序排列
+ */
+pub async fn segment_paths(dir: &str) -> Result<Vec<String>> {
+    let mut paths: Vec<String> = get_wal_files_name(dir)
+        .await?
+        .into_iter()
+        .map(|name| format!("{}/{}", dir, name))
+        .collect();
+    let table_dir = format!("{}/{}", dir, TABLE_WAL_DIR);
+    if tokio::fs::metadata(&table_dir).await.is_err() {
+        return Ok(paths);
+    }
+    let mut tables = Vec::new();
+    let mut entries = tokio::fs::read_dir(&table_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+            tables.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    tables.sort();
+    for table in tables {
+        let stream_dir = format!("{}/{}", table_dir, table);
+        for name in get_wal_files_name(&stream_dir).await? {
+            paths.push(format!("{}/{}", stream_dir, name));
+        }
+    }
+    Ok(paths)
+}
+
+/**
+ * 列出目录下所有wal文件的概况
+ */
+pub async fn segments(dir: &str) -> Result<Vec<SegmentInfo>> {
+    let mut infos = Vec::new();
+    for path in segment_paths(dir).await? {
+        infos.push(scan_segment(&path, RecoveryMode::TruncateTail).await?);
+    }
+    Ok(infos)
+}
+
+/**
+ * 严格模式校验wal文件，任何损坏都记录在 SegmentInfo::error 中
+ */
+pub async fn verify_segment(path: &str) -> Result<SegmentInfo> {
+    scan_segment(path, RecoveryMode::Strict).await
+}
+
+async fn scan_segment(path: &str, mode: RecoveryMode) -> Result<SegmentInfo> {
+    let file_len = tokio::fs::metadata(path).await?.len() as usize;
+    let mut info = SegmentInfo {
+        path: path.to_string(),
+        file_len,
+        ..Default::default()
+    };
+    let mut reader = match WalReader::open(path, mode).await {
+        Ok(reader) => reader,
+        Err(e) => {
+            info.error = Some(e.to_string());
+            return Ok(info);
+        }
+    };
+    info.start_seq = reader.header().map(|header| header.start_seq);
+    loop {
+        match reader.next().await {
+            Ok(Some((_, record))) => {
+                info.records += 1;
+                info.first_lsn.get_or_insert(record.seq());
+                info.last_lsn = Some(record.seq());
+            }
+            Ok(None) => break,
+            Err(e) => {
+                info.error = Some(e.to_string());
+                break;
+            }
+        }
+    }
+    info.valid_len = reader.valid_len();
+    info.torn = reader.is_torn();
+    Ok(info)
+}
+
+/**
+ * 解析wal文件中的每条记录，末尾不完整的记录会被忽略
+ */
+pub async fn dump_segment(path: &str) -> Result<Vec<RecordInfo>> {
+    let mut reader = WalReader::open(path, RecoveryMode::TruncateTail).await?;
+    let mut infos = Vec::new();
+    while let Some((offset, record)) = reader.next().await? {
+        infos.push(record_info(offset.offset, &record)?);
+    }
+    Ok(infos)
+}
+
+fn record_info(offset: usize, record: &WalRecord) -> Result<RecordInfo> {
+    let data = record.data()?;
+    let wal_msg = WalMsg::decode(data.clone())?;
+    let entry = WalEntry::from_wal_msg(&wal_msg)?;
+    let mut info = RecordInfo {
+        lsn: record.seq(),
+        offset,
+        len: record.payload().len(),
+        data_len: data.len(),
+        flags: record.flags(),
+        kind: entry.kind(),
+        table: entry.table_name(),
+        schema: None,
+        rows: 0,
+    };
+    match entry {
+        WalEntry::InsertBatch(fds) => {
+            let batches = flight_data_to_batches(&fds)?;
+            info.schema = batches.first().map(|batch| batch.schema());
+            info.rows = batches.iter().map(|batch| batch.num_rows()).sum();
+        }
+        WalEntry::SchemaChange { schema, .. } => info.schema = Some(schema),
+        _ => {}
+    }
+    Ok(info)
+}
+
+/**
+ * 将wal文件截断到最后一条完整的记录，返回被截断的字节数
+ * 文件中间的损坏无法通过截断修复，返回错误
+ */
+pub async fn truncate_segment(path: &str) -> Result<usize> {
+    let info = scan_segment(path, RecoveryMode::TruncateTail).await?;
+    if let Some(e) = info.error {
+        return Err(anyhow::Error::msg(e));
+    }
+    if !info.torn {
+        return Ok(0);
+    }
+    let file = tokio::fs::OpenOptions::new().write(true).open(path).await?;
+    file.set_len(info.valid_len as u64).await?;
+    file.sync_all().await?;
+    Ok(info.file_len - info.valid_len)
+}
+
+/**
+ * 将wal文件中写入的数据按表导出到out_dir下，每个表一个文件，返回每个表导出的行数
+ * 同一个表不同的schema会被合并
+ */
+pub async fn export_segment(
+    path: &str,
+    out_dir: &str,
+    format: ExportFormat,
+) -> Result<BTreeMap<String, usize>> {
+    let mut reader = WalReader::open(path, RecoveryMode::TruncateTail).await?;
+    let mut tables: BTreeMap<String, Vec<RecordBatch>> = BTreeMap::new();
+    while let Some((_, record)) = reader.next().await? {
+        let wal_msg = WalMsg::decode(record.data()?)?;
+        let entry = WalEntry::from_wal_msg(&wal_msg)?;
+        let table = entry
+            .table_name()
+            .unwrap_or_else(|| format!("lsn-{}", record.seq()));
+        if let WalEntry::InsertBatch(fds) = entry {
+            // 表名来自wal中的数据，作为文件名时不能写到out_dir之外
+            check_table_name(&table)?;
+            let batches = flight_data_to_batches(&fds)?;
+            tables.entry(table).or_default().extend(batches);
+        }
+    }
+    tokio::fs::create_dir_all(out_dir).await?;
+    let mut rows = BTreeMap::new();
+    for (table, batches) in tables {
+        // 导出时没有表的配置，按默认的时间字段合并
+        let batch = merge_batches(&batches, TIMESTAMP)?;
+        let file_path = Path::new(out_dir).join(format!("{}.{}", table, format.extension()));
+        write_batch(&file_path, &batch, format)?;
+        rows.insert(table, batch.num_rows());
+    }
+    Ok(rows)
+}
+
+fn write_batch(path: &Path, batch: &RecordBatch, format: ExportFormat) -> Result<()> {
+    let file = std::fs::File::create(path)?;
+    match format {
+        ExportFormat::Ipc => {
+            let mut writer = FileWriter::try_new(file, &batch.schema())?;
+            writer.write(batch)?;
+            writer.finish()?;
+        }
+        ExportFormat::Parquet => {
+            let mut writer = ArrowWriter::try_new(file, batch.schema(), None)?;
+            writer.write(batch)?;
+            writer.close()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use arrow::{
+        array::{Int32Array, RecordBatch},
+        datatypes::{DataType, Field, Schema},
+        ipc::reader::FileReader,
+    };
+    use arrow_flight::utils::batches_to_flight_data;
+    use datafusion::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use crate::{
+        utils::time_utils::now,
+        wal::{wal_entry::EntryKind, wal_entry::WalEntry, Append, WalService},
+        TABLE_NAME,
+    };
+
+    use super::{
+        dump_segment, export_segment, segment_paths, segments, truncate_segment, verify_segment,
+        ExportFormat,
+    };
+
+    fn create_data(table: &str, ages: Vec<i32>) -> WalEntry {
+        let schema = Arc::new(
+            Schema::new(vec![Field::new("age", DataType::Int32, true)])
+                .with_metadata(HashMap::from([(TABLE_NAME.to_string(), table.to_string())])),
+        );
+        let batch =
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(ages))]).unwrap();
+        WalEntry::InsertBatch(batches_to_flight_data(&schema, vec![batch]).unwrap())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn inspect_should_dump_verify_truncate_and_export() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-inspect-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let mut service = WalService::init(&path, 1024 * 1024).await.unwrap();
+        service
+            .append(create_data("cpu", vec![1, 2]))
+            .await
+            .unwrap();
+        service.append(create_data("mem", vec![3])).await.unwrap();
+        let drop = WalEntry::DropTable {
+            table: "mem".to_string(),
+        };
+        service.append(drop).await.unwrap();
+        service.append(create_data("cpu", vec![4])).await.unwrap();
+        service.sync().await.unwrap();
+
+        let wal_path = segment_paths(&path).await.unwrap().remove(0);
+        let infos = segments(&path).await.unwrap();
+        assert_eq!(infos.len(), 1);
+        assert_eq!((infos[0].first_lsn, infos[0].last_lsn), (Some(0), Some(3)));
+        assert!(infos[0].is_healthy());
+
+        let records = dump_segment(&wal_path).await.unwrap();
+        let kinds: Vec<EntryKind> = records.iter().map(|record| record.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                EntryKind::InsertBatch,
+                EntryKind::InsertBatch,
+                EntryKind::DropTable,
+                EntryKind::InsertBatch
+            ]
+        );
+        assert_eq!(records[0].table.as_deref(), Some("cpu"));
+        assert_eq!(records[0].rows, 2);
+        assert!(records[0].schema.is_some());
+        assert_eq!(records[2].table.as_deref(), Some("mem"));
+
+        let out_dir = format!("{}/export", path);
+        let rows = export_segment(&wal_path, &out_dir, ExportFormat::Ipc)
+            .await
+            .unwrap();
+        assert_eq!(rows.get("cpu"), Some(&3));
+        assert_eq!(rows.get("mem"), Some(&1));
+        let file = std::fs::File::open(format!("{}/cpu.arrow", out_dir)).unwrap();
+        let reader = FileReader::try_new(file, None).unwrap();
+        let num: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+        assert_eq!(num, 3);
+        export_segment(&wal_path, &out_dir, ExportFormat::Parquet)
+            .await
+            .unwrap();
+        let file = std::fs::File::open(format!("{}/mem.parquet", out_dir)).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .build()
+            .unwrap();
+        let num: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+        assert_eq!(num, 1);
+
+        // 模拟末尾写了一半的记录
+        let len = tokio::fs::metadata(&wal_path).await.unwrap().len();
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(&wal_path)
+            .await
+            .unwrap();
+        file.set_len(len - 5).await.unwrap();
+        let info = verify_segment(&wal_path).await.unwrap();
+        assert!(info.error.is_some());
+        let truncated = truncate_segment(&wal_path).await.unwrap();
+        assert_eq!(truncated as u64, len - 5 - info.valid_len as u64);
+        let info = verify_segment(&wal_path).await.unwrap();
+        assert!(info.is_healthy());
+        assert_eq!(info.last_lsn, Some(2));
+        assert_eq!(truncate_segment(&wal_path).await.unwrap(), 0);
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn export_should_reject_table_name_outside_out_dir() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-export-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let mut service = WalService::init(&path, 1024 * 1024).await.unwrap();
+        service
+            .append(create_data("../escaped", vec![1]))
+            .await
+            .unwrap();
+        service.sync().await.unwrap();
+        let wal_path = segment_paths(&path).await.unwrap().remove(0);
+        let out_dir = format!("{}/export", path);
+        let resp = export_segment(&wal_path, &out_dir, ExportFormat::Ipc).await;
+        assert!(resp.is_err());
+        assert!(tokio::fs::metadata(format!("{}/escaped.arrow", path))
+            .await
+            .is_err());
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+}
@@ -1,19 +1,48 @@
 pub mod active_wal;
+pub mod checkpoint;
+pub mod compression;
 pub mod index_file;
+pub mod inspect;
 pub(crate) mod offset;
+pub mod reader;
+pub mod record;
+pub mod retention;
+pub mod sealed_wal;
+pub mod segment;
 pub mod serialization;
+pub mod sync_policy;
+pub mod wal_entry;
 pub mod wal_message;
+pub mod wal_mode;
 pub mod wal_msg;
 
 use std::collections::HashMap;
 
 use anyhow::Result;
+use bytes::BytesMut;
 
 use active_wal::ActiveWal;
+use checkpoint::Checkpoint;
+use compression::Compression;
+use index_file::{Index, IndexFile};
+pub use offset::Lsn;
 use offset::Offset;
-use wal_msg::IntoWalMsg;
+use reader::{SegmentReader, WalReader};
+use record::{RecoveryMode, FILE_HEADER_LEN};
+use retention::{list_segments, WalRetention};
+use sealed_wal::SealedWal;
+use segment::{next_segment_id, WalSegment};
+use sync_policy::SyncPolicy;
+use wal_entry::EntryKind;
+use wal_mode::WalMode;
+use wal_msg::{IntoWalMsg, WalMsg};
 
-use crate::utils::file_utils::{get_files_name, has_file_in_path};
+use crate::{
+    error::{LsmError, LsmResult},
+    utils::file_utils::{
+        get_recycle_files_name, get_wal_files_name, RECYCLE_FILE_SUFFIX, WAL_FILE_SUFFIX,
+    },
+};
 
 #[allow(async_fn_in_trait)]
 pub trait Wal {
@@ -28,72 +57,314 @@ pub trait Append<T> {
     async fn append(&mut self, data: T) -> Self::Result;
 }
 
+// PerTable 模式下，每个表的wal所在的目录: {path}/tables/{prefix}
+pub const TABLE_WAL_DIR: &str = "tables";
+
 #[derive(Debug)]
 pub struct WalService {
     path: String,
     wal: ActiveWal,
     wal_max_size: usize,
+    // wal的落盘策略
+    sync_policy: SyncPolicy,
+    // 是否存在还没有fsync的数据
+    dirty: bool,
+    // 已经写入到sstable中的最大序列号
+    checkpoint: Option<u64>,
+    // 已经被checkpoint覆盖的wal文件的保留策略
+    retention: WalRetention,
+    // wal记录的压缩算法
+    compression: Compression,
+    // wal文件的分配策略
+    segment: WalSegment,
+    // 下一个wal文件的 segment id
+    next_segment_id: u64,
+    // 等待复用的wal文件
+    recycle_pool: Vec<String>,
     // 记录ActiveWal文件中的offset
     pub(crate) indexs: Vec<Offset>,
     // 记录ActiveWal文件中的offset, key为wal文件名, value为offset
     pub(crate) indexs_map: HashMap<String, Vec<Offset>>,
+    // 写满的wal文件的mmap，key为wal文件名
+    sealed: HashMap<String, SealedWal>,
+    // wal的组织方式
+    mode: WalMode,
+    // PerTable 模式下每个表的wal，key为表名前缀
+    streams: HashMap<String, WalService>,
 }
 impl WalService {
     // 初始化walService
-    // todo 需要重构，拆分代码
     pub async fn init(path: impl Into<String>, wal_size: usize) -> Result<Self> {
         let path: String = path.into();
-        match has_file_in_path(&path).await {
-            Ok(b) => match b {
-                true => {
-                    println!("wal文件存在,读取wal文件");
-                    // 存在wal文件
-                    if let Ok(mut files_name) = get_files_name(path.as_str()).await {
-                        files_name.sort();
-                        let file_name = files_name.last().unwrap();
-                        let file_path = path.clone() + "/" + file_name;
-                        let _a: Vec<&str> = file_name.split(".").collect();
-                        // print!("file_path = {}",file_path);
-                        // 1、加载wal文件
-                        let (wal, offsets) = ActiveWal::load(file_path.as_str()).await.unwrap();
-                        // println!("offsets = {:?}",offsets);
-
-                        // 3、创建
-                        Ok(Self {
-                            path: path.clone(),
-                            wal,
-                            wal_max_size: wal_size,
-                            indexs: offsets,
-                            indexs_map: HashMap::new(),
-                        })
-                    } else {
-                        Err(anyhow::Error::msg("wal文件获取失败"))
-                    }
-                }
-                false => {
-                    println!("wal文件不存在,新建wal文件");
-                    // 不存在wal文件,是第一次启动
-                    let wal = ActiveWal::with_size(&path, wal_size).await?;
-                    Ok(Self {
-                        path: path.clone(),
-                        wal,
-                        wal_max_size: wal_size,
-                        indexs: Vec::new(),
-                        indexs_map: HashMap::new(),
-                    })
-                }
-            },
-            Err(e) => Err(e.into()),
+        let files_name = get_wal_files_name(&path).await?;
+        match files_name.is_empty() {
+            true => Self::first_start(&path, wal_size).await,
+            false => Self::init_with_file(&path, wal_size).await,
+        }
+    }
+
+    pub fn with_sync_policy(mut self, sync_policy: SyncPolicy) -> Self {
+        self.sync_policy = sync_policy;
+        self.streams
+            .values_mut()
+            .for_each(|stream| stream.sync_policy = sync_policy);
+        self
+    }
+
+    pub fn sync_policy(&self) -> SyncPolicy {
+        self.sync_policy
+    }
+
+    pub fn with_retention(mut self, retention: WalRetention) -> Self {
+        self.retention = retention;
+        for (prefix, stream) in self.streams.iter_mut() {
+            stream.retention = table_retention(&self.retention, prefix);
+        }
+        self
+    }
+
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self.wal.set_compression(compression);
+        for stream in self.streams.values_mut() {
+            stream.compression = compression;
+            stream.wal.set_compression(compression);
+        }
+        self
+    }
+
+    /**
+     * 设置wal文件的分配策略，开启预分配时当前的wal文件也会被预分配
+     */
+    pub async fn with_segment(mut self, segment: WalSegment) -> Result<Self> {
+        self.apply_segment(segment).await?;
+        for stream in self.streams.values_mut() {
+            stream.apply_segment(segment).await?;
+        }
+        Ok(self)
+    }
+
+    async fn apply_segment(&mut self, segment: WalSegment) -> Result<()> {
+        self.segment = segment;
+        if segment.preallocate && self.wal.is_writeable() {
+            self.wal.preallocate().await?;
+        }
+        // 超出数量的复用文件直接删除
+        while self.recycle_pool.len() > segment.recycle {
+            if let Some(recycle_path) = self.recycle_pool.pop() {
+                tokio::fs::remove_file(&recycle_path).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * 设置wal的组织方式，PerTable 模式下会加载每个表已经存在的wal
+     */
+    pub async fn with_mode(mut self, mode: WalMode) -> Result<Self> {
+        self.mode = mode;
+        self.streams.clear();
+        if !self.mode.is_per_table() {
+            return Ok(self);
         }
+        let tables_path = self.file_path(TABLE_WAL_DIR);
+        if !tokio::fs::try_exists(&tables_path).await? {
+            return Ok(self);
+        }
+        let mut dir = tokio::fs::read_dir(&tables_path).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                let prefix = entry.file_name().to_string_lossy().to_string();
+                let stream = self.create_stream(&prefix).await?;
+                self.streams.insert(prefix, stream);
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn mode(&self) -> &WalMode {
+        &self.mode
+    }
+
+    /**
+     * 当前的wal以及 PerTable 模式下每个表的wal
+     */
+    pub fn all_streams(&self) -> Vec<&WalService> {
+        std::iter::once(self).chain(self.streams.values()).collect()
+    }
+
+    /**
+     * PerTable 模式下指定表的wal
+     */
+    pub fn table_stream(&self, prefix: &str) -> Option<&WalService> {
+        self.streams.get(prefix)
+    }
+
+    /**
+     * 创建(或加载)指定表的wal，配置和当前的wal保持一致
+     */
+    async fn create_stream(&self, prefix: &str) -> Result<WalService> {
+        check_table_name(prefix)?;
+        let path = self.file_path(&format!("{}/{}", TABLE_WAL_DIR, prefix));
+        tokio::fs::create_dir_all(&path).await?;
+        let wal_size = self.mode.wal_size(prefix).unwrap_or(self.wal_max_size);
+        let stream = WalService::init(path, wal_size)
+            .await?
+            .with_sync_policy(self.sync_policy)
+            .with_retention(table_retention(&self.retention, prefix))
+            .with_compression(self.compression)
+            .with_segment(self.segment)
+            .await?;
+        Ok(stream)
+    }
+
+    pub fn checkpoint_seq(&self) -> Option<u64> {
+        self.checkpoint
     }
+
     async fn update_wal(&mut self) -> Result<bool> {
+        // 切换文件之前，旧文件中的数据必须先落盘
+        self.sync_wal().await?;
         let old_wal_name = self.wal.name();
-        let old_indexs = self.indexs.clone();
-        let new_wal = ActiveWal::with_size(&self.path, self.wal_max_size).await?;
+        let old_indexs = std::mem::take(&mut self.indexs);
+        // 旧文件不再写入，将其索引落盘，重启之后不需要重新扫描
+        let index_file = IndexFile::init(&self.file_path(&old_wal_name));
+        let index = Index::new(old_wal_name.clone(), old_indexs.clone());
+        if let Err(e) = index_file.save(&index).await {
+            println!("wal索引文件保存失败：{:?}", e);
+        }
+        let start_seq = self.wal.next_seq();
+        // 优先复用已经被checkpoint覆盖的wal文件
+        let mut new_wal = match self.recycle_pool.pop() {
+            Some(recycle_path) => {
+                println!("复用wal文件:【{}】", recycle_path);
+                ActiveWal::recycle(
+                    &self.path,
+                    &recycle_path,
+                    self.next_segment_id,
+                    self.wal_max_size,
+                    start_seq,
+                )
+                .await?
+            }
+            None => {
+                ActiveWal::with_segment_id(
+                    &self.path,
+                    self.next_segment_id,
+                    self.wal_max_size,
+                    start_seq,
+                )
+                .await?
+            }
+        };
+        self.next_segment_id += 1;
+        if self.segment.preallocate {
+            new_wal.preallocate().await?;
+        }
+        new_wal.set_compression(self.compression);
+        if let Some(sealed) = open_sealed(&self.file_path(&old_wal_name)) {
+            self.sealed.insert(old_wal_name.clone(), sealed);
+        }
         self.indexs_map.insert(old_wal_name, old_indexs);
         self.wal = new_wal;
         Ok(true)
     }
+
+    fn file_path(&self, file_name: &str) -> String {
+        format!("{}/{}", self.path.trim_end_matches('/'), file_name)
+    }
+
+    /**
+     * 获取指定wal文件的索引
+     */
+    pub fn offsets(&self, file_name: &str) -> Vec<Offset> {
+        for stream in self.all_streams() {
+            if file_name == stream.wal.name() {
+                return stream.indexs.clone();
+            }
+            if let Some(offsets) = stream.indexs_map.get(file_name) {
+                return offsets.clone();
+            }
+        }
+        Vec::new()
+    }
+}
+
+/**
+ * 表名会作为目录名或文件名(PerTable 模式下每个表的wal目录、导出的文件)，不能为空，不能包含路径分隔符，不能是 . 或 ..
+ */
+pub fn check_table_name(prefix: &str) -> Result<()> {
+    if prefix.is_empty() || prefix.contains(['/', '\\']) || prefix == "." || prefix == ".." {
+        let msg = format!("invalid table name for wal: 【{}】", prefix);
+        return Err(anyhow::Error::msg(msg));
+    }
+    Ok(())
+}
+
+/**
+ * 表的wal使用独立的归档目录，避免不同表的wal文件重名
+ */
+fn table_retention(retention: &WalRetention, prefix: &str) -> WalRetention {
+    let mut retention = retention.clone();
+    retention.archive_path = retention.archive_path.map(|archive_path| {
+        format!(
+            "{}/{}/{}",
+            archive_path.trim_end_matches('/'),
+            TABLE_WAL_DIR,
+            prefix
+        )
+    });
+    retention
+}
+
+/**
+ * mmap写满的wal文件，失败时读取这个文件会退化为逐条读取
+ */
+fn open_sealed(file_path: &str) -> Option<SealedWal> {
+    match SealedWal::open(file_path) {
+        Ok(sealed) => Some(sealed),
+        Err(e) => {
+            println!("wal文件:【{}】mmap失败：{:?}", file_path, e);
+            None
+        }
+    }
+}
+
+/**
+ * 目录中等待复用的wal文件
+ */
+async fn recycle_pool(path: &str) -> Result<Vec<String>> {
+    Ok(get_recycle_files_name(path)
+        .await?
+        .into_iter()
+        .map(|name| format!("{}/{}", path.trim_end_matches('/'), name))
+        .collect())
+}
+
+/**
+ * 读取wal文件的索引，索引文件不存在或已损坏时重新扫描wal文件并重建索引
+ */
+async fn load_index(file_path: &str) -> Result<Vec<Offset>> {
+    let index_file = IndexFile::init(file_path);
+    let file_len = tokio::fs::metadata(file_path).await?.len() as usize;
+    if let Ok(index) = index_file.load().await {
+        let end = match index.offsets().last() {
+            Some(offset) => offset.offset + offset.len,
+            None => 0,
+        };
+        if end <= file_len {
+            return Ok(index.offsets);
+        }
+    }
+    println!("wal文件:【{}】的索引不可用，重新扫描wal文件", file_path);
+    let mut reader = WalReader::open(file_path, RecoveryMode::TruncateTail).await?;
+    let mut offsets = Vec::new();
+    while let Some((offset, _)) = reader.next().await? {
+        offsets.push(offset);
+    }
+    let name = file_path.rsplit('/').next().unwrap_or_default();
+    index_file.save(&Index::new(name, offsets.clone())).await?;
+    Ok(offsets)
 }
 
 impl WalService {
@@ -106,8 +377,19 @@ impl WalService {
             path: path.as_ref().to_string(),
             wal,
             wal_max_size: wal_size,
+            sync_policy: SyncPolicy::default(),
+            dirty: false,
+            checkpoint: Checkpoint::load(path.as_ref()).await?.map(|c| c.seq()),
+            retention: WalRetention::default(),
+            compression: Compression::default(),
+            segment: WalSegment::default(),
+            next_segment_id: next_segment_id(path.as_ref()).await?,
+            recycle_pool: recycle_pool(path.as_ref()).await?,
             indexs: Vec::new(),
             indexs_map: HashMap::new(),
+            sealed: HashMap::new(),
+            mode: WalMode::default(),
+            streams: HashMap::new(),
         })
     }
 
@@ -115,23 +397,41 @@ impl WalService {
     pub async fn init_with_file(path: impl AsRef<str>, wal_size: usize) -> Result<Self> {
         println!("wal文件存在,读取wal文件");
         // 存在wal文件
-        if let Ok(mut files_name) = get_files_name(path.as_ref()).await {
-            files_name.sort();
-            let file_name = files_name.last().unwrap();
-            let file_path = path.as_ref().to_string() + "/" + file_name;
-            let _a: Vec<&str> = file_name.split(".").collect();
-            // print!("file_path = {}",file_path);
+        let mut files_name = get_wal_files_name(path.as_ref()).await?;
+        if let Some(file_name) = files_name.pop() {
+            let file_path = path.as_ref().to_string() + "/" + &file_name;
             // 1、加载wal文件
-            let (wal, offsets) = ActiveWal::load(file_path.as_str()).await.unwrap();
-            // println!("offsets = {:?}",offsets);
-
+            let (mut wal, offsets) = ActiveWal::load(file_path.as_str()).await?;
+            wal.set_max_size(wal_size);
+            // 2、加载已经写满的wal文件的索引
+            let mut indexs_map = HashMap::new();
+            let mut sealed = HashMap::new();
+            for file_name in files_name {
+                let file_path = path.as_ref().to_string() + "/" + &file_name;
+                let offsets = load_index(&file_path).await?;
+                if let Some(wal) = open_sealed(&file_path) {
+                    sealed.insert(file_name.clone(), wal);
+                }
+                indexs_map.insert(file_name, offsets);
+            }
             // 3、创建
             Ok(Self {
                 path: path.as_ref().to_string(),
                 wal,
                 wal_max_size: wal_size,
+                sync_policy: SyncPolicy::default(),
+                dirty: false,
+                checkpoint: Checkpoint::load(path.as_ref()).await?.map(|c| c.seq()),
+                retention: WalRetention::default(),
+                compression: Compression::default(),
+                segment: WalSegment::default(),
+                next_segment_id: next_segment_id(path.as_ref()).await?,
+                recycle_pool: recycle_pool(path.as_ref()).await?,
                 indexs: offsets,
-                indexs_map: HashMap::new(),
+                indexs_map,
+                sealed,
+                mode: WalMode::default(),
+                streams: HashMap::new(),
             })
         } else {
             Err(anyhow::Error::msg("wal文件获取失败"))
@@ -139,42 +439,757 @@ impl WalService {
     }
 }
 
+/**
+ * wal 落盘相关的方法
+ */
+impl WalService {
+    /**
+     * 将active wal(包括每个表的wal)中的数据fsync到磁盘
+     */
+    pub async fn sync(&mut self) -> Result<()> {
+        self.sync_wal().await?;
+        for stream in self.streams.values_mut() {
+            stream.sync_wal().await?;
+        }
+        Ok(())
+    }
+
+    async fn sync_wal(&mut self) -> Result<()> {
+        if self.dirty {
+            self.wal.sync().await?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    /**
+     * 提交之前的写入：根据落盘策略决定是否需要fsync
+     * Always/GroupCommit 会在这里fsync，Interval 由定时任务调用 sync
+     */
+    pub async fn commit(&mut self) -> Result<()> {
+        match self.sync_policy.sync_on_commit() {
+            true => self.sync().await,
+            false => Ok(()),
+        }
+    }
+}
+
+/**
+ * wal 文件清理相关的方法
+ */
+impl WalService {
+    /**
+     * 记录已经写入到sstable中的最大序列号，并清理不再需要的wal文件(包括每个表的wal)
+     * 返回被清理(删除或归档)的wal文件
+     */
+    pub async fn checkpoint(&mut self, seq: u64) -> Result<Vec<String>> {
+        let mut resp = self.checkpoint_wal(seq).await?;
+        for stream in self.streams.values_mut() {
+            resp.extend(stream.checkpoint_wal(seq).await?);
+        }
+        Ok(resp)
+    }
+
+    /**
+     * PerTable 模式下，指定表的数据写入sstable之后只清理这个表的wal
+     */
+    pub async fn checkpoint_table(&mut self, prefix: &str, seq: u64) -> Result<Vec<String>> {
+        match self.streams.get_mut(prefix) {
+            Some(stream) => stream.checkpoint_wal(seq).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn checkpoint_wal(&mut self, seq: u64) -> Result<Vec<String>> {
+        if self.checkpoint.is_none_or(|checkpoint| seq > checkpoint) {
+            // 新的wal文件可能还没有落盘，checkpoint不能超过已经落盘的数据
+            self.sync_wal().await?;
+            Checkpoint::new(seq).save(&self.path).await?;
+            self.checkpoint = Some(seq);
+        }
+        self.purge_wal().await
+    }
+
+    /**
+     * 清理已经被checkpoint覆盖、并且超出保留范围的wal文件(包括每个表的wal)
+     */
+    pub async fn purge(&mut self) -> Result<Vec<String>> {
+        let mut resp = self.purge_wal().await?;
+        for stream in self.streams.values_mut() {
+            resp.extend(stream.purge_wal().await?);
+        }
+        Ok(resp)
+    }
+
+    async fn purge_wal(&mut self) -> Result<Vec<String>> {
+        let checkpoint = match self.checkpoint {
+            Some(checkpoint) => checkpoint,
+            None => return Ok(Vec::new()),
+        };
+        let active = self.wal.name();
+        let segments = list_segments(&self.path).await?;
+        // 一个wal文件中最后一条记录的序列号 = 下一个wal文件的 start_seq - 1
+        let obsolete: Vec<_> = segments
+            .windows(2)
+            .take_while(|w| w[0].name != active && w[1].start_seq <= checkpoint + 1)
+            .map(|w| w[0].clone())
+            .collect();
+        let expired = self
+            .retention
+            .expired(&obsolete, std::time::SystemTime::now());
+        let mut resp = Vec::new();
+        for segment in expired {
+            let index_path = IndexFile::init(&segment.path).path().to_string();
+            // 先从sealed中移除，之后不会再有新的读取；其他任务仍然持有mmap时文件不能复用(原地覆盖)
+            let in_use = self
+                .sealed
+                .remove(&segment.name)
+                .is_some_and(|sealed| sealed.is_shared());
+            match &self.retention.archive_path {
+                Some(archive_path) => {
+                    tokio::fs::create_dir_all(archive_path).await?;
+                    let archive =
+                        format!("{}/{}", archive_path.trim_end_matches('/'), segment.name);
+                    tokio::fs::rename(&segment.path, &archive).await?;
+                    println!("wal文件:【{}】已归档到:【{}】", segment.name, archive);
+                }
+                None if !in_use && self.recycle_pool.len() < self.segment.recycle => {
+                    let stem = segment.path.trim_end_matches(WAL_FILE_SUFFIX);
+                    let recycle_path = format!("{}{}", stem, RECYCLE_FILE_SUFFIX);
+                    tokio::fs::rename(&segment.path, &recycle_path).await?;
+                    self.recycle_pool.push(recycle_path);
+                    println!("wal文件:【{}】等待复用", segment.name);
+                }
+                None => {
+                    tokio::fs::remove_file(&segment.path).await?;
+                    println!("wal文件:【{}】已删除", segment.name);
+                }
+            }
+            let _ = tokio::fs::remove_file(index_path).await;
+            self.indexs_map.remove(&segment.name);
+            resp.push(segment.name);
+        }
+        Ok(resp)
+    }
+}
+
+/**
+ * wal 文件恢复相关的方法
+ */
+impl WalService {
+    /**
+     * 按创建顺序返回目录下所有wal文件的路径，不包括每个表的wal
+     */
+    pub async fn wal_file_paths(&self) -> Result<Vec<String>> {
+        let files_name = get_wal_files_name(&self.path).await?;
+        Ok(files_name
+            .iter()
+            .map(|file_name| self.file_path(file_name))
+            .collect())
+    }
+
+    /**
+     * 读取wal文件中的记录，写满的wal文件通过mmap读取
+     */
+    pub async fn segment_reader(&self, file_path: &str) -> Result<SegmentReader> {
+        let file_name = file_path.rsplit('/').next().unwrap_or_default();
+        match (self.sealed.get(file_name), self.indexs_map.get(file_name)) {
+            (Some(sealed), Some(offsets)) => {
+                Ok(SegmentReader::sealed(sealed.clone(), offsets.clone()))
+            }
+            _ => SegmentReader::open(file_path).await,
+        }
+    }
+
+    /**
+     * 所有写满的wal文件(包括每个表的wal)，可以交给其他任务并发读取
+     */
+    pub fn sealed_segments(&self) -> Vec<SealedWal> {
+        self.all_streams()
+            .into_iter()
+            .flat_map(|stream| stream.sealed.values().cloned())
+            .collect()
+    }
+
+    /**
+     * 序列号为seq的记录是否已经写入sstable，恢复时不需要重放
+     */
+    pub fn is_checkpointed(&self, seq: u64) -> bool {
+        self.checkpoint.is_some_and(|c| seq <= c)
+    }
+
+    /**
+     * 按创建顺序读取所有的wal文件(包括每个表的wal)，返回还没有被checkpoint覆盖的WalMsg
+     */
+    pub async fn load_wal_msgs(&self) -> Result<Vec<WalMsg>> {
+        let mut resp = Vec::new();
+        for stream in self.all_streams() {
+            resp.extend(stream.load_stream_msgs().await?);
+        }
+        Ok(resp)
+    }
+
+    async fn load_stream_msgs(&self) -> Result<Vec<WalMsg>> {
+        let mut resp = Vec::new();
+        for file_path in self.wal_file_paths().await? {
+            // 末尾不完整的记录直接丢弃，不影响整个文件的恢复
+            let mut reader = self.segment_reader(&file_path).await?;
+            let mut num = 0;
+            while let Some(record) = reader.next().await? {
+                if self.is_checkpointed(record.seq()) {
+                    continue;
+                }
+                resp.push(WalMsg::decode(record.data()?)?);
+                num += 1;
+            }
+            println!("wal文件:【{}】,恢复数据 {} 条", file_path, num);
+        }
+        Ok(resp)
+    }
+}
+
+/**
+ * LSN相关的方法
+ */
+impl WalService {
+    /**
+     * 下一次append将要分配的LSN
+     */
+    pub fn next_lsn(&self) -> Lsn {
+        self.streams
+            .values()
+            .map(|stream| stream.wal.next_seq())
+            .fold(self.wal.next_seq(), Lsn::max)
+    }
+
+    /**
+     * 最近一次append分配的LSN，还没有写入过数据时返回None
+     */
+    pub fn last_lsn(&self) -> Option<Lsn> {
+        self.next_lsn().checked_sub(1)
+    }
+
+    /**
+     * 以lsn为游标，按顺序读取LSN大于等于lsn的记录(包括每个表的wal)，最多返回limit条
+     * 游标之前的数据已经被purge时返回错误，调用方需要从sstable重新同步
+     */
+    pub async fn read_from(&self, lsn: Lsn, limit: usize) -> LsmResult<Vec<(Lsn, WalMsg)>> {
+        let mut resp = Vec::new();
+        for stream in self.all_streams() {
+            resp.extend(stream.read_stream(lsn, limit).await?);
+        }
+        resp.sort_by_key(|(lsn, _)| *lsn);
+        resp.truncate(limit);
+        Ok(resp)
+    }
+
+    async fn read_stream(&self, lsn: Lsn, limit: usize) -> LsmResult<Vec<(Lsn, WalMsg)>> {
+        let read_err = |e: anyhow::Error| LsmError::WalRead(e.to_string());
+        let segments = list_segments(&self.path).await.map_err(read_err)?;
+        if let Some(first) = segments.first() {
+            // 只有被checkpoint覆盖的数据才会被清理
+            if first.start_seq > lsn && self.is_checkpointed(lsn) {
+                return Err(LsmError::LsnPurged {
+                    lsn,
+                    oldest: first.start_seq,
+                });
+            }
+        }
+        let mut resp = Vec::new();
+        for (i, segment) in segments.iter().enumerate() {
+            // 下一个文件的起始LSN不大于lsn时，当前文件中的记录都在游标之前
+            if segments
+                .get(i + 1)
+                .is_some_and(|next| next.start_seq <= lsn)
+            {
+                continue;
+            }
+            let mut reader = self.segment_reader(&segment.path).await.map_err(read_err)?;
+            reader.skip_to(lsn);
+            while let Some(record) = reader.next().await.map_err(read_err)? {
+                if record.seq() < lsn {
+                    continue;
+                }
+                if resp.len() >= limit {
+                    return Ok(resp);
+                }
+                let wal_msg = record.data().and_then(WalMsg::decode).map_err(read_err)?;
+                resp.push((record.seq(), wal_msg));
+            }
+        }
+        Ok(resp)
+    }
+}
+
 impl<T> Append<T> for WalService
 where
-    T: IntoWalMsg + Clone,
+    T: IntoWalMsg,
 {
-    type Result = bool;
+    // 写入成功时返回数据所在的位置，其中包含分配给这条数据的LSN
+    type Result = LsmResult<Offset>;
 
     async fn append(&mut self, data: T) -> Self::Result {
-        loop {
-            // 1、数据落盘
-            let resp = self.wal.append(data.clone()).await;
-            let resp = match resp {
-                Ok(offset) => {
-                    self.indexs.push(offset);
-                    true
-                }
-                Err(_e) => {
-                    println!("wal 写入已满,新建wal文件：【{:?}】", self.wal.name());
-                    let resp = self.update_wal().await;
-                    match resp {
-                        Ok(_b) => continue,
-                        Err(e) => {
-                            println!("wal 更新失败：{:?}", e);
-                            return false;
-                        }
-                    }
-                }
-            };
-            return resp;
+        let wal_msg = data.to_wal_msg();
+        // 恢复时还不能重放的操作，写入wal之后重启前后的状态会不一致
+        if wal_msg.kind() == EntryKind::DeleteRange {
+            return Err(LsmError::UnsupportedEntry(format!("{:?}", wal_msg.kind())));
+        }
+        let next_lsn = self.next_lsn();
+        let prefix = match self.mode.is_per_table() {
+            true => data.table_name(),
+            false => None,
+        };
+        let Some(prefix) = prefix else {
+            return self.append_wal(wal_msg, next_lsn).await;
+        };
+        if !self.streams.contains_key(&prefix) {
+            let stream = self
+                .create_stream(&prefix)
+                .await
+                .map_err(|e| LsmError::WalWrite(e.to_string()))?;
+            self.streams.insert(prefix.clone(), stream);
+        }
+        match self.streams.get_mut(&prefix) {
+            Some(stream) => stream.append_wal(wal_msg, next_lsn).await,
+            None => Err(LsmError::WalWrite(format!(
+                "wal of table 【{}】 not found",
+                prefix
+            ))),
         }
     }
 }
 
+impl WalService {
+    /**
+     * 写入当前的wal，next_lsn是所有wal中下一个LSN，保证LSN全局递增
+     * 写入之前检查文件剩余的空间，不够时先切换wal文件，wal文件的大小不会超过 wal_max_size
+     */
+    async fn append_wal(&mut self, wal_msg: WalMsg, next_lsn: Lsn) -> LsmResult<Offset> {
+        self.wal.advance_seq(next_lsn);
+        let mut buf = BytesMut::new();
+        wal_msg.encode(&mut buf);
+        let record = self
+            .wal
+            .build_record(buf.freeze())
+            .map_err(|e| LsmError::WalWrite(e.to_string()))?;
+        // 一个空的wal文件也放不下的记录，切换wal文件也无法写入
+        let size = record.encode_len();
+        let max = self.wal_max_size.saturating_sub(FILE_HEADER_LEN);
+        if size > max {
+            return Err(LsmError::RecordTooLarge { size, max });
+        }
+        if !self.wal.is_writeable() || !self.wal.fits(size) {
+            println!("wal 写入已满,新建wal文件：【{:?}】", self.wal.name());
+            self.update_wal()
+                .await
+                .map_err(|e| LsmError::WalRollover(e.to_string()))?;
+        }
+        // 数据写入wal，是否fsync由commit根据落盘策略决定
+        let offset = self
+            .wal
+            .write_record(record)
+            .await
+            .map_err(|e| LsmError::WalWrite(e.to_string()))?;
+        self.indexs.push(offset.clone());
+        self.dirty = true;
+        Ok(offset)
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use arrow::{
+        array::{RecordBatch, StringArray},
+        datatypes::{DataType, Field, Schema},
+    };
+    use arrow_flight::{utils::batches_to_flight_data, FlightData};
+
+    use crate::{
+        error::LsmError,
+        utils::{
+            file_utils::{get_recycle_files_name, get_wal_files_name},
+            time_utils::now,
+        },
+        wal::{
+            active_wal::ActiveWal,
+            compression::Compression,
+            index_file::IndexFile,
+            record::{FILE_HEADER_LEN, RECORD_HEADER_LEN},
+            retention::WalRetention,
+            segment::segment_file_name,
+            segment::WalSegment,
+            wal_mode::WalMode,
+            wal_msg::WalMsg,
+            Append, TABLE_WAL_DIR,
+        },
+        WalService, TABLE_NAME,
+    };
 
-    use crate::WalService;
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn wal_service_should_read_mixed_compressed_records() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-wal-compression-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let mut service = WalService::init(&path, 1024 * 1024).await.unwrap();
+        let compressions = [Compression::None, Compression::Lz4, Compression::Zstd(3)];
+        let mut datas = Vec::new();
+        for (i, compression) in compressions.into_iter().enumerate() {
+            service = service.with_compression(compression);
+            let fds = vec![FlightData::new().with_data_body(vec![i as u8; 1024])];
+            let offset = service.append(fds.clone()).await.unwrap();
+            datas.push((offset, WalMsg::from(fds)));
+        }
+        service.sync().await.unwrap();
+        // 同一个wal文件中压缩和未压缩的记录都可以读取
+        assert!(datas[1].0.len < datas[0].0.len);
+        let wal = ActiveWal::open(&service.file_path(&service.wal.name()))
+            .await
+            .unwrap();
+        for (offset, wal_msg) in datas.iter() {
+            assert_eq!(&wal.read_with_index(offset.clone()).await.unwrap(), wal_msg);
+            let position = offset.offset - RECORD_HEADER_LEN;
+            assert_eq!(&wal.read_with_offset(position).await.unwrap(), wal_msg);
+        }
+        let wal_msgs = service.load_wal_msgs().await.unwrap();
+        let expect: Vec<WalMsg> = datas.into_iter().map(|(_, wal_msg)| wal_msg).collect();
+        assert_eq!(wal_msgs, expect);
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn wal_service_should_load_index_after_restart() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-wal-index-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let path = path.to_str().unwrap().to_string();
+        // wal文件很小，每次写入都会切换wal文件
+        let mut service = WalService::init(&path, 100).await.unwrap();
+        let mut sealed = Vec::new();
+        for i in 0..3 {
+            let old_name = service.wal.name();
+            let fds = vec![FlightData::new().with_data_body(vec![i as u8; 32])];
+            assert!(service.append(fds).await.is_ok());
+            if old_name != service.wal.name() {
+                sealed.push(old_name);
+            }
+        }
+        assert!(!sealed.is_empty());
+
+        let service1 = WalService::init(&path, 100).await.unwrap();
+        for name in sealed.iter() {
+            let offsets = service1.offsets(name);
+            assert_eq!(offsets, service.offsets(name));
+            // 索引来自索引文件，而不是重新扫描wal文件
+            let index_file = IndexFile::init(&format!("{}/{}", path, name));
+            assert_eq!(index_file.load().await.unwrap().offsets, offsets);
+        }
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn wal_service_checkpoint_should_purge_wal_files() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-wal-checkpoint-{}", now()));
+        let archive_path = path.join("archive");
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let archive_path = archive_path.to_str().unwrap().to_string();
+        let retention = WalRetention::new().with_archive_path(&archive_path);
+        let mut service = WalService::init(&path, 100)
+            .await
+            .unwrap()
+            .with_retention(retention);
+        // 每条记录一个wal文件, 序列号为 0..5
+        for i in 0..5 {
+            let fds = vec![FlightData::new().with_data_body(vec![i as u8; 32])];
+            assert!(service.append(fds).await.is_ok());
+        }
+        assert_eq!(get_wal_files_name(&path).await.unwrap().len(), 5);
+        // 序列号0、1、2已经写入sstable
+        let purged = service.checkpoint(2).await.unwrap();
+        assert_eq!(purged.len(), 3);
+        assert_eq!(get_wal_files_name(&path).await.unwrap().len(), 2);
+        assert_eq!(get_wal_files_name(&archive_path).await.unwrap(), purged);
+        // 重启之后只恢复没有被checkpoint覆盖的数据
+        let service = WalService::init(&path, 100).await.unwrap();
+        assert_eq!(service.checkpoint_seq(), Some(2));
+        assert_eq!(service.load_wal_msgs().await.unwrap().len(), 2);
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn wal_service_should_preallocate_and_recycle_segments() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-wal-segment-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let segment = WalSegment::new().with_preallocate(true).with_recycle(2);
+        let mut service = WalService::init(&path, 150)
+            .await
+            .unwrap()
+            .with_segment(segment)
+            .await
+            .unwrap();
+        let file_len = |name: String| {
+            let file_path = format!("{}/{}", path, name);
+            async move { tokio::fs::metadata(file_path).await.unwrap().len() }
+        };
+        assert_eq!(file_len(segment_file_name(0)).await, 150);
+        // 每个wal文件两条记录，序列号为 0..6
+        for i in 0..6 {
+            let fds = vec![FlightData::new().with_data_body(vec![i as u8; 32])];
+            assert!(service.append(fds).await.is_ok());
+        }
+        let names: Vec<String> = (0..3).map(segment_file_name).collect();
+        assert_eq!(get_wal_files_name(&path).await.unwrap(), names);
+        // 被checkpoint覆盖的wal文件进入复用池
+        let purged = service.checkpoint(3).await.unwrap();
+        assert_eq!(purged, names[..2].to_vec());
+        assert_eq!(get_recycle_files_name(&path).await.unwrap().len(), 2);
+        // 切换wal文件时复用旧文件，segment id 继续递增
+        let fds = vec![FlightData::new().with_data_body(vec![6; 32])];
+        assert_eq!(service.append(fds).await.unwrap().lsn(), 6);
+        service.sync().await.unwrap();
+        assert_eq!(
+            get_wal_files_name(&path).await.unwrap(),
+            vec![segment_file_name(2), segment_file_name(3)]
+        );
+        assert_eq!(get_recycle_files_name(&path).await.unwrap().len(), 1);
+
+        // 重启之后忽略复用文件中遗留的旧数据
+        let mut service = WalService::init(&path, 150)
+            .await
+            .unwrap()
+            .with_segment(segment)
+            .await
+            .unwrap();
+        assert_eq!(service.last_lsn(), Some(6));
+        assert_eq!(service.load_wal_msgs().await.unwrap().len(), 3);
+        let fds = vec![FlightData::new().with_data_body(vec![7; 32])];
+        assert_eq!(service.append(fds).await.unwrap().lsn(), 7);
+        service.sync().await.unwrap();
+        let service = WalService::init(&path, 150).await.unwrap();
+        let records = service.read_from(4, 10).await.unwrap();
+        let lsns: Vec<u64> = records.iter().map(|(lsn, _)| *lsn).collect();
+        assert_eq!(lsns, vec![4, 5, 6, 7]);
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn wal_service_should_not_recycle_segments_in_use() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-wal-in-use-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let segment = WalSegment::new().with_recycle(2);
+        let mut service = WalService::init(&path, 150)
+            .await
+            .unwrap()
+            .with_segment(segment)
+            .await
+            .unwrap();
+        // 每个wal文件两条记录，序列号为 0..6
+        for i in 0..6 {
+            let fds = vec![FlightData::new().with_data_body(vec![i as u8; 32])];
+            assert_eq!(service.append(fds).await.unwrap().lsn(), i);
+        }
+        // 其他任务持有第一个wal文件的mmap，以及从中读取到的记录
+        let held = service
+            .sealed_segments()
+            .into_iter()
+            .find(|wal| wal.name() == segment_file_name(0))
+            .unwrap();
+        let record = held.read_record_at(FILE_HEADER_LEN).unwrap();
+        let expect = held.data().to_vec();
+        let purged = service.checkpoint(3).await.unwrap();
+        assert_eq!(purged, vec![segment_file_name(0), segment_file_name(1)]);
+        // 只有没有被持有的wal文件进入复用池
+        assert_eq!(get_recycle_files_name(&path).await.unwrap().len(), 1);
+        // 切换wal文件时复用旧文件，持有的mmap中的数据不受影响
+        for i in 6..10 {
+            let fds = vec![FlightData::new().with_data_body(vec![i as u8; 32])];
+            assert!(service.append(fds).await.is_ok());
+        }
+        service.sync().await.unwrap();
+        assert!(get_recycle_files_name(&path).await.unwrap().is_empty());
+        assert_eq!(held.data().to_vec(), expect);
+        assert_eq!(record.seq(), 0);
+        assert_eq!(
+            WalMsg::decode(record.data().unwrap()).unwrap(),
+            WalMsg::from(vec![FlightData::new().with_data_body(vec![0; 32])])
+        );
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn wal_service_should_not_exceed_wal_size() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-wal-cap-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let mut service = WalService::init(&path, 150).await.unwrap();
+        for i in 0..5 {
+            let fds = vec![FlightData::new().with_data_body(vec![i as u8; 32])];
+            assert_eq!(service.append(fds).await.unwrap().lsn(), i);
+        }
+        // 下一条记录放不下时先切换wal文件，每个文件最多两条记录
+        let names = get_wal_files_name(&path).await.unwrap();
+        assert_eq!(names.len(), 3);
+        for name in names {
+            let file_path = format!("{}/{}", path, name);
+            assert!(tokio::fs::metadata(file_path).await.unwrap().len() <= 150);
+        }
+        // 超过wal文件大小的记录直接返回错误，不会切换wal文件，也不会占用LSN
+        let fds = vec![FlightData::new().with_data_body(vec![0; 200])];
+        match service.append(fds).await {
+            Err(LsmError::RecordTooLarge { size, max }) => {
+                assert!(size > max);
+                assert_eq!(max, 150 - FILE_HEADER_LEN);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(get_wal_files_name(&path).await.unwrap().len(), 3);
+        let fds = vec![FlightData::new().with_data_body(vec![5; 32])];
+        assert_eq!(service.append(fds).await.unwrap().lsn(), 5);
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn wal_service_should_read_sealed_segments_concurrently() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-wal-sealed-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let path = path.to_str().unwrap().to_string();
+        // 每条记录一个wal文件
+        let mut service = WalService::init(&path, 100).await.unwrap();
+        let mut expect = Vec::new();
+        for i in 0..4 {
+            let fds = vec![FlightData::new().with_data_body(vec![i as u8; 32])];
+            expect.push(WalMsg::from(fds.clone()));
+            service.append(fds).await.unwrap();
+        }
+        let sealed = service.sealed_segments();
+        assert_eq!(sealed.len(), 3);
+        // 写满的wal文件可以在其他任务中读取，同时继续写入
+        let first = sealed
+            .into_iter()
+            .find(|wal| wal.name() == segment_file_name(0))
+            .unwrap();
+        let reader = tokio::spawn(async move { first.read_with_offset(FILE_HEADER_LEN) });
+        for i in 4..8 {
+            let fds = vec![FlightData::new().with_data_body(vec![i as u8; 32])];
+            expect.push(WalMsg::from(fds.clone()));
+            service.append(fds).await.unwrap();
+        }
+        assert_eq!(reader.await.unwrap().unwrap(), expect[0]);
+        let records = service.read_from(2, 3).await.unwrap();
+        let msgs: Vec<WalMsg> = records.into_iter().map(|(_, msg)| msg).collect();
+        assert_eq!(msgs, expect[2..5].to_vec());
+        // 重启之后写满的wal文件同样通过mmap读取
+        let service = WalService::init(&path, 100).await.unwrap();
+        assert_eq!(service.sealed_segments().len(), 7);
+        assert_eq!(service.load_wal_msgs().await.unwrap(), expect);
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn wal_service_lsn_should_increase_across_files_and_restart() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-wal-lsn-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let mut service = WalService::init(&path, 100).await.unwrap();
+        assert_eq!(service.last_lsn(), None);
+        let mut lsns = Vec::new();
+        for i in 0..3 {
+            let fds = vec![FlightData::new().with_data_body(vec![i as u8; 32])];
+            lsns.push(service.append(fds).await.unwrap().lsn());
+        }
+        assert_eq!(lsns, vec![0, 1, 2]);
+        // 重启之后LSN继续递增
+        let mut service = WalService::init(&path, 100).await.unwrap();
+        assert_eq!(service.last_lsn(), Some(2));
+        let fds = vec![FlightData::new().with_data_body(vec![3; 32])];
+        assert_eq!(service.append(fds).await.unwrap().lsn(), 3);
+        // 以LSN为游标读取
+        let records = service.read_from(1, 2).await.unwrap();
+        let lsns: Vec<u64> = records.iter().map(|(lsn, _)| *lsn).collect();
+        assert_eq!(lsns, vec![1, 2]);
+        assert!(service.read_from(4, 10).await.unwrap().is_empty());
+        // 被purge之后的游标返回错误
+        service.checkpoint(1).await.unwrap();
+        assert!(service.read_from(0, 10).await.is_err());
+        assert_eq!(service.read_from(2, 10).await.unwrap().len(), 2);
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+
+    fn create_table_data(table: &str) -> Vec<FlightData> {
+        let schema = Arc::new(
+            Schema::new(vec![Field::new("name", DataType::Utf8, true)])
+                .with_metadata(HashMap::from([(TABLE_NAME.to_string(), table.to_string())])),
+        );
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(StringArray::from(vec![table]))],
+        )
+        .unwrap();
+        batches_to_flight_data(&schema, vec![batch]).unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn wal_service_per_table_mode() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-wal-per-table-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let path = path.to_str().unwrap().to_string();
+        // cpu表的wal文件很小，每次写入都会切换wal文件
+        let mode = WalMode::per_table().with_table_wal_size("cpu", 600);
+        let mut service = WalService::init(&path, 1024 * 1024)
+            .await
+            .unwrap()
+            .with_mode(mode.clone())
+            .await
+            .unwrap();
+        let mut lsns = Vec::new();
+        for i in 0..6 {
+            let table = if i % 2 == 0 { "cpu" } else { "mem" };
+            lsns.push(
+                service
+                    .append(create_table_data(table))
+                    .await
+                    .unwrap()
+                    .lsn(),
+            );
+        }
+        // 没有表名的数据写入共享的wal
+        let fds = vec![FlightData::new().with_data_body(vec![1; 8])];
+        lsns.push(service.append(fds).await.unwrap().lsn());
+        // LSN在所有表之间全局递增
+        assert_eq!(lsns, (0..7).collect::<Vec<u64>>());
+        service.sync().await.unwrap();
+        let cpu_path = format!("{}/{}/cpu", path, TABLE_WAL_DIR);
+        let mem_path = format!("{}/{}/mem", path, TABLE_WAL_DIR);
+        assert_eq!(get_wal_files_name(&cpu_path).await.unwrap().len(), 3);
+        assert_eq!(get_wal_files_name(&mem_path).await.unwrap().len(), 1);
+        assert_eq!(get_wal_files_name(&path).await.unwrap().len(), 1);
+
+        // 只清理cpu表的wal
+        let purged = service.checkpoint_table("cpu", 4).await.unwrap();
+        assert_eq!(purged.len(), 2);
+        assert_eq!(get_wal_files_name(&cpu_path).await.unwrap().len(), 1);
+        assert_eq!(get_wal_files_name(&mem_path).await.unwrap().len(), 1);
+
+        // 重启之后加载每个表的wal，LSN继续递增
+        let service = WalService::init(&path, 1024 * 1024)
+            .await
+            .unwrap()
+            .with_mode(mode)
+            .await
+            .unwrap();
+        assert_eq!(service.last_lsn(), Some(6));
+        assert!(service.table_stream("cpu").is_some());
+        // cpu表的数据都已经被checkpoint覆盖
+        assert_eq!(service.load_wal_msgs().await.unwrap().len(), 4);
+        let records = service.read_from(5, 10).await.unwrap();
+        let lsns: Vec<u64> = records.iter().map(|(lsn, _)| *lsn).collect();
+        assert_eq!(lsns, vec![5, 6]);
+        assert!(service.read_from(1, 10).await.is_err());
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
     async fn wal_service_init_test() {
@@ -1,12 +1,25 @@
+/**
+ * 全局单调递增的日志序列号(Log Sequence Number)
+ * 每次append都会分配一个新的LSN，跨wal文件(以及重启)保持递增，
+ * 可以作为复制、变更订阅和read-your-writes查询的游标
+ */
+pub type Lsn = u64;
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Offset {
     pub(crate) offset: usize,
     pub(crate) len: usize,
+    // 该记录的LSN
+    pub(crate) lsn: Lsn,
 }
 
 impl Offset {
     pub fn from(offset: usize) -> Self {
-        Self { offset, len: 0 }
+        Self {
+            offset,
+            len: 0,
+            lsn: 0,
+        }
     }
 
     pub fn with_fd_lens(offset: usize, fd_lens: Vec<u32>) -> Self {
@@ -14,12 +27,17 @@ impl Offset {
         Self {
             offset,
             len: sum as usize,
+            lsn: 0,
         }
     }
 
     pub fn update(&mut self, len: usize) {
         self.len = len;
     }
+
+    pub fn lsn(&self) -> Lsn {
+        self.lsn
+    }
 }
 
 #[cfg(test)]
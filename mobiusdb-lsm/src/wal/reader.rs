@@ -0,0 +1,446 @@
+use std::{fmt::Display, io::SeekFrom};
+
+use anyhow::Result;
+use bytes::{Bytes, BytesMut};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, BufReader},
+};
+
+use super::{
+    offset::{Lsn, Offset},
+    record::{
+        find_record, is_zeroed, RecordHeader, RecoveryMode, WalFileHeader, WalRecord,
+        FILE_HEADER_LEN, RECORD_HEADER_LEN,
+    },
+    sealed_wal::SealedWal,
+    serialization::Decoder,
+};
+
+// 读取wal文件时使用的缓冲区大小
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/**
+ * 顺序读取wal文件中的记录，每次只读取一条记录到内存中，
+ * 内存占用和wal文件的大小无关，只和单条记录的大小有关
+ *
+ *  let mut reader = WalReader::open(path, RecoveryMode::TruncateTail).await?;
+ *  while let Some((offset, record)) = reader.next().await? { ... }
+ *
+ * 对损坏数据的处理和 scan_records 一致：
+ *  文件末尾不完整的记录在 TruncateTail 模式下被丢弃(is_torn 为 true)，文件中间的损坏总是返回错误
+ *  预分配空间中的0和复用文件中的旧数据表示数据结束，不是损坏，
+ *  复用文件中校验失败的记录之后还有序列号递增的记录时是损坏
+ */
+#[derive(Debug)]
+pub struct WalReader {
+    reader: BufReader<File>,
+    file_len: usize,
+    mode: RecoveryMode,
+    header: Option<WalFileHeader>,
+    // 下一条记录的起始位置，也是文件中完整记录的长度
+    position: usize,
+    last_seq: Option<u64>,
+    torn: bool,
+    // 已经读取到数据结束的位置
+    finished: bool,
+}
+
+impl WalReader {
+    pub async fn open(path: &str, mode: RecoveryMode) -> Result<Self> {
+        let file = File::open(path).await?;
+        let file_len = file.metadata().await?.len() as usize;
+        let mut reader = Self {
+            reader: BufReader::with_capacity(READ_BUFFER_SIZE, file),
+            file_len,
+            mode,
+            header: None,
+            position: 0,
+            last_seq: None,
+            torn: false,
+            finished: false,
+        };
+        if file_len == 0 {
+            return Ok(reader);
+        }
+        if file_len < FILE_HEADER_LEN {
+            reader.torn_tail("wal file header is torn")?;
+            return Ok(reader);
+        }
+        let mut buf = vec![0; FILE_HEADER_LEN];
+        reader.reader.read_exact(&mut buf).await?;
+        reader.header = Some(WalFileHeader::decode(Bytes::from(buf))?);
+        reader.position = FILE_HEADER_LEN;
+        Ok(reader)
+    }
+
+    /**
+     * 读取下一条记录以及其payload所在的位置，读取完毕(或遇到torn tail)时返回None
+     */
+    pub async fn next(&mut self) -> Result<Option<(Offset, WalRecord)>> {
+        if self.torn || self.finished || self.position >= self.file_len {
+            return Ok(None);
+        }
+        let rest = self.file_len - self.position;
+        let recycled = self.header.is_some_and(|header| header.is_recycled());
+        if rest < RECORD_HEADER_LEN {
+            let mut buf = vec![0; rest];
+            self.reader.read_exact(&mut buf).await?;
+            if is_zeroed(&buf) || recycled {
+                self.finished = true;
+                return Ok(None);
+            }
+            let msg = format!(
+                "wal record is torn: header needs {} bytes",
+                RECORD_HEADER_LEN
+            );
+            self.torn_tail(&msg)?;
+            return Ok(None);
+        }
+        let mut header = [0; RECORD_HEADER_LEN];
+        self.reader.read_exact(&mut header).await?;
+        let len = match RecordHeader::parse(&header) {
+            Ok(header) => header.len,
+            Err(e) if recycled => return self.stale_or_corrupted(e).await,
+            // 记录头校验失败时len不可信，只有之后全为0时才是数据结束
+            Err(e) => {
+                if !self.rest_is_zeroed().await? {
+                    let msg = format!("wal file is corrupted at position {}: {}", self.position, e);
+                    return Err(anyhow::Error::msg(msg));
+                }
+                // 全为0是预分配的空间，否则是写了一半的记录头
+                if is_zeroed(&header) {
+                    self.finished = true;
+                } else {
+                    self.torn_tail(&e.to_string())?;
+                }
+                return Ok(None);
+            }
+        };
+        // 记录头校验通过，长度超过文件剩余的数据，是末尾写了一半的记录(或者复用文件中的旧数据)，
+        // 同时避免按照错误的长度分配内存
+        if len > rest - RECORD_HEADER_LEN {
+            let msg = format!(
+                "wal record is torn: need {} bytes but {} left",
+                len,
+                rest - RECORD_HEADER_LEN
+            );
+            if recycled {
+                return self.stale_or_corrupted(msg).await;
+            }
+            self.torn_tail(&msg)?;
+            return Ok(None);
+        }
+        let mut buf = BytesMut::with_capacity(RECORD_HEADER_LEN + len);
+        buf.extend_from_slice(&header);
+        buf.resize(RECORD_HEADER_LEN + len, 0);
+        self.reader
+            .read_exact(&mut buf[RECORD_HEADER_LEN..])
+            .await?;
+        match WalRecord::decode(buf.freeze()) {
+            // 复用的文件中遗留的旧数据，数据结束
+            Ok(record)
+                if self
+                    .header
+                    .is_some_and(|header| header.is_stale(record.seq, self.last_seq)) =>
+            {
+                self.finished = true;
+                Ok(None)
+            }
+            Err(e) if recycled => self.stale_or_corrupted(e).await,
+            Ok(record) => {
+                let offset = Offset {
+                    offset: self.position + RECORD_HEADER_LEN,
+                    len: record.payload.len(),
+                    lsn: record.seq,
+                };
+                self.position += record.encode_len();
+                self.last_seq = Some(record.seq);
+                Ok(Some((offset, record)))
+            }
+            Err(e) => {
+                // 预分配的文件中，写了一半的记录之后全为0
+                if !self.rest_is_zeroed().await? {
+                    let msg = format!("wal file is corrupted at position {}: {}", self.position, e);
+                    return Err(anyhow::Error::msg(msg));
+                }
+                self.torn_tail(&e.to_string())?;
+                Ok(None)
+            }
+        }
+    }
+
+    /**
+     * 复用的文件中校验失败的记录：之后没有序列号递增的记录时是旧数据(数据结束)，
+     * 否则是文件中间的损坏
+     */
+    async fn stale_or_corrupted(&mut self, e: impl Display) -> Result<Option<(Offset, WalRecord)>> {
+        if let Some(found) = self.find_record_after().await? {
+            let msg = format!(
+                "wal file is corrupted at position {}: {}, but a newer record is found at {}",
+                self.position, e, found
+            );
+            return Err(anyhow::Error::msg(msg));
+        }
+        self.finished = true;
+        Ok(None)
+    }
+
+    /**
+     * 从当前记录的下一个字节开始查找序列号递增的记录(record::find_record)，返回它的位置，
+     * 分块读取，相邻的两块之间保留不足一个记录头的数据
+     */
+    async fn find_record_after(&mut self) -> Result<Option<usize>> {
+        let Some(header) = self.header else {
+            return Ok(None);
+        };
+        let mut start = self.position + 1;
+        self.reader.seek(SeekFrom::Start(start as u64)).await?;
+        let mut buf = Vec::with_capacity(READ_BUFFER_SIZE + RECORD_HEADER_LEN);
+        let mut chunk = vec![0; READ_BUFFER_SIZE];
+        loop {
+            let n = self.reader.read(&mut chunk).await?;
+            if n == 0 {
+                return Ok(None);
+            }
+            buf.extend_from_slice(&chunk[..n]);
+            if let Some(found) = find_record(&buf, &header, self.last_seq) {
+                return Ok(Some(start + found));
+            }
+            let consumed = buf.len().saturating_sub(RECORD_HEADER_LEN - 1);
+            buf.drain(..consumed);
+            start += consumed;
+        }
+    }
+
+    /**
+     * 从当前读取的位置到文件末尾是否全为0
+     */
+    async fn rest_is_zeroed(&mut self) -> Result<bool> {
+        let mut buf = vec![0; READ_BUFFER_SIZE];
+        loop {
+            let n = self.reader.read(&mut buf).await?;
+            if n == 0 {
+                return Ok(true);
+            }
+            if !is_zeroed(&buf[..n]) {
+                return Ok(false);
+            }
+        }
+    }
+
+    fn torn_tail(&mut self, msg: &str) -> Result<()> {
+        if self.mode == RecoveryMode::Strict {
+            let msg = format!(
+                "wal file is corrupted at position {}: {}",
+                self.position, msg
+            );
+            return Err(anyhow::Error::msg(msg));
+        }
+        println!(
+            "wal文件末尾存在不完整的记录，丢弃位置 {} 之后的数据: {}",
+            self.position, msg
+        );
+        self.torn = true;
+        Ok(())
+    }
+
+    pub fn header(&self) -> Option<WalFileHeader> {
+        self.header
+    }
+
+    /**
+     * 已经读取的完整记录的长度，is_torn为true时文件应该被截断到这个长度
+     */
+    pub fn valid_len(&self) -> usize {
+        self.position
+    }
+
+    pub fn is_torn(&self) -> bool {
+        self.torn
+    }
+
+    /**
+     * 下一条记录应该使用的序列号，读取完所有记录之后才有意义
+     */
+    pub fn next_seq(&self) -> u64 {
+        match (self.last_seq, self.header) {
+            (Some(seq), _) => seq + 1,
+            (None, Some(header)) => header.start_seq,
+            (None, None) => 0,
+        }
+    }
+}
+
+/**
+ * 读取一个wal文件中的记录：
+ *  Sealed: 写满的wal文件，通过mmap按照索引读取，不需要扫描文件
+ *  File: 当前写入的wal文件(或者无法mmap的文件)，通过 WalReader 逐条读取
+ */
+#[derive(Debug)]
+pub enum SegmentReader {
+    Sealed {
+        wal: SealedWal,
+        offsets: Vec<Offset>,
+        next: usize,
+    },
+    File(WalReader),
+}
+
+impl SegmentReader {
+    pub fn sealed(wal: SealedWal, offsets: Vec<Offset>) -> Self {
+        SegmentReader::Sealed {
+            wal,
+            offsets,
+            next: 0,
+        }
+    }
+
+    pub async fn open(path: &str) -> Result<Self> {
+        let reader = WalReader::open(path, RecoveryMode::TruncateTail).await?;
+        Ok(SegmentReader::File(reader))
+    }
+
+    /**
+     * 跳过LSN小于lsn的记录，写满的wal文件可以直接通过索引定位
+     */
+    pub fn skip_to(&mut self, lsn: Lsn) {
+        if let SegmentReader::Sealed { offsets, next, .. } = self {
+            *next = offsets.partition_point(|offset| offset.lsn < lsn);
+        }
+    }
+
+    pub async fn next(&mut self) -> Result<Option<WalRecord>> {
+        match self {
+            SegmentReader::Sealed { wal, offsets, next } => match offsets.get(*next) {
+                Some(offset) => {
+                    *next += 1;
+                    Ok(Some(wal.read_record(offset)?))
+                }
+                None => Ok(None),
+            },
+            SegmentReader::File(reader) => Ok(reader.next().await?.map(|(_, record)| record)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{Bytes, BytesMut};
+
+    use crate::{
+        utils::time_utils::now,
+        wal::{
+            active_wal::ActiveWal,
+            record::{scan_records, RecoveryMode, WalFileHeader, WalRecord, FILE_HEADER_LEN},
+            serialization::Encoder,
+        },
+    };
+
+    use super::WalReader;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn wal_reader_should_read_records_one_by_one() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-reader-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let file_path = path.join("0.wal");
+        let file_path = file_path.to_str().unwrap();
+        let mut buf = BytesMut::new();
+        WalFileHeader::new(5).encode(&mut buf).unwrap();
+        for seq in 5..10 {
+            let record = WalRecord::new(seq, Bytes::from(format!("record-{}", seq)));
+            record.encode(&mut buf).unwrap();
+        }
+        let full = buf.freeze();
+        // 末尾写了一半的记录
+        let torn = full.slice(..full.len() - 3);
+        tokio::fs::write(file_path, &torn).await.unwrap();
+
+        assert!(WalReader::open(file_path, RecoveryMode::Strict)
+            .await
+            .unwrap()
+            .next()
+            .await
+            .is_ok());
+        let mut reader = WalReader::open(file_path, RecoveryMode::TruncateTail)
+            .await
+            .unwrap();
+        let mut records = Vec::new();
+        while let Some(record) = reader.next().await.unwrap() {
+            records.push(record);
+        }
+        // 和一次性读取整个文件的结果一致
+        let scan = scan_records(torn, RecoveryMode::TruncateTail).unwrap();
+        assert_eq!(&records, scan.records());
+        assert!(reader.is_torn());
+        assert_eq!(reader.valid_len(), scan.valid_len());
+        assert_eq!(reader.next_seq(), 9);
+
+        let mut reader = WalReader::open(file_path, RecoveryMode::Strict)
+            .await
+            .unwrap();
+        let mut result = Ok(None);
+        for _ in 0..5 {
+            result = reader.next().await;
+        }
+        assert!(result.is_err());
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn wal_reader_should_not_truncate_on_corrupted_len() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-reader-len-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let file_path = path.join("0.wal");
+        let file_path = file_path.to_str().unwrap();
+        let mut buf = BytesMut::new();
+        WalFileHeader::new(0).encode(&mut buf).unwrap();
+        for seq in 0..5 {
+            let record = WalRecord::new(seq, Bytes::from(format!("record-{}", seq)));
+            record.encode(&mut buf).unwrap();
+        }
+        // 翻转第一条记录len中的一位，之后的记录都是有效的
+        buf[FILE_HEADER_LEN + 3] ^= 0x04;
+        tokio::fs::write(file_path, &buf).await.unwrap();
+        let mut reader = WalReader::open(file_path, RecoveryMode::TruncateTail)
+            .await
+            .unwrap();
+        assert!(reader.next().await.is_err());
+        assert!(!reader.is_torn());
+        // 加载时返回错误，不能截断文件
+        assert!(ActiveWal::load(file_path).await.is_err());
+        let len = tokio::fs::metadata(file_path).await.unwrap().len();
+        assert_eq!(len as usize, buf.len());
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn wal_reader_should_report_corruption_in_recycled_file() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-reader-recycled-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let file_path = path.join("0.wal");
+        let file_path = file_path.to_str().unwrap();
+        let mut buf = BytesMut::new();
+        WalFileHeader::new(0).encode(&mut buf).unwrap();
+        for seq in 0..10 {
+            let record = WalRecord::new(seq, Bytes::from(format!("record-{}", seq)));
+            record.encode(&mut buf).unwrap();
+        }
+        let mut new = BytesMut::new();
+        WalFileHeader::recycled(20).encode(&mut new).unwrap();
+        for seq in 20..23 {
+            let record = WalRecord::new(seq, Bytes::from(format!("new-record-{}", seq)));
+            record.encode(&mut new).unwrap();
+        }
+        buf[..new.len()].copy_from_slice(&new);
+        // 第一条新记录的payload损坏，之后的新记录仍然有效
+        buf[FILE_HEADER_LEN + new.len() / 10] ^= 0x01;
+        tokio::fs::write(file_path, &buf).await.unwrap();
+        let mut reader = WalReader::open(file_path, RecoveryMode::TruncateTail)
+            .await
+            .unwrap();
+        assert!(reader.next().await.is_err());
+        assert!(ActiveWal::load(file_path).await.is_err());
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+}
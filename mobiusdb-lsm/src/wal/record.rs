@@ -0,0 +1,634 @@
+use anyhow::Result;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use super::{
+    compression::{decompress, Compression, COMPRESSION_MASK},
+    offset::Offset,
+    serialization::{Decoder, Encoder},
+};
+
+/**
+ * wal文件的格式：
+ *
+ *  文件头: | magic(4) | version(2) | flags(2) | start_seq(8) |
+ *  记录  : | len(4) | crc32c(4) | seq(8) | flags(1) | header_crc(4) | payload(len) |
+ *
+ *  crc32c 覆盖 seq、flags 和 payload，用于发现位翻转和写入一半的记录(torn write)
+ *  header_crc 覆盖记录头中它之前的所有字段(包括len)，len 只有在记录头校验通过之后才可信
+ *  seq 即该记录的LSN，在所有wal文件之间全局单调递增
+ *  payload 是一条带有类型标记的WalMsg(version 2)
+ *  flags 的低两位表示payload的压缩算法(0: 不压缩, 1: lz4, 2: zstd)，crc32c 覆盖压缩之后的数据
+ *  文件头的 flags 表示文件是否是复用的旧文件(HEADER_RECYCLED)
+ *
+ *  预分配的文件在有效数据之后全为0，读取到全0的记录头(并且之后也全为0)表示数据结束；
+ *  复用的文件在有效数据之后是旧的数据，读取到序列号没有递增的记录、
+ *  或者校验失败并且之后没有序列号递增的记录时表示数据结束
+ */
+pub const WAL_MAGIC: &[u8; 4] = b"MBWL";
+pub const WAL_VERSION: u16 = 3;
+pub const FILE_HEADER_LEN: usize = 16;
+pub const RECORD_HEADER_LEN: usize = 21;
+// 记录头中 header_crc 之前的字段的长度
+const RECORD_HEADER_BODY_LEN: usize = RECORD_HEADER_LEN - 4;
+pub const HEADER_RECYCLED: u16 = 1;
+
+/**
+ * wal文件头
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalFileHeader {
+    pub(crate) version: u16,
+    pub(crate) flags: u16,
+    // 当前文件中第一条记录的序列号
+    pub(crate) start_seq: u64,
+}
+
+impl WalFileHeader {
+    pub fn new(start_seq: u64) -> Self {
+        Self {
+            version: WAL_VERSION,
+            flags: 0,
+            start_seq,
+        }
+    }
+
+    /**
+     * 复用旧文件时写入的文件头
+     */
+    pub fn recycled(start_seq: u64) -> Self {
+        Self {
+            flags: HEADER_RECYCLED,
+            ..Self::new(start_seq)
+        }
+    }
+
+    pub fn is_recycled(&self) -> bool {
+        self.flags & HEADER_RECYCLED != 0
+    }
+
+    /**
+     * 复用的文件中，序列号小于 start_seq 或者没有递增的记录(校验通过)是旧文件中遗留的数据
+     */
+    pub fn is_stale(&self, seq: u64, last_seq: Option<u64>) -> bool {
+        self.is_recycled() && (seq < self.start_seq || last_seq.is_some_and(|last| seq <= last))
+    }
+}
+
+/**
+ * 预分配的空间全为0
+ */
+pub fn is_zeroed(bytes: &[u8]) -> bool {
+    bytes.iter().all(|b| *b == 0)
+}
+
+impl Encoder for WalFileHeader {
+    type Error = anyhow::Error;
+
+    fn encode(&self, buffer: &mut BytesMut) -> Result<usize, Self::Error> {
+        buffer.put_slice(WAL_MAGIC);
+        buffer.put_u16(self.version);
+        buffer.put_u16(self.flags);
+        buffer.put_u64(self.start_seq);
+        Ok(FILE_HEADER_LEN)
+    }
+}
+
+impl Decoder for WalFileHeader {
+    type Error = anyhow::Error;
+
+    fn decode(mut bytes: Bytes) -> Result<Self, Self::Error> {
+        if bytes.len() < FILE_HEADER_LEN {
+            let msg = format!("invalid wal file header length: {}", bytes.len());
+            return Err(anyhow::Error::msg(msg));
+        }
+        let magic = bytes.split_to(4);
+        if magic.as_ref() != WAL_MAGIC {
+            return Err(anyhow::Error::msg("not a wal file: magic mismatch"));
+        }
+        let version = bytes.get_u16();
+        if version != WAL_VERSION {
+            let msg = format!("unsupported wal version: {}", version);
+            return Err(anyhow::Error::msg(msg));
+        }
+        let flags = bytes.get_u16();
+        let start_seq = bytes.get_u64();
+        Ok(Self {
+            version,
+            flags,
+            start_seq,
+        })
+    }
+}
+
+/**
+ * 记录头，解析时校验 header_crc
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordHeader {
+    pub(crate) len: usize,
+    pub(crate) crc: u32,
+    pub(crate) seq: u64,
+    pub(crate) flags: u8,
+}
+
+impl RecordHeader {
+    /**
+     * 解析bytes开头的记录头，bytes不足一个记录头或者校验失败时返回错误
+     */
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < RECORD_HEADER_LEN {
+            let msg = format!(
+                "wal record is torn: header needs {} bytes",
+                RECORD_HEADER_LEN
+            );
+            return Err(anyhow::Error::msg(msg));
+        }
+        let mut header = &bytes[..RECORD_HEADER_LEN];
+        let expected = crc32c::crc32c(&header[..RECORD_HEADER_BODY_LEN]);
+        let len = header.get_u32() as usize;
+        let crc = header.get_u32();
+        let seq = header.get_u64();
+        let flags = header.get_u8();
+        if header.get_u32() != expected {
+            return Err(anyhow::Error::msg("wal record header checksum mismatch"));
+        }
+        Ok(Self {
+            len,
+            crc,
+            seq,
+            flags,
+        })
+    }
+}
+
+/**
+ * wal中的一条记录
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalRecord {
+    pub(crate) seq: u64,
+    pub(crate) flags: u8,
+    pub(crate) payload: Bytes,
+}
+
+impl WalRecord {
+    pub fn new(seq: u64, payload: Bytes) -> Self {
+        Self {
+            seq,
+            flags: 0,
+            payload,
+        }
+    }
+
+    /**
+     * 按照指定的压缩算法构建一条记录
+     */
+    pub fn with_compression(seq: u64, payload: Bytes, compression: Compression) -> Result<Self> {
+        let (payload, flags) = compression.compress(payload)?;
+        Ok(Self {
+            seq,
+            flags,
+            payload,
+        })
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /**
+     * 写入文件中的payload(可能是压缩之后的数据)
+     */
+    pub fn payload(&self) -> Bytes {
+        self.payload.clone()
+    }
+
+    /**
+     * 解压之后的payload
+     */
+    pub fn data(&self) -> Result<Bytes> {
+        decompress(self.flags, self.payload.clone())
+    }
+
+    pub fn encode_len(&self) -> usize {
+        RECORD_HEADER_LEN + self.payload.len()
+    }
+
+    fn checksum(&self) -> u32 {
+        checksum(self.seq, self.flags, &self.payload)
+    }
+}
+
+fn checksum(seq: u64, flags: u8, payload: &[u8]) -> u32 {
+    let crc = crc32c::crc32c(&seq.to_be_bytes());
+    let crc = crc32c::crc32c_append(crc, &[flags]);
+    crc32c::crc32c_append(crc, payload)
+}
+
+impl Encoder for WalRecord {
+    type Error = anyhow::Error;
+
+    fn encode(&self, buffer: &mut BytesMut) -> Result<usize, Self::Error> {
+        let start = buffer.len();
+        buffer.put_u32(self.payload.len() as u32);
+        buffer.put_u32(self.checksum());
+        buffer.put_u64(self.seq);
+        buffer.put_u8(self.flags);
+        let header_crc = crc32c::crc32c(&buffer[start..]);
+        buffer.put_u32(header_crc);
+        buffer.put(self.payload.clone());
+        Ok(self.encode_len())
+    }
+}
+
+/**
+ * 解码一条完整的记录(记录头 + payload)，校验失败时返回错误
+ */
+impl Decoder for WalRecord {
+    type Error = anyhow::Error;
+
+    fn decode(mut bytes: Bytes) -> Result<Self, Self::Error> {
+        let RecordHeader {
+            len,
+            crc,
+            seq,
+            flags,
+        } = RecordHeader::parse(&bytes)?;
+        bytes.advance(RECORD_HEADER_LEN);
+        if bytes.len() < len {
+            let msg = format!(
+                "wal record {} is torn: need {} bytes but {} left",
+                seq,
+                len,
+                bytes.len()
+            );
+            return Err(anyhow::Error::msg(msg));
+        }
+        let payload = bytes.split_to(len);
+        if checksum(seq, flags, &payload) != crc {
+            let msg = format!("wal record {} checksum mismatch", seq);
+            return Err(anyhow::Error::msg(msg));
+        }
+        Ok(Self {
+            seq,
+            flags,
+            payload,
+        })
+    }
+}
+
+/**
+ * 读取wal文件时遇到损坏数据的处理方式
+ *  Strict: 任何损坏都返回错误
+ *  TruncateTail: 文件末尾写了一半(或校验失败)的记录被丢弃，文件中间的损坏仍然返回错误
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecoveryMode {
+    Strict,
+    #[default]
+    TruncateTail,
+}
+
+/**
+ * 扫描wal文件的结果
+ */
+#[derive(Debug, Clone, Default)]
+pub struct WalScan {
+    pub(crate) header: Option<WalFileHeader>,
+    // 每条记录以及其payload所在的位置
+    pub(crate) records: Vec<(Offset, WalRecord)>,
+    // 文件中完整记录的长度，torn为true时文件应该被截断到这个长度
+    pub(crate) valid_len: usize,
+    pub(crate) torn: bool,
+}
+
+impl WalScan {
+    pub fn records(&self) -> &Vec<(Offset, WalRecord)> {
+        &self.records
+    }
+
+    pub fn offsets(&self) -> Vec<Offset> {
+        self.records
+            .iter()
+            .map(|(offset, _)| offset.clone())
+            .collect()
+    }
+
+    pub fn valid_len(&self) -> usize {
+        self.valid_len
+    }
+
+    pub fn is_torn(&self) -> bool {
+        self.torn
+    }
+
+    /**
+     * 下一条记录应该使用的序列号
+     */
+    pub fn next_seq(&self) -> u64 {
+        match (self.records.last(), self.header) {
+            (Some((_, record)), _) => record.seq + 1,
+            (None, Some(header)) => header.start_seq,
+            (None, None) => 0,
+        }
+    }
+}
+
+/**
+ * 扫描整个wal文件(包含文件头)
+ */
+pub fn scan_records(bytes: Bytes, mode: RecoveryMode) -> Result<WalScan> {
+    let total = bytes.len();
+    if total == 0 {
+        return Ok(WalScan::default());
+    }
+    if total < FILE_HEADER_LEN {
+        return match mode {
+            RecoveryMode::Strict => Err(anyhow::Error::msg("wal file header is torn")),
+            RecoveryMode::TruncateTail => Ok(WalScan {
+                torn: true,
+                ..Default::default()
+            }),
+        };
+    }
+    let header = WalFileHeader::decode(bytes.slice(0..FILE_HEADER_LEN))?;
+    let mut scan = WalScan {
+        header: Some(header),
+        records: Vec::new(),
+        valid_len: FILE_HEADER_LEN,
+        torn: false,
+    };
+    let mut position = FILE_HEADER_LEN;
+    while position < total {
+        let rest = bytes.slice(position..);
+        // 预分配的空间，数据结束
+        if is_zeroed(&rest) {
+            break;
+        }
+        let last_seq = scan.records.last().map(|(_, record)| record.seq);
+        match WalRecord::decode(rest.clone()) {
+            // 复用的文件中遗留的旧数据，数据结束
+            Ok(record) if header.is_stale(record.seq, last_seq) => {
+                break;
+            }
+            // 校验失败之后没有序列号递增的记录时是旧数据，否则是文件中间的损坏
+            Err(e) if header.is_recycled() => {
+                if let Some(found) = find_record(&rest[1..], &header, last_seq) {
+                    let msg = format!(
+                        "wal file is corrupted at position {}: {}, but a newer record is found at {}",
+                        position,
+                        e,
+                        position + 1 + found
+                    );
+                    return Err(anyhow::Error::msg(msg));
+                }
+                break;
+            }
+            Ok(record) => {
+                let offset = Offset {
+                    offset: position + RECORD_HEADER_LEN,
+                    len: record.payload.len(),
+                    lsn: record.seq,
+                };
+                position += record.encode_len();
+                scan.records.push((offset, record));
+                scan.valid_len = position;
+            }
+            Err(e) => {
+                if mode == RecoveryMode::Strict || !is_torn_tail(&rest) {
+                    let msg = format!("wal file is corrupted at position {}: {}", position, e);
+                    return Err(anyhow::Error::msg(msg));
+                }
+                println!(
+                    "wal文件末尾存在不完整的记录，丢弃位置 {} 之后的数据: {}",
+                    position, e
+                );
+                scan.torn = true;
+                break;
+            }
+        }
+    }
+    Ok(scan)
+}
+
+/**
+ * 复用的文件中，查找bytes中第一条记录头校验通过、并且不是旧数据(序列号递增)的记录，返回它的位置
+ * 旧文件中遗留的记录序列号都小于 start_seq，只需要校验记录头；
+ * 先检查序列号和flags，避免对每个位置都计算校验和
+ */
+pub fn find_record(bytes: &[u8], header: &WalFileHeader, last_seq: Option<u64>) -> Option<usize> {
+    if bytes.len() < RECORD_HEADER_LEN {
+        return None;
+    }
+    (0..=bytes.len() - RECORD_HEADER_LEN).find(|&position| {
+        let candidate = &bytes[position..position + RECORD_HEADER_LEN];
+        let seq = u64::from_be_bytes(candidate[8..16].try_into().unwrap_or_default());
+        let flags = candidate[16];
+        flags & !COMPRESSION_MASK == 0
+            && !header.is_stale(seq, last_seq)
+            && RecordHeader::parse(candidate).is_ok()
+    })
+}
+
+/**
+ * 解码失败的记录是否是文件末尾写了一半的记录(之后没有其他数据)，rest 从这条记录开始：
+ *  1、剩余的数据不足一个记录头
+ *  2、记录头校验通过，记录超出了文件末尾，或者记录之后全为0(预分配的空间)
+ *  3、记录头校验失败(len不可信)，记录头之后全为0
+ * 其他情况都是文件中间的损坏，截断会丢失之后的有效记录
+ */
+fn is_torn_tail(rest: &[u8]) -> bool {
+    if rest.len() < RECORD_HEADER_LEN {
+        return true;
+    }
+    match RecordHeader::parse(rest) {
+        Ok(header) => {
+            let end = RECORD_HEADER_LEN.saturating_add(header.len);
+            end > rest.len() || is_zeroed(&rest[end..])
+        }
+        Err(_) => is_zeroed(&rest[RECORD_HEADER_LEN..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{Bytes, BytesMut};
+
+    use crate::wal::{
+        compression::Compression,
+        serialization::{Decoder, Encoder},
+    };
+
+    use super::{
+        scan_records, RecoveryMode, WalFileHeader, WalRecord, FILE_HEADER_LEN, RECORD_HEADER_LEN,
+    };
+
+    fn create_file(num: u64) -> BytesMut {
+        let mut buf = BytesMut::new();
+        WalFileHeader::new(10).encode(&mut buf).unwrap();
+        for seq in 10..10 + num {
+            let record = WalRecord::new(seq, Bytes::from(format!("record-{}", seq)));
+            record.encode(&mut buf).unwrap();
+        }
+        buf
+    }
+
+    #[test]
+    fn wal_record_encode_and_decode() {
+        let record = WalRecord::new(7, Bytes::from_static(b"hello world !"));
+        let mut buf = BytesMut::new();
+        let len = record.encode(&mut buf).unwrap();
+        assert_eq!(len, RECORD_HEADER_LEN + 13);
+        let record1 = WalRecord::decode(buf.freeze()).unwrap();
+        assert_eq!(record, record1);
+    }
+
+    #[test]
+    fn scan_should_return_all_records() {
+        let buf = create_file(5);
+        let scan = scan_records(buf.freeze(), RecoveryMode::Strict).unwrap();
+        assert_eq!(scan.records().len(), 5);
+        assert_eq!(scan.next_seq(), 15);
+        assert!(!scan.is_torn());
+        let (offset, record) = &scan.records()[0];
+        assert_eq!(offset.offset, FILE_HEADER_LEN + RECORD_HEADER_LEN);
+        assert_eq!(offset.len, record.payload.len());
+    }
+
+    #[test]
+    fn scan_should_truncate_torn_tail() {
+        let buf = create_file(3);
+        let full_len = buf.len();
+        let torn = buf.freeze().slice(..full_len - 3);
+        assert!(scan_records(torn.clone(), RecoveryMode::Strict).is_err());
+        let scan = scan_records(torn, RecoveryMode::TruncateTail).unwrap();
+        assert!(scan.is_torn());
+        assert_eq!(scan.records().len(), 2);
+        assert_eq!(scan.valid_len(), full_len - (RECORD_HEADER_LEN + 9));
+    }
+
+    #[test]
+    fn scan_should_stop_at_preallocated_and_recycled_data() {
+        // 预分配的文件，有效数据之后全为0
+        let mut buf = create_file(3);
+        let full_len = buf.len();
+        buf.resize(full_len + 100, 0);
+        let scan = scan_records(buf.clone().freeze(), RecoveryMode::Strict).unwrap();
+        assert_eq!(scan.records().len(), 3);
+        assert!(!scan.is_torn());
+        assert_eq!(scan.valid_len(), full_len);
+        // 最后一条记录只写了一半
+        buf[full_len - 3..full_len].fill(0);
+        assert!(scan_records(buf.clone().freeze(), RecoveryMode::Strict).is_err());
+        let scan = scan_records(buf.freeze(), RecoveryMode::TruncateTail).unwrap();
+        assert!(scan.is_torn());
+        assert_eq!(scan.records().len(), 2);
+
+        // 复用的文件，新记录之后是旧文件中遗留的数据
+        for payload in ["record-20", "new"] {
+            let mut buf = create_file(3);
+            let mut new = BytesMut::new();
+            WalFileHeader::recycled(20).encode(&mut new).unwrap();
+            let record = WalRecord::new(20, Bytes::from(payload));
+            record.encode(&mut new).unwrap();
+            buf[..new.len()].copy_from_slice(&new);
+            let scan = scan_records(buf.freeze(), RecoveryMode::Strict).unwrap();
+            assert_eq!(scan.records().len(), 1);
+            assert!(!scan.is_torn());
+            assert_eq!(scan.valid_len(), new.len());
+            assert_eq!(scan.next_seq(), 21);
+        }
+    }
+
+    #[test]
+    fn scan_should_detect_flipped_bit() {
+        let mut buf = create_file(3);
+        let len = buf.len();
+        // 翻转最后一条记录中的一位，视为torn tail
+        buf[len - 1] ^= 0x01;
+        let scan = scan_records(buf.clone().freeze(), RecoveryMode::TruncateTail).unwrap();
+        assert_eq!(scan.records().len(), 2);
+        // 翻转第一条记录中的一位，文件中间损坏，必须返回错误
+        buf[len - 1] ^= 0x01;
+        buf[FILE_HEADER_LEN + RECORD_HEADER_LEN] ^= 0x01;
+        assert!(scan_records(buf.freeze(), RecoveryMode::TruncateTail).is_err());
+    }
+
+    #[test]
+    fn scan_should_not_truncate_on_corrupted_len() {
+        // 文件中间记录的len被翻转(变大或者变小)，记录头校验失败，不能当作torn tail截断
+        for bit in [0x01, 0x80] {
+            let mut buf = create_file(5);
+            buf[FILE_HEADER_LEN + 3] ^= bit;
+            assert!(scan_records(buf.clone().freeze(), RecoveryMode::TruncateTail).is_err());
+            buf[FILE_HEADER_LEN] ^= bit;
+            buf[FILE_HEADER_LEN + 3] ^= bit;
+            assert!(scan_records(buf.freeze(), RecoveryMode::TruncateTail).is_err());
+        }
+        // 预分配的文件中，最后一条记录的记录头只写了一半
+        let mut buf = create_file(3);
+        let full_len = buf.len();
+        buf.resize(full_len + 100, 0);
+        let start = full_len - (RECORD_HEADER_LEN + 9);
+        buf[start + 10..full_len].fill(0);
+        let scan = scan_records(buf.freeze(), RecoveryMode::TruncateTail).unwrap();
+        assert!(scan.is_torn());
+        assert_eq!(scan.records().len(), 2);
+        assert_eq!(scan.valid_len(), start);
+    }
+
+    #[test]
+    fn scan_should_report_corruption_in_recycled_file() {
+        // 复用的文件：3条新记录覆盖在旧文件的数据之上
+        let mut buf = create_file(10);
+        let mut new = BytesMut::new();
+        WalFileHeader::recycled(20).encode(&mut new).unwrap();
+        for seq in 20..23 {
+            let record = WalRecord::new(seq, Bytes::from(format!("new-record-{}", seq)));
+            record.encode(&mut new).unwrap();
+        }
+        buf[..new.len()].copy_from_slice(&new);
+        let record_len = RECORD_HEADER_LEN + 13;
+        let scan = scan_records(buf.clone().freeze(), RecoveryMode::Strict).unwrap();
+        assert_eq!(scan.records().len(), 3);
+        // 最后一条新记录损坏，之后只有旧数据，视为数据结束
+        let mut last = buf.clone();
+        last[new.len() - 1] ^= 0x01;
+        let scan = scan_records(last.freeze(), RecoveryMode::TruncateTail).unwrap();
+        assert_eq!(scan.records().len(), 2);
+        assert!(!scan.is_torn());
+        // 中间的新记录损坏(payload或者len)，之后还有序列号递增的记录，返回错误
+        for position in [
+            FILE_HEADER_LEN + record_len * 2 - 1,
+            FILE_HEADER_LEN + record_len + 3,
+        ] {
+            let mut middle = buf.clone();
+            middle[position] ^= 0x01;
+            assert!(scan_records(middle.freeze(), RecoveryMode::TruncateTail).is_err());
+        }
+    }
+
+    #[test]
+    fn scan_should_read_mixed_compressed_records() {
+        let mut buf = BytesMut::new();
+        WalFileHeader::new(0).encode(&mut buf).unwrap();
+        let payload = Bytes::from("telemetry ".repeat(64));
+        let compressions = [Compression::None, Compression::Lz4, Compression::Zstd(3)];
+        for (seq, compression) in compressions.iter().enumerate() {
+            let record =
+                WalRecord::with_compression(seq as u64, payload.clone(), *compression).unwrap();
+            assert_eq!(record.flags(), compression.flag());
+            record.encode(&mut buf).unwrap();
+        }
+        let scan = scan_records(buf.freeze(), RecoveryMode::Strict).unwrap();
+        assert_eq!(scan.records().len(), 3);
+        for (offset, record) in scan.records() {
+            assert_eq!(offset.len, record.payload().len());
+            assert_eq!(record.data().unwrap(), payload);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use bytes::Bytes;
+use tokio::io::AsyncReadExt;
+
+use crate::utils::file_utils::get_wal_files_name;
+
+use super::{
+    record::{WalFileHeader, FILE_HEADER_LEN},
+    serialization::Decoder,
+};
+
+/**
+ * 已经被checkpoint覆盖的wal文件的保留策略，用于按时间点恢复
+ *  1、保留范围内的wal文件留在wal目录中
+ *  2、超出范围的wal文件移动到归档目录，没有设置归档目录时直接删除
+ *  3、max_age 和 max_size 都没有设置时，不保留任何已经被覆盖的wal文件
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WalRetention {
+    // 最多保留的时间
+    pub(crate) max_age: Option<Duration>,
+    // 最多保留的总大小
+    pub(crate) max_size: Option<usize>,
+    // 归档目录
+    pub(crate) archive_path: Option<String>,
+}
+
+impl WalRetention {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    pub fn with_archive_path(mut self, archive_path: impl Into<String>) -> Self {
+        self.archive_path = Some(archive_path.into());
+        self
+    }
+
+    /**
+     * 从已经被checkpoint覆盖的wal文件(按创建顺序排列)中挑选出超出保留范围的文件
+     */
+    pub fn expired(&self, obsolete: &[Segment], now: SystemTime) -> Vec<Segment> {
+        if self.max_age.is_none() && self.max_size.is_none() {
+            return obsolete.to_vec();
+        }
+        let mut total_size = 0;
+        // 从最新的文件开始，找到第一个超出范围的文件，比它更早的文件也都超出了范围
+        let position = obsolete.iter().rposition(|segment| {
+            total_size += segment.size;
+            let age = now.duration_since(segment.modified).unwrap_or_default();
+            let too_old = self.max_age.is_some_and(|max_age| age > max_age);
+            let too_large = self.max_size.is_some_and(|max_size| total_size > max_size);
+            too_old || too_large
+        });
+        match position {
+            Some(position) => obsolete[..=position].to_vec(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/**
+ * wal目录中的一个wal文件
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub(crate) name: String,
+    pub(crate) path: String,
+    // 文件中第一条记录的序列号
+    pub(crate) start_seq: u64,
+    pub(crate) size: usize,
+    pub(crate) modified: SystemTime,
+}
+
+/**
+ * 按创建顺序列出wal目录中的所有wal文件，只读取文件头
+ */
+pub async fn list_segments(path: &str) -> Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+    for name in get_wal_files_name(path).await? {
+        let file_path = format!("{}/{}", path.trim_end_matches('/'), name);
+        let mut file = tokio::fs::File::open(&file_path).await?;
+        let metadata = file.metadata().await?;
+        let mut buf = vec![0; FILE_HEADER_LEN];
+        // 文件头不完整的文件是还没有写入数据的新文件
+        let start_seq = match file.read_exact(&mut buf).await {
+            Ok(_) => WalFileHeader::decode(Bytes::from(buf))?.start_seq,
+            Err(_) => continue,
+        };
+        segments.push(Segment {
+            name,
+            path: file_path,
+            start_seq,
+            size: metadata.len() as usize,
+            modified: metadata.modified()?,
+        });
+    }
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{Segment, WalRetention};
+
+    fn create_segments(now: SystemTime) -> Vec<Segment> {
+        (0..5)
+            .map(|i| Segment {
+                name: format!("{}.wal", i),
+                path: format!("/tmp/{}.wal", i),
+                start_seq: i * 10,
+                size: 100,
+                modified: now - Duration::from_secs((5 - i) * 60),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn retention_expired_should_be_work() {
+        let now = SystemTime::now();
+        let segments = create_segments(now);
+        // 不保留
+        assert_eq!(WalRetention::new().expired(&segments, now).len(), 5);
+        // 按时间保留：3分钟以内的文件(3.wal、4.wal)
+        let retention = WalRetention::new().with_max_age(Duration::from_secs(150));
+        let expired = retention.expired(&segments, now);
+        assert_eq!(expired, segments[..3].to_vec());
+        // 按大小保留：最多保留250字节(3.wal、4.wal)
+        let retention = WalRetention::new().with_max_size(250);
+        assert_eq!(retention.expired(&segments, now), segments[..3].to_vec());
+        // 保留全部
+        let retention = WalRetention::new().with_max_size(1000);
+        assert!(retention.expired(&segments, now).is_empty());
+    }
+}
@@ -0,0 +1,205 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use bytes::Bytes;
+use memmap2::Mmap;
+
+use super::{
+    offset::Offset,
+    record::{WalRecord, FILE_HEADER_LEN, RECORD_HEADER_LEN},
+    serialization::Decoder,
+    wal_msg::WalMsg,
+};
+
+/**
+ * 已经写满的wal文件，文件不会再被修改，通过mmap读取：
+ *  1、读取时不需要加锁，可以和append并发执行
+ *  2、读取到的记录是mmap的Bytes切片，没有拷贝(压缩的记录解压时才会拷贝)
+ *  3、SealedWal 可以clone给其他任务(复制、恢复)使用，所有clone共享同一个mmap
+ *
+ * 每个clone和读取到的Bytes都持有mmap的引用计数，复用wal文件会原地覆盖文件的内容，
+ * 所以只有没有任何引用(is_shared() == false)的wal文件才能进入复用池，否则只删除文件
+ */
+#[derive(Clone)]
+pub struct SealedWal {
+    name: String,
+    // 长度为0的文件不能mmap
+    mmap: Option<Arc<Mmap>>,
+}
+
+/**
+ * Bytes的owner，持有mmap的引用计数
+ */
+struct MmapOwner(Arc<Mmap>);
+
+impl AsRef<[u8]> for MmapOwner {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+// 不打印mmap中的数据
+impl std::fmt::Debug for SealedWal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SealedWal")
+            .field("name", &self.name)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl SealedWal {
+    pub fn open(path: &str) -> Result<Self> {
+        let name = path.rsplit('/').next().unwrap_or_default().to_string();
+        let file = std::fs::File::open(path)?;
+        if file.metadata()?.len() == 0 {
+            return Ok(Self { name, mmap: None });
+        }
+        // 写满的wal文件只读，不会被截断；只有mmap没有任何引用之后才会被复用(覆盖)
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(Self {
+            name,
+            mmap: Some(Arc::new(mmap)),
+        })
+    }
+
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /**
+     * 整个wal文件的内容，返回的Bytes(包括它的切片)持有mmap的引用
+     */
+    pub fn data(&self) -> Bytes {
+        match &self.mmap {
+            Some(mmap) => Bytes::from_owner(MmapOwner(mmap.clone())),
+            None => Bytes::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /**
+     * 除了这个SealedWal之外，是否还有其他的clone或者读取到的Bytes在使用这个mmap，
+     * 还在使用时wal文件不能被复用
+     */
+    pub fn is_shared(&self) -> bool {
+        self.mmap
+            .as_ref()
+            .is_some_and(|mmap| Arc::strong_count(mmap) > 1)
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match &self.mmap {
+            Some(mmap) => mmap.as_ref(),
+            None => &[],
+        }
+    }
+
+    /**
+     * 根据位置读取一条记录，这个offset是记录头(RecordHeader + WalMsg)的起始位置
+     */
+    pub fn read_record_at(&self, offset: usize) -> Result<WalRecord> {
+        if offset < FILE_HEADER_LEN || offset + RECORD_HEADER_LEN > self.len() {
+            let msg = format!("invalid wal record position: {}", offset);
+            return Err(anyhow::Error::msg(msg));
+        }
+        let header = &self.bytes()[offset..offset + 4];
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        self.slice_record(offset, len)
+    }
+
+    /**
+     * 读取一条记录，这个offset是WalMsg(payload)的偏移量，会同时校验记录头中的crc
+     */
+    pub fn read_record(&self, offset: &Offset) -> Result<WalRecord> {
+        if offset.offset < FILE_HEADER_LEN + RECORD_HEADER_LEN {
+            let msg = format!("invalid wal offset: {:?}", offset);
+            return Err(anyhow::Error::msg(msg));
+        }
+        self.slice_record(offset.offset - RECORD_HEADER_LEN, offset.len)
+    }
+
+    fn slice_record(&self, start: usize, len: usize) -> Result<WalRecord> {
+        let end = start + RECORD_HEADER_LEN + len;
+        if end > self.len() {
+            let msg = format!(
+                "wal record at {} exceeds wal file 【{}】 of {} bytes",
+                start,
+                self.name,
+                self.len()
+            );
+            return Err(anyhow::Error::msg(msg));
+        }
+        WalRecord::decode(self.data().slice(start..end))
+    }
+
+    pub fn read_with_offset(&self, offset: usize) -> Result<WalMsg> {
+        WalMsg::decode(self.read_record_at(offset)?.data()?)
+    }
+
+    pub fn read_with_index(&self, offset: Offset) -> Result<WalMsg> {
+        WalMsg::decode(self.read_record(&offset)?.data()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_flight::FlightData;
+
+    use crate::{
+        utils::time_utils::now,
+        wal::{
+            active_wal::ActiveWal,
+            record::{FILE_HEADER_LEN, RECORD_HEADER_LEN},
+            wal_msg::WalMsg,
+            Append,
+        },
+    };
+
+    use super::SealedWal;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn sealed_wal_should_read_without_copy() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-sealed-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let mut wal = ActiveWal::with_size(&path, 1024 * 1024).await.unwrap();
+        let mut offsets = Vec::new();
+        for i in 0..3 {
+            let fds = vec![FlightData::new().with_data_body(vec![i as u8; 32])];
+            offsets.push((wal.append(fds.clone()).await.unwrap(), WalMsg::from(fds)));
+        }
+        wal.sync().await.unwrap();
+        let sealed = SealedWal::open(&format!("{}/{}", path, wal.name())).unwrap();
+        for (offset, expect) in offsets {
+            assert_eq!(sealed.read_with_index(offset.clone()).unwrap(), expect);
+            let position = offset.offset - RECORD_HEADER_LEN;
+            assert_eq!(sealed.read_with_offset(position).unwrap(), expect);
+            // 记录中的payload直接指向mmap
+            let record = sealed.read_record(&offset).unwrap();
+            let data = sealed.data();
+            let start = data.as_ptr() as usize;
+            let payload = record.payload();
+            let ptr = payload.as_ptr() as usize;
+            assert!(ptr >= start && ptr + payload.len() <= start + data.len());
+        }
+        assert!(sealed.read_with_offset(sealed.len()).is_err());
+        // 读取到的记录和clone都持有mmap的引用
+        assert!(!sealed.is_shared());
+        let record = sealed.read_record_at(FILE_HEADER_LEN).unwrap();
+        assert!(sealed.is_shared());
+        drop(record);
+        let cloned = sealed.clone();
+        assert!(sealed.is_shared());
+        drop(cloned);
+        assert!(!sealed.is_shared());
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+}
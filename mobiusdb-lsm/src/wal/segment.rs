@@ -0,0 +1,109 @@
+use anyhow::Result;
+use tokio::fs::File;
+
+use crate::utils::file_utils::{file_id, get_wal_files_name, WAL_FILE_SUFFIX};
+
+/**
+ * wal文件(segment)的分配策略
+ *  preallocate: 新建wal文件时预先分配 wal_max_size 大小的磁盘空间(fallocate)，
+ *               写入时不需要扩展文件，避免每次写入都更新文件的元数据
+ *  recycle: 最多保留多少个已经被checkpoint覆盖的wal文件用于复用，
+ *           切换wal文件时优先复用这些文件，0表示不复用；设置了归档目录时不复用
+ *
+ * 预分配和复用的wal文件中，有效数据之后是全0或者旧的数据，读取时遇到它们表示数据结束
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WalSegment {
+    pub(crate) preallocate: bool,
+    pub(crate) recycle: usize,
+}
+
+impl WalSegment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_preallocate(mut self, preallocate: bool) -> Self {
+        self.preallocate = preallocate;
+        self
+    }
+
+    pub fn with_recycle(mut self, recycle: usize) -> Self {
+        self.recycle = recycle;
+        self
+    }
+}
+
+/**
+ * wal文件的名称，segment id 单调递增，补齐到20位保证按文件名排序和按编号排序一致
+ */
+pub fn segment_file_name(segment_id: u64) -> String {
+    format!("{:020}{}", segment_id, WAL_FILE_SUFFIX)
+}
+
+/**
+ * 目录中下一个wal文件的 segment id
+ */
+pub async fn next_segment_id(path: &str) -> Result<u64> {
+    let max_id = get_wal_files_name(path)
+        .await?
+        .iter()
+        .filter_map(|name| file_id(name, WAL_FILE_SUFFIX))
+        .max();
+    Ok(max_id.map_or(0, |id| id + 1))
+}
+
+/**
+ * 为文件分配len大小的磁盘空间，新分配的空间读取时全为0
+ */
+pub async fn preallocate(file: &File, len: usize) -> Result<()> {
+    if file.metadata().await?.len() >= len as u64 {
+        return Ok(());
+    }
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::AsRawFd;
+        let ret = unsafe { libc::fallocate(file.as_raw_fd(), 0, 0, len as libc::off_t) };
+        if ret == 0 {
+            return Ok(());
+        }
+        // 文件系统不支持fallocate时退化为set_len
+        println!(
+            "fallocate失败，使用set_len预分配wal文件: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+    file.set_len(len as u64).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::time_utils::now;
+
+    use super::{next_segment_id, preallocate, segment_file_name};
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn segment_name_and_preallocate() {
+        let path = std::env::temp_dir().join(format!("mobiusdb-segment-{}", now()));
+        tokio::fs::create_dir_all(&path).await.unwrap();
+        let path = path.to_str().unwrap().to_string();
+        assert_eq!(next_segment_id(&path).await.unwrap(), 0);
+        assert_eq!(segment_file_name(12), "00000000000000000012.wal");
+        // 旧版本按创建时间命名的wal文件
+        let legacy = format!("{}/{}.wal", path, now());
+        tokio::fs::write(&legacy, b"").await.unwrap();
+        let next = next_segment_id(&path).await.unwrap();
+        let name = segment_file_name(next);
+        let file = tokio::fs::File::create(format!("{}/{}", path, name))
+            .await
+            .unwrap();
+        preallocate(&file, 4096).await.unwrap();
+        assert_eq!(file.metadata().await.unwrap().len(), 4096);
+        let names = crate::utils::file_utils::get_wal_files_name(&path)
+            .await
+            .unwrap();
+        assert_eq!(names.last(), Some(&name));
+        let _ = tokio::fs::remove_dir_all(&path).await;
+    }
+}
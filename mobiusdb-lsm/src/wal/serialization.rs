@@ -2,6 +2,9 @@ use super::offset::Offset;
 use anyhow::{Ok, Result};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 
+// 每个Offset编码后的长度: | offset(8) | len(8) | lsn(8) |
+pub const OFFSET_ENCODE_LEN: usize = 24;
+
 pub trait Encoder: Sync + Send + 'static {
     type Error;
     fn encode(&self, buffer: &mut BytesMut) -> Result<usize, Self::Error>;
@@ -23,7 +26,8 @@ impl Encoder for Offset {
     fn encode(&self, buffer: &mut BytesMut) -> Result<usize, Self::Error> {
         buffer.put_u64(self.offset as u64);
         buffer.put_u64(self.len as u64);
-        Ok(16)
+        buffer.put_u64(self.lsn);
+        Ok(OFFSET_ENCODE_LEN)
     }
 }
 
@@ -32,12 +36,13 @@ impl Decoder for Offset {
 
     fn decode(mut bytes: Bytes) -> Result<Self, Self::Error> {
         let len = bytes.len();
-        if len != 16 {
+        if len != OFFSET_ENCODE_LEN {
             return Err(anyhow::anyhow!("invalid offset length: {}", len));
         }
         let offset = bytes.get_u64() as usize;
         let len = bytes.get_u64() as usize;
-        Ok(Offset { offset, len })
+        let lsn = bytes.get_u64();
+        Ok(Offset { offset, len, lsn })
     }
 }
 
@@ -53,7 +58,7 @@ impl Encoder for Vec<Offset> {
 
         buffer.put_u32(len as u32);
         buffer.put(buf);
-        Ok(len * 16 + 4)
+        Ok(len * OFFSET_ENCODE_LEN + 4)
     }
 }
 
@@ -61,10 +66,20 @@ impl Decoder for Vec<Offset> {
     type Error = anyhow::Error;
 
     fn decode(mut bytes: Bytes) -> Result<Self, Self::Error> {
-        let vec_len = bytes.get_u32();
-        let mut vec = Vec::with_capacity(vec_len as usize);
-        while bytes.len() > 0 {
-            let a = bytes.split_to(16);
+        if bytes.len() < 4 {
+            return Err(anyhow::anyhow!("invalid offsets length: {}", bytes.len()));
+        }
+        let vec_len = bytes.get_u32() as usize;
+        if bytes.len() != vec_len * OFFSET_ENCODE_LEN {
+            return Err(anyhow::anyhow!(
+                "invalid offsets length: expect {} offsets but got {} bytes",
+                vec_len,
+                bytes.len()
+            ));
+        }
+        let mut vec = Vec::with_capacity(vec_len);
+        while !bytes.is_empty() {
+            let a = bytes.split_to(OFFSET_ENCODE_LEN);
             let offset = Offset::decode(a)?;
             vec.push(offset);
         }
@@ -85,6 +100,7 @@ mod tests {
         let offset = Offset {
             offset: 1234,
             len: 5678,
+            lsn: 42,
         };
 
         let mut buf = BytesMut::new();
@@ -92,6 +108,7 @@ mod tests {
         println!("len: {}", len);
         let new_offset = Offset::decode(buf.freeze()).unwrap();
         println!("offset: {:?}", new_offset);
+        assert_eq!(offset, new_offset);
     }
 
     #[test]
@@ -101,6 +118,7 @@ mod tests {
             let offset = Offset {
                 offset: i,
                 len: 5678,
+                lsn: i as u64,
             };
             offsets.push(offset);
         }
@@ -108,7 +126,11 @@ mod tests {
         let mut buf = BytesMut::new();
         let len = offsets.encode(&mut buf).unwrap();
         println!("len: {}", len);
-        let new_offsets = Vec::<Offset>::decode(buf.freeze()).unwrap();
+        let buf = buf.freeze();
+        let new_offsets = Vec::<Offset>::decode(buf.clone()).unwrap();
         println!("offsets: {:?}", new_offsets);
+        assert_eq!(offsets, new_offsets);
+        // 数据不完整时返回错误
+        assert!(Vec::<Offset>::decode(buf.slice(..buf.len() - 1)).is_err());
     }
 }
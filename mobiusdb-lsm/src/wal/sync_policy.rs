@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+/**
+ * wal的落盘(fsync)策略，决定 LsmCommand::Append 的返回值代表什么级别的持久化
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyncPolicy {
+    // 每次写入之后都执行fsync，返回true时数据一定已经落盘
+    #[default]
+    Always,
+    // 组提交：把并发的写入(最多max_batch条)合并成一次fsync，fsync完成之后才返回
+    GroupCommit {
+        max_batch: usize,
+    },
+    // 定时fsync：写入之后立即返回，最多丢失interval时间内的数据
+    Interval(Duration),
+}
+
+impl SyncPolicy {
+    /**
+     * 返回之前是否需要执行fsync
+     */
+    pub fn sync_on_commit(&self) -> bool {
+        !matches!(self, SyncPolicy::Interval(_))
+    }
+
+    /**
+     * 一次提交最多包含的写入条数
+     */
+    pub fn max_batch(&self) -> usize {
+        match self {
+            SyncPolicy::GroupCommit { max_batch } => (*max_batch).max(1),
+            _ => 1,
+        }
+    }
+
+    pub fn interval(&self) -> Option<Duration> {
+        match self {
+            SyncPolicy::Interval(interval) => Some(*interval),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::SyncPolicy;
+
+    #[test]
+    fn sync_policy_should_be_work() {
+        assert!(SyncPolicy::default().sync_on_commit());
+        assert_eq!(SyncPolicy::Always.max_batch(), 1);
+        let group = SyncPolicy::GroupCommit { max_batch: 0 };
+        assert!(group.sync_on_commit());
+        assert_eq!(group.max_batch(), 1);
+        let interval = SyncPolicy::Interval(Duration::from_millis(10));
+        assert!(!interval.sync_on_commit());
+        assert_eq!(interval.interval(), Some(Duration::from_millis(10)));
+    }
+}
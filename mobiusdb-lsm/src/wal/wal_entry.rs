@@ -0,0 +1,220 @@
+use anyhow::Result;
+use arrow::{datatypes::Schema, datatypes::SchemaRef, ipc::writer::IpcWriteOptions};
+use arrow_flight::{FlightData, SchemaAsIpc};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use prost::Message;
+
+use super::{
+    offset::Lsn,
+    wal_msg::{IntoWalMsg, WalMsg},
+};
+
+/**
+ * wal中数据的类型，写入在每条WalMsg的第一个字节
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EntryKind {
+    #[default]
+    InsertBatch = 1,
+    DeleteRange = 2,
+    SchemaChange = 3,
+    DropTable = 4,
+    Checkpoint = 5,
+}
+
+impl TryFrom<u8> for EntryKind {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(EntryKind::InsertBatch),
+            2 => Ok(EntryKind::DeleteRange),
+            3 => Ok(EntryKind::SchemaChange),
+            4 => Ok(EntryKind::DropTable),
+            5 => Ok(EntryKind::Checkpoint),
+            _ => Err(anyhow::Error::msg(format!(
+                "unknown wal entry kind: {}",
+                value
+            ))),
+        }
+    }
+}
+
+/**
+ * wal中记录的操作，除了写入数据之外的修改也需要先写入wal，才能在重启之后重放
+ *  InsertBatch: 写入数据
+ *  DeleteRange: 删除表中 [start, end) 时间范围内的数据
+ *  SchemaChange: 修改表结构
+ *  DropTable: 删除表
+ *  Checkpoint: lsn之前的数据都已经写入到sstable
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum WalEntry {
+    InsertBatch(Vec<FlightData>),
+    DeleteRange { table: String, start: u64, end: u64 },
+    SchemaChange { table: String, schema: SchemaRef },
+    DropTable { table: String },
+    Checkpoint { lsn: Lsn },
+}
+
+impl WalEntry {
+    pub fn kind(&self) -> EntryKind {
+        match self {
+            WalEntry::InsertBatch(_) => EntryKind::InsertBatch,
+            WalEntry::DeleteRange { .. } => EntryKind::DeleteRange,
+            WalEntry::SchemaChange { .. } => EntryKind::SchemaChange,
+            WalEntry::DropTable { .. } => EntryKind::DropTable,
+            WalEntry::Checkpoint { .. } => EntryKind::Checkpoint,
+        }
+    }
+
+    /**
+     * 将WalMsg还原为WalEntry，是 IntoWalMsg 的逆过程
+     */
+    pub fn from_wal_msg(wal_msg: &WalMsg) -> Result<Self> {
+        let mut bytes = wal_msg.bytes();
+        let entry = match wal_msg.kind() {
+            EntryKind::InsertBatch => WalEntry::InsertBatch(wal_msg.to_messages::<FlightData>()?),
+            EntryKind::DeleteRange => {
+                let table = get_string(&mut bytes)?;
+                check_len(&bytes, 16)?;
+                let start = bytes.get_u64();
+                let end = bytes.get_u64();
+                WalEntry::DeleteRange { table, start, end }
+            }
+            EntryKind::SchemaChange => {
+                let table = get_string(&mut bytes)?;
+                let fd = FlightData::decode(bytes)?;
+                let schema = Schema::try_from(&fd)?;
+                WalEntry::SchemaChange {
+                    table,
+                    schema: SchemaRef::new(schema),
+                }
+            }
+            EntryKind::DropTable => WalEntry::DropTable {
+                table: get_string(&mut bytes)?,
+            },
+            EntryKind::Checkpoint => {
+                check_len(&bytes, 8)?;
+                WalEntry::Checkpoint {
+                    lsn: bytes.get_u64(),
+                }
+            }
+        };
+        Ok(entry)
+    }
+}
+
+impl IntoWalMsg for WalEntry {
+    fn to_wal_msg(&self) -> WalMsg {
+        let mut buf = BytesMut::new();
+        match self {
+            WalEntry::InsertBatch(fds) => return WalMsg::from(fds),
+            WalEntry::DeleteRange { table, start, end } => {
+                put_string(&mut buf, table);
+                buf.put_u64(*start);
+                buf.put_u64(*end);
+            }
+            WalEntry::SchemaChange { table, schema } => {
+                put_string(&mut buf, table);
+                let fd: FlightData = SchemaAsIpc::new(schema, &IpcWriteOptions::default()).into();
+                let _ = fd.encode(&mut buf);
+            }
+            WalEntry::DropTable { table } => put_string(&mut buf, table),
+            WalEntry::Checkpoint { lsn } => buf.put_u64(*lsn),
+        }
+        WalMsg::with_kind(self.kind(), buf.freeze())
+    }
+
+    fn table_name(&self) -> Option<String> {
+        match self {
+            WalEntry::InsertBatch(fds) => fds.table_name(),
+            WalEntry::DeleteRange { table, .. }
+            | WalEntry::SchemaChange { table, .. }
+            | WalEntry::DropTable { table } => Some(table.clone()),
+            WalEntry::Checkpoint { .. } => None,
+        }
+    }
+}
+
+fn put_string(buf: &mut BytesMut, s: &str) {
+    buf.put_u16(s.len() as u16);
+    buf.put_slice(s.as_bytes());
+}
+
+fn get_string(bytes: &mut Bytes) -> Result<String> {
+    check_len(bytes, 2)?;
+    let len = bytes.get_u16() as usize;
+    check_len(bytes, len)?;
+    let s = String::from_utf8(bytes.split_to(len).to_vec())?;
+    Ok(s)
+}
+
+fn check_len(bytes: &Bytes, len: usize) -> Result<()> {
+    if bytes.len() < len {
+        let msg = format!(
+            "wal entry is broken, need {} bytes but {} left",
+            len,
+            bytes.len()
+        );
+        return Err(anyhow::Error::msg(msg));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use arrow::{
+        array::{Int32Array, RecordBatch},
+        datatypes::{DataType, Field, Schema},
+    };
+    use arrow_flight::utils::batches_to_flight_data;
+    use bytes::BytesMut;
+
+    use crate::{
+        wal::wal_msg::{IntoWalMsg, WalMsg},
+        TABLE_NAME,
+    };
+
+    use super::{EntryKind, WalEntry};
+
+    #[test]
+    fn wal_entry_encode_and_decode() {
+        let schema = Arc::new(
+            Schema::new(vec![Field::new("age", DataType::Int32, true)]).with_metadata(
+                HashMap::from([(TABLE_NAME.to_string(), "class".to_string())]),
+            ),
+        );
+        let batch =
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(vec![1, 2]))])
+                .unwrap();
+        let fds = batches_to_flight_data(&schema, vec![batch]).unwrap();
+        let entries = vec![
+            WalEntry::InsertBatch(fds),
+            WalEntry::DeleteRange {
+                table: "class".to_string(),
+                start: 10,
+                end: 20,
+            },
+            WalEntry::SchemaChange {
+                table: "class".to_string(),
+                schema,
+            },
+            WalEntry::DropTable {
+                table: "class".to_string(),
+            },
+            WalEntry::Checkpoint { lsn: 42 },
+        ];
+        for entry in entries {
+            let mut buf = BytesMut::new();
+            entry.to_wal_msg().encode(&mut buf);
+            let wal_msg = WalMsg::decode(buf.freeze()).unwrap();
+            assert_eq!(wal_msg.kind(), entry.kind());
+            assert_eq!(WalEntry::from_wal_msg(&wal_msg).unwrap(), entry);
+        }
+        assert!(EntryKind::try_from(0).is_err());
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+
+/**
+ * wal的组织方式
+ *  Shared: 所有表写入同一个wal
+ *  PerTable: 每个表(TABLE_NAME 对应的前缀)写入独立的wal，可以单独清理、单独恢复，
+ *            wal_sizes 中可以为每个表设置单独的wal文件大小，没有设置的表使用默认大小
+ *
+ * PerTable 模式下，LSN 仍然在所有表之间全局单调递增
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum WalMode {
+    #[default]
+    Shared,
+    PerTable {
+        wal_sizes: HashMap<String, usize>,
+    },
+}
+
+impl WalMode {
+    pub fn per_table() -> Self {
+        WalMode::PerTable {
+            wal_sizes: HashMap::new(),
+        }
+    }
+
+    /**
+     * 设置指定表的wal文件大小，只对 PerTable 模式有效
+     */
+    pub fn with_table_wal_size(mut self, prefix: impl Into<String>, wal_size: usize) -> Self {
+        if let WalMode::PerTable { wal_sizes } = &mut self {
+            wal_sizes.insert(prefix.into(), wal_size);
+        }
+        self
+    }
+
+    pub fn is_per_table(&self) -> bool {
+        matches!(self, WalMode::PerTable { .. })
+    }
+
+    pub fn wal_size(&self, prefix: &str) -> Option<usize> {
+        match self {
+            WalMode::Shared => None,
+            WalMode::PerTable { wal_sizes } => wal_sizes.get(prefix).copied(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WalMode;
+
+    #[test]
+    fn wal_mode_table_wal_size() {
+        let mode = WalMode::per_table().with_table_wal_size("cpu", 1024);
+        assert!(mode.is_per_table());
+        assert_eq!(mode.wal_size("cpu"), Some(1024));
+        assert_eq!(mode.wal_size("mem"), None);
+        let mode = WalMode::Shared.with_table_wal_size("cpu", 1024);
+        assert_eq!(mode, WalMode::Shared);
+    }
+}
@@ -1,20 +1,48 @@
+use arrow::datatypes::Schema;
 use arrow_flight::FlightData;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 
-use super::{offset::Offset, serialization::Decoder};
+use crate::TABLE_NAME;
+
+use super::{
+    record::{scan_records, RecoveryMode},
+    serialization::Decoder,
+    wal_entry::EntryKind,
+};
 
 /**
- * todo:
- *  1、WalMsg结构需要优化一下，现在的结构和Vec<FlightData>耦合性太高了
+ * wal中一条记录的payload：
+ *
+ *  | kind(1) | num(2) | len(4) * num | bytes |
+ *
+ *  kind 表示这条数据对应的WalEntry类型，bytes 由 num 段数据组成，
+ *  每一段的长度记录在 indexs 中，具体的含义由 kind 决定
  */
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct WalMsg {
+    kind: EntryKind,
     num: u16,
     indexs: Vec<u32>,
     bytes: Bytes,
 }
 
 impl WalMsg {
+    /**
+     * 由一段bytes构建指定类型的WalMsg
+     */
+    pub fn with_kind(kind: EntryKind, bytes: Bytes) -> Self {
+        Self {
+            kind,
+            num: 1,
+            indexs: vec![bytes.len() as u32],
+            bytes,
+        }
+    }
+
+    pub fn kind(&self) -> EntryKind {
+        self.kind
+    }
+
     pub fn indexs(&self) -> &Vec<u32> {
         &self.indexs
     }
@@ -24,10 +52,31 @@ impl WalMsg {
     }
 
     pub fn encode_len(&self) -> usize {
-        let mut n = (self.num * 4) as usize;
+        let mut n = 3 + (self.num as usize) * 4;
         n += self.bytes.len();
         n
     }
+
+    /**
+     * 将WalMsg还原为Vec<T>，是 From<Vec<T>> 的逆过程
+     */
+    pub fn to_messages<T: ::prost::Message + Default>(&self) -> anyhow::Result<Vec<T>> {
+        let mut bytes = self.bytes.clone();
+        let mut resp = Vec::with_capacity(self.num as usize);
+        for len in &self.indexs {
+            let len = *len as usize;
+            if bytes.len() < len {
+                let msg = format!(
+                    "wal msg is broken, need {} bytes but {} left",
+                    len,
+                    bytes.len()
+                );
+                return Err(anyhow::Error::msg(msg));
+            }
+            resp.push(T::decode(bytes.split_to(len))?);
+        }
+        Ok(resp)
+    }
 }
 
 impl<T: ::prost::Message> From<Vec<T>> for WalMsg {
@@ -43,6 +92,7 @@ impl<T: ::prost::Message> From<Vec<T>> for WalMsg {
             indexs.push(len as u32);
         });
         Self {
+            kind: EntryKind::InsertBatch,
             num,
             indexs,
             bytes: data_buf.freeze(),
@@ -63,6 +113,7 @@ impl<T: ::prost::Message> From<&Vec<T>> for WalMsg {
             indexs.push(len as u32);
         });
         Self {
+            kind: EntryKind::InsertBatch,
             num,
             indexs,
             bytes: data_buf.freeze(),
@@ -73,6 +124,7 @@ impl<T: ::prost::Message> From<&Vec<T>> for WalMsg {
 impl WalMsg {
     pub fn encode(&self, buf: &mut BytesMut) {
         let indexs = self.indexs.clone();
+        buf.put_u8(self.kind as u8);
         buf.put_u16(self.num);
         for index in indexs {
             buf.put_u32(index);
@@ -80,70 +132,76 @@ impl WalMsg {
         buf.put(self.bytes.clone());
     }
 
-    pub fn decode(mut buf: Bytes) -> Self {
+    pub fn decode(mut buf: Bytes) -> anyhow::Result<Self> {
+        if buf.len() < 3 {
+            let msg = format!(
+                "wal msg is broken, header needs 3 bytes but got {}",
+                buf.len()
+            );
+            return Err(anyhow::Error::msg(msg));
+        }
+        let kind = EntryKind::try_from(buf.get_u8())?;
         let num = buf.get_u16();
+        if buf.len() < num as usize * 4 {
+            let msg = format!("wal msg is broken, indexs need {} bytes", num as usize * 4);
+            return Err(anyhow::Error::msg(msg));
+        }
         let mut indexs = Vec::new();
         for _ in 0..num {
             let index = buf.get_u32();
             indexs.push(index);
         }
-        Self {
+        Ok(Self {
+            kind,
             num,
             indexs,
             bytes: buf,
-        }
+        })
     }
 }
 
+/**
+ * 解码一个完整的wal文件(文件头 + 记录)，任何损坏都会返回错误
+ */
 impl Decoder for Vec<WalMsg> {
     type Error = anyhow::Error;
 
-    fn decode(mut bytes: Bytes) -> anyhow::Result<Self, Self::Error> {
-        println!("start decode bytes len = {}", bytes.len());
-        let mut wal_msgs = Vec::new();
-        while bytes.len() > 4 {
-            let wal_msg_len = bytes.get_u32();
-            println!(
-                "本次循环：wal_msg_len = {}，bytes len = {}",
-                wal_msg_len,
-                bytes.len()
-            );
-            if bytes.len() < wal_msg_len as usize {
-                println!("bytes len = {},wal_msg_len = {}", bytes.len(), wal_msg_len);
-                return Err(anyhow::Error::msg("1234"));
-            }
-            let buf = bytes.split_to(wal_msg_len as usize);
-            println!("after split off bytes len = {}", bytes.len());
-            let wal_msg = WalMsg::decode(buf);
-            wal_msgs.push(wal_msg);
-        }
-        Ok(wal_msgs)
+    fn decode(bytes: Bytes) -> anyhow::Result<Self, Self::Error> {
+        let scan = scan_records(bytes, RecoveryMode::Strict)?;
+        scan.records()
+            .iter()
+            .map(|(_, record)| WalMsg::decode(record.data()?))
+            .collect()
     }
 }
 
-pub fn walmsgs_to_offsets(walmsgs: &Vec<WalMsg>) -> Vec<Offset> {
-    let mut offsets = Vec::new();
-    let mut offset = 0;
-    for wal_msg in walmsgs {
-        let wal_msg_len = wal_msg.encode_len();
-        let offset_obj = Offset {
-            offset,
-            len: wal_msg_len,
-        };
-        offsets.push(offset_obj);
-        offset += wal_msg_len;
-    }
-    offsets
-}
-
+/**
+ * 所有可以写入wal的数据都需要实现这个trait，
+ * 新的数据类型(WalEntry)通过它转换为带有类型标记的WalMsg
+ */
 pub trait IntoWalMsg {
-    fn into_wal_msg(&self) -> WalMsg;
+    fn to_wal_msg(&self) -> WalMsg;
+
+    /**
+     * 数据所属的表(TABLE_NAME 对应的前缀)，PerTable 模式下用于选择写入哪个wal
+     */
+    fn table_name(&self) -> Option<String> {
+        None
+    }
 }
 
 impl IntoWalMsg for Vec<FlightData> {
-    fn into_wal_msg(&self) -> WalMsg {
+    fn to_wal_msg(&self) -> WalMsg {
         WalMsg::from(self)
     }
+
+    /**
+     * 第一个FlightData是schema，表名在schema的metadata中
+     */
+    fn table_name(&self) -> Option<String> {
+        let schema = Schema::try_from(self.first()?).ok()?;
+        schema.metadata().get(TABLE_NAME).cloned()
+    }
 }
 
 #[cfg(test)]
@@ -155,9 +213,13 @@ mod tests {
         datatypes::*,
     };
     use arrow_flight::{utils::batches_to_flight_data, FlightData};
-    use bytes::{BufMut, Bytes, BytesMut};
+    use bytes::{Bytes, BytesMut};
 
-    use crate::wal::serialization::Decoder;
+    use crate::wal::{
+        record::{WalFileHeader, WalRecord},
+        serialization::{Decoder, Encoder},
+        wal_entry::EntryKind,
+    };
 
     use super::WalMsg;
 
@@ -168,7 +230,7 @@ mod tests {
         println!("wal_msg: {:?}", wal_msg);
         let mut buf = BytesMut::new();
         wal_msg.encode(&mut buf);
-        let wal_msg1 = WalMsg::decode(buf.freeze());
+        let wal_msg1 = WalMsg::decode(buf.freeze()).unwrap();
         println!("wal_msg1: {:?}", wal_msg1);
         assert_eq!(wal_msg, wal_msg1);
     }
@@ -176,8 +238,11 @@ mod tests {
     #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
     async fn wal_msg_encode_and_decode_test1() {
         let buf = create_bytes_from_walmsgs(20);
-        let vecs = Vec::<WalMsg>::decode(buf);
-        println!("v: {:?}", vecs);
+        let vecs = Vec::<WalMsg>::decode(buf.clone()).unwrap();
+        assert_eq!(vecs.len(), 20);
+        // 文件末尾不完整时，严格模式返回错误
+        let torn = buf.slice(..buf.len() - 1);
+        assert!(Vec::<WalMsg>::decode(torn).is_err());
     }
 
     fn create_datas(n: impl Into<String>) -> Vec<FlightData> {
@@ -218,17 +283,18 @@ mod tests {
 
     pub fn create_bytes_from_walmsgs(num: usize) -> Bytes {
         let mut resp = BytesMut::new();
+        let _ = WalFileHeader::new(0).encode(&mut resp);
         let b = Bytes::from("hello world !");
         for i in 0..num {
             let wal_msg = WalMsg {
+                kind: EntryKind::InsertBatch,
                 num: i as u16,
                 indexs: vec![45; i],
                 bytes: b.clone(),
             };
             let mut buf = BytesMut::new();
             wal_msg.encode(&mut buf);
-            resp.put_u32(buf.len() as u32);
-            resp.put(buf);
+            let _ = WalRecord::new(i as u64, buf.freeze()).encode(&mut resp);
         }
         resp.freeze()
     }
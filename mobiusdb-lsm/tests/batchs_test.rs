@@ -13,6 +13,7 @@ use datafusion::prelude::SessionContext;
 use mobiusdb_lsm::{
     memtable::array_data_utils::{merge_batches, merge_batches_with_schema, merge_schema},
     utils::data_utils::batch_sort,
+    TIMESTAMP,
 };
 
 pub mod common {
@@ -25,7 +26,7 @@ fn merge_batches_test() {
     let batch1 = create_student_batch1("Tom", 19);
     let batch2 = create_teacher_batch2();
     let teacher = create_teacher_batch2();
-    let merged = merge_batches(vec![&batch1, &batch2, &teacher]);
+    let merged = merge_batches(vec![&batch1, &batch2, &teacher], TIMESTAMP);
     println!("merged: {:?}", merged)
 }
 
@@ -123,7 +124,7 @@ fn batch_sort_test() {
 fn test1() {
     let batch1 = create_student_batch1("Tom", 19);
     let batch2 = create_teacher_batch2();
-    let schema = merge_schema(&batch1.schema(), &batch2.schema());
+    let schema = merge_schema(&batch1.schema(), &batch2.schema(), TIMESTAMP).unwrap();
     let resp = merge_batches_with_schema(&Arc::new(schema), vec![&batch1, &batch2]);
     println!("resp: {:?}", resp);
 }
@@ -180,3 +181,43 @@ merged: [StringArray
 fn combind_colums(v1: Vec<Arc<dyn Array>>, v2: Vec<Arc<dyn Array>>) -> Vec<Arc<dyn Array>> {
     todo!()
 }
+
+#[test]
+fn merge_batches_should_keep_column_order() {
+    let student = create_student_batch1("Tom", 19);
+    let teacher = create_teacher_batch2_with_times("class_1", 20);
+    // 多次合并的结果一致：时间字段在第一位，其余字段按第一次出现的先后顺序排列
+    for _ in 0..10 {
+        let merged = merge_batches(vec![&student, &teacher], TIMESTAMP).unwrap();
+        let names: Vec<&str> = merged
+            .schema_ref()
+            .fields()
+            .iter()
+            .map(|f| f.name().as_str())
+            .collect();
+        assert_eq!(names, vec!["timestamp", "name", "age", "address", "teach"]);
+        assert_eq!(merged.num_rows(), 6);
+        assert_eq!(merged.column(3).null_count(), 3);
+    }
+    // 表的时间字段排在第一位
+    let merged = merge_batches(vec![&student, &teacher], "age").unwrap();
+    assert_eq!(merged.schema_ref().field(0).name(), "age");
+    assert_eq!(merged.schema_ref().field(1).name(), "name");
+    // 同名字段只保留一个，可以为null和metadata合并，类型放宽
+    let s1 = Schema::new(vec![
+        Field::new("age", DataType::Int32, false),
+        Field::new("name", DataType::Utf8, false),
+    ]);
+    let s2 = Schema::new(vec![
+        Field::new("age", DataType::Int64, true),
+        Field::new("name", DataType::Utf8, false)
+            .with_metadata([("k".to_string(), "v".to_string())].into()),
+    ]);
+    let merged = merge_schema(&s1, &s2, TIMESTAMP).unwrap();
+    assert_eq!(merged.fields().len(), 2);
+    assert_eq!(merged.field(0), &Field::new("age", DataType::Int64, true));
+    assert!(!merged.field(1).is_nullable());
+    assert_eq!(merged.field(1).metadata().get("k"), Some(&"v".to_string()));
+    let s3 = Schema::new(vec![Field::new("age", DataType::Utf8, true)]);
+    assert!(merge_schema(&s1, &s3, TIMESTAMP).is_err());
+}
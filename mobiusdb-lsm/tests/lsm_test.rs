@@ -2,9 +2,12 @@ pub mod common {
     pub mod data_utils;
 }
 
-use std::{sync::atomic::Ordering, time::Duration};
+use std::{collections::HashMap, sync::atomic::Ordering, sync::Arc, time::Duration};
 
-use arrow::array::RecordBatch;
+use arrow::{
+    array::{Int32Array, Int64Array, RecordBatch, StringArray, TimestampMicrosecondArray},
+    datatypes::{DataType, Field, Schema, TimeUnit},
+};
 use arrow_flight::utils::{batches_to_flight_data, flight_data_to_batches};
 use common::data_utils::{
     create_batch_with_opts, create_data, create_diff_data, create_students,
@@ -12,8 +15,24 @@ use common::data_utils::{
 };
 use datafusion::prelude::SessionContext;
 use mobiusdb_lsm::{
-    server,
-    utils::data_utils::{self, flight_data_to_batch},
+    error::LsmError,
+    memtable::{
+        memtable_limit::{MemTableLimit, MemTableLimits},
+        time_column::TimeColumns,
+    },
+    options::LsmOptions,
+    recovery::recover,
+    server, server_with_options,
+    sstable::manifest::Manifest,
+    utils::{
+        data_utils::{self, flight_data_to_batch},
+        file_utils::{get_wal_files_name, Level},
+        time_utils::now,
+    },
+    wal::{
+        sync_policy::SyncPolicy, wal_entry::WalEntry, wal_mode::WalMode, WalService, TABLE_WAL_DIR,
+    },
+    TABLE_NAME,
 };
 use tokio::time::sleep;
 
@@ -223,3 +242,390 @@ async fn data_size_test() {
     let resp = client.append_fds(fds).await;
     println!("resp: {:?}", resp);
 }
+
+async fn append_with_sync_policy(sync_policy: SyncPolicy) {
+    let path = std::env::temp_dir().join(format!("mobiusdb-sync-{}", now()));
+    tokio::fs::create_dir_all(&path).await.unwrap();
+    let path = path.to_str().unwrap().to_string();
+    let opts = LsmOptions::new()
+        .with_wal_size(1024 * 1024)
+        .with_sync_policy(sync_policy);
+    let client = server_with_options(&path, opts).await.unwrap();
+    // 并发写入，组提交时会被合并为少量的fsync
+    let mut handles = Vec::new();
+    for i in 0..20 {
+        let client = client.clone();
+        handles.push(tokio::spawn(async move {
+            let batch = create_teacher_batch2_with_times("class_sync", i);
+            client.append_batch(batch).await.unwrap().lsn
+        }));
+    }
+    let mut lsns = Vec::new();
+    for handle in handles {
+        lsns.push(handle.await.unwrap());
+    }
+    // 每次写入都分配了不同的LSN
+    lsns.sort();
+    assert_eq!(lsns, (0..20).collect::<Vec<u64>>());
+    assert_eq!(client.last_lsn().await.unwrap(), Some(19));
+    let wal_service = WalService::init(&path, 1024 * 1024).await.unwrap();
+    // 写入sstable的数据会被checkpoint覆盖，直接按LSN读取wal中的所有记录
+    let records = wal_service.read_from(0, 100).await.unwrap();
+    assert_eq!(records.len(), 20);
+    let _ = tokio::fs::remove_dir_all(&path).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn append_with_always_sync_should_be_work() {
+    append_with_sync_policy(SyncPolicy::Always).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn append_with_group_commit_should_be_work() {
+    append_with_sync_policy(SyncPolicy::GroupCommit { max_batch: 8 }).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn append_with_interval_sync_should_be_work() {
+    append_with_sync_policy(SyncPolicy::Interval(Duration::from_millis(5))).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn append_with_zero_interval_sync_should_be_work() {
+    append_with_sync_policy(SyncPolicy::Interval(Duration::ZERO)).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn read_from_lsn_should_be_work() {
+    let path = std::env::temp_dir().join(format!("mobiusdb-read-from-{}", now()));
+    tokio::fs::create_dir_all(&path).await.unwrap();
+    let path = path.to_str().unwrap().to_string();
+    let client = server(&path, 1024 * 1024).await.unwrap();
+    assert_eq!(client.last_lsn().await.unwrap(), None);
+    for i in 0..5 {
+        let batch = create_teacher_batch2_with_times("class_lsn", i);
+        let resp = client.append_batch(batch).await.unwrap();
+        assert_eq!(resp.lsn, i as u64);
+        assert_eq!(resp.table_name, "class_lsn");
+        assert_eq!(resp.rows, 3);
+    }
+    // 以LSN为游标分页读取
+    let records = client.read_from(1, 3).await.unwrap();
+    let lsns: Vec<u64> = records.iter().map(|(lsn, _)| *lsn).collect();
+    assert_eq!(lsns, vec![1, 2, 3]);
+    match &records[0].1 {
+        WalEntry::InsertBatch(fds) => {
+            let batches = flight_data_to_batches(fds).unwrap();
+            assert_eq!(batches.first().unwrap().num_rows(), 3);
+        }
+        entry => panic!("unexpected wal entry: {:?}", entry),
+    }
+    let records = client.read_from(lsns[2] + 1, 3).await.unwrap();
+    assert_eq!(records.len(), 1);
+    let _ = tokio::fs::remove_dir_all(&path).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn append_should_return_typed_errors() {
+    let path = std::env::temp_dir().join(format!("mobiusdb-append-error-{}", now()));
+    tokio::fs::create_dir_all(&path).await.unwrap();
+    let path = path.to_str().unwrap().to_string();
+    let client = server(&path, 1024 * 1024).await.unwrap();
+    // 没有数据
+    let resp = client.append_fds(vec![]).await;
+    assert!(matches!(resp, Err(LsmError::Decode(_))));
+    // 没有表名
+    let schema = Arc::new(Schema::new(vec![Field::new("age", DataType::Int32, true)]));
+    let batch = RecordBatch::try_new(schema, vec![Arc::new(Int32Array::from(vec![1]))]).unwrap();
+    let resp = client.append_batch(batch).await;
+    assert!(matches!(resp, Err(LsmError::MissingTableName(_))));
+    // 同名字段类型不一致
+    let batch = create_teacher_batch2_with_times("class_error", 18);
+    let resp = client.append_batch(batch).await.unwrap();
+    assert!(resp.bytes > 0);
+    let schema = Arc::new(
+        Schema::new(vec![Field::new("age", DataType::Utf8, true)]).with_metadata(HashMap::from([
+            (TABLE_NAME.to_string(), "class_error".to_string()),
+        ])),
+    );
+    let batch =
+        RecordBatch::try_new(schema, vec![Arc::new(StringArray::from(vec!["18"]))]).unwrap();
+    let resp = client.append_batch(batch).await;
+    assert!(matches!(resp, Err(LsmError::SchemaConflict { .. })));
+    // 被拒绝的数据不会写入wal
+    assert_eq!(client.last_lsn().await.unwrap(), Some(0));
+    let _ = tokio::fs::remove_dir_all(&path).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn immutables_should_flush_to_sstable() {
+    let path = std::env::temp_dir().join(format!("mobiusdb-flush-{}", now()));
+    tokio::fs::create_dir_all(&path).await.unwrap();
+    let path = path.to_str().unwrap().to_string();
+    let data_path = format!("{}/data", path);
+    // 每个memtable最多6行(两次写入)
+    let limits = MemTableLimits::new().with_limit(MemTableLimit::new().with_max_rows(6));
+    let opts = LsmOptions::new()
+        .with_wal_size(1024 * 1024)
+        .with_data_path(&data_path)
+        .with_memtable_limits(limits.clone());
+    let client = server_with_options(&path, opts).await.unwrap();
+    for i in 0..10 {
+        let batch = create_teacher_batch2_with_times("class_flush", i);
+        client.append_batch(batch).await.unwrap();
+    }
+    // 等待后台任务把immutable memtable写入sstable
+    let mut sstables = Vec::new();
+    for _ in 0..200 {
+        sstables = Manifest::load(&data_path)
+            .await
+            .unwrap()
+            .sstables()
+            .to_vec();
+        if !sstables.is_empty() {
+            break;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    assert!(!sstables.is_empty());
+    for sstable in sstables.iter() {
+        assert!(tokio::fs::metadata(sstable.file_path()).await.is_ok());
+    }
+    let tables = client.table_list().await.unwrap().unwrap();
+    assert_eq!(tables.len(), 1);
+    assert_eq!(tables[0].name, "class_flush");
+    assert_eq!(tables[0].rows, 30);
+    assert!(tables[0]
+        .files
+        .get(&Level::L0)
+        .is_some_and(|files| *files > 0));
+    drop(client);
+    sleep(Duration::from_millis(100)).await;
+    // 写入sstable的数据被checkpoint覆盖，重启之后不会重复恢复
+    let wal_service = WalService::init(&path, 1024 * 1024).await.unwrap();
+    assert!(wal_service.checkpoint_seq().is_some());
+    let manifest = Manifest::load(&data_path).await.unwrap();
+    let memtable = recover(&wal_service, &manifest, limits).await.unwrap();
+    let batches = memtable
+        .query_with_table_prefix("class_flush")
+        .await
+        .unwrap();
+    let rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+    assert_eq!(rows, 30);
+    // 逻辑表合并sstable和memtable中的数据
+    let resp = memtable
+        .query("select count(*) from class_flush")
+        .await
+        .unwrap();
+    let count = resp[0]
+        .column(0)
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .unwrap()
+        .value(0);
+    assert_eq!(count, 30);
+    let _ = tokio::fs::remove_dir_all(&path).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn append_should_check_time_column() {
+    let path = std::env::temp_dir().join(format!("mobiusdb-time-column-{}", now()));
+    tokio::fs::create_dir_all(&path).await.unwrap();
+    let path = path.to_str().unwrap().to_string();
+    let opts = LsmOptions::new()
+        .with_wal_size(1024 * 1024)
+        .with_time_columns(TimeColumns::new().with_table_column("cpu_ts", "ts"));
+    let client = server_with_options(&path, opts).await.unwrap();
+    // cpu_ts 使用 Timestamp 类型的 ts 字段
+    let schema = Arc::new(
+        Schema::new(vec![
+            Field::new("value", DataType::Int32, true),
+            Field::new("ts", DataType::Timestamp(TimeUnit::Microsecond, None), true),
+        ])
+        .with_metadata(HashMap::from([(
+            TABLE_NAME.to_string(),
+            "cpu_ts".to_string(),
+        )])),
+    );
+    let batch = RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(Int32Array::from(vec![1, 2, 3])),
+            Arc::new(TimestampMicrosecondArray::from(vec![10, 20, 30])),
+        ],
+    )
+    .unwrap();
+    client.append_batch(batch).await.unwrap();
+    let resp = client
+        .query("select count(*) from cpu_ts where ts >= arrow_cast(20, 'Timestamp(Microsecond, None)')")
+        .await
+        .unwrap()
+        .unwrap();
+    let count = resp
+        .column(0)
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .unwrap()
+        .value(0);
+    assert_eq!(count, 2);
+    // cpu_ts 缺少 ts 字段(只有默认的 timestamp 字段)的数据被拒绝并且不会写入wal
+    let batch = create_teacher_batch2_with_times("cpu_ts", 1);
+    let resp = client.append_batch(batch).await;
+    assert!(matches!(resp, Err(LsmError::MissingTimeColumn { .. })));
+    // 其他表使用默认的 timestamp 字段，缺少时同样被拒绝
+    let schema = Arc::new(
+        Schema::new(vec![Field::new("age", DataType::Int32, true)]).with_metadata(HashMap::from([
+            (TABLE_NAME.to_string(), "class_time".to_string()),
+        ])),
+    );
+    let batch = RecordBatch::try_new(schema, vec![Arc::new(Int32Array::from(vec![1]))]).unwrap();
+    let resp = client.append_batch(batch).await;
+    assert!(matches!(resp, Err(LsmError::MissingTimeColumn { .. })));
+    assert_eq!(client.last_lsn().await.unwrap(), Some(0));
+    let _ = tokio::fs::remove_dir_all(&path).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn per_table_wal_should_checkpoint_flushed_table() {
+    let path = std::env::temp_dir().join(format!("mobiusdb-per-table-flush-{}", now()));
+    tokio::fs::create_dir_all(&path).await.unwrap();
+    let path = path.to_str().unwrap().to_string();
+    let data_path = format!("{}/data", path);
+    // class_pt每个memtable最多6行(两次写入)，class_hold的memtable一直不会写满
+    let limits = MemTableLimits::new()
+        .with_limit(MemTableLimit::new().with_max_rows(1000))
+        .with_table_limit("class_pt", MemTableLimit::new().with_max_rows(6));
+    // class_pt的wal文件很小，每次写入都会切换wal文件
+    let mode = WalMode::per_table().with_table_wal_size("class_pt", 2048);
+    let opts = LsmOptions::new()
+        .with_wal_size(1024 * 1024)
+        .with_data_path(&data_path)
+        .with_wal_mode(mode)
+        .with_memtable_limits(limits);
+    let client = server_with_options(&path, opts).await.unwrap();
+    // class_hold中的数据一直在内存中，全局的checkpoint无法推进
+    let batch = create_teacher_batch2_with_times("class_hold", 0);
+    client.append_batch(batch).await.unwrap();
+    for i in 0..10 {
+        let batch = create_teacher_batch2_with_times("class_pt", i);
+        client.append_batch(batch).await.unwrap();
+    }
+    // 等待后台任务把class_pt写入sstable，并清理class_pt的wal文件
+    let table_path = format!("{}/{}/class_pt", path, TABLE_WAL_DIR);
+    let mut segments = usize::MAX;
+    for _ in 0..200 {
+        segments = get_wal_files_name(&table_path).await.unwrap().len();
+        if segments <= 2 {
+            break;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    assert!(segments <= 2);
+    drop(client);
+    sleep(Duration::from_millis(100)).await;
+    let wal_service = WalService::init(&path, 1024 * 1024).await.unwrap();
+    assert!(wal_service.checkpoint_seq().is_none());
+    let hold_path = format!("{}/{}/class_hold", path, TABLE_WAL_DIR);
+    assert_eq!(get_wal_files_name(&hold_path).await.unwrap().len(), 1);
+    let _ = tokio::fs::remove_dir_all(&path).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn query_should_return_all_batches() {
+    let path = std::env::temp_dir().join(format!("mobiusdb-query-{}", now()));
+    tokio::fs::create_dir_all(&path).await.unwrap();
+    let path = path.to_str().unwrap().to_string();
+    // 每个memtable最多6行(两次写入)，查询结果来自多个memtable
+    let limits = MemTableLimits::new().with_limit(MemTableLimit::new().with_max_rows(6));
+    let opts = LsmOptions::new()
+        .with_wal_size(1024 * 1024)
+        .with_data_path(format!("{}/data", path))
+        .with_memtable_limits(limits);
+    let client = server_with_options(&path, opts).await.unwrap();
+    for i in 0..4 {
+        let batch = create_teacher_batch2_with_times("class_query", i);
+        client.append_batch(batch).await.unwrap();
+    }
+    let resp = client
+        .query("select * from class_query")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(resp.num_rows(), 12);
+    let resp = client.table("class_query").await.unwrap().unwrap();
+    assert_eq!(resp.num_rows(), 12);
+    // 没有结果时返回空的batch
+    let resp = client
+        .query("select name, age from class_query where age > 1000")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(resp.num_rows(), 0);
+    assert_eq!(resp.num_columns(), 2);
+    // 查询失败时返回None，服务端仍然可以继续处理请求
+    assert!(client
+        .query("select * from not_exists")
+        .await
+        .unwrap()
+        .is_none());
+    assert!(client.table("not_exists").await.unwrap().is_none());
+    let resp = client
+        .query("select count(*) from class_query")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(resp.num_rows(), 1);
+    let _ = tokio::fs::remove_dir_all(&path).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn schema_change_and_drop_table_should_apply_before_restart() {
+    let path = std::env::temp_dir().join(format!("mobiusdb-apply-{}", now()));
+    tokio::fs::create_dir_all(&path).await.unwrap();
+    let path = path.to_str().unwrap().to_string();
+    let opts = || LsmOptions::new().with_wal_size(1024 * 1024);
+    let client = server_with_options(&path, opts()).await.unwrap();
+    for table in ["class_apply", "class_drop"] {
+        let batch = create_teacher_batch2_with_times(table, 1);
+        client.append_batch(batch).await.unwrap();
+    }
+    // 新增level字段，age放宽为Int64
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("age", DataType::Int64, true),
+        Field::new("level", DataType::Utf8, true),
+    ]));
+    client.change_schema("class_apply", schema).await.unwrap();
+    client.drop_table("class_drop").await.unwrap();
+    // 不兼容的修改和还不能重放的删除被拒绝，不会写入wal
+    let schema = Arc::new(Schema::new(vec![Field::new("age", DataType::Utf8, true)]));
+    let resp = client.change_schema("class_apply", schema).await;
+    assert!(matches!(resp, Err(LsmError::SchemaConflict { .. })));
+    let resp = client
+        .apply(WalEntry::DeleteRange {
+            table: "class_apply".to_string(),
+            start: 0,
+            end: u64::MAX,
+        })
+        .await;
+    assert!(matches!(resp, Err(LsmError::UnsupportedEntry(_))));
+    assert_eq!(client.last_lsn().await.unwrap(), Some(3));
+    let tables = client.table_list().await.unwrap().unwrap();
+    assert_eq!(tables.len(), 1);
+    let schema = tables[0].schema.clone();
+    assert_eq!(
+        schema.field_with_name("age").unwrap().data_type(),
+        &DataType::Int64
+    );
+    assert!(schema.field_with_name("level").is_ok());
+    assert!(client.table("class_drop").await.unwrap().is_none());
+    drop(client);
+    sleep(Duration::from_millis(100)).await;
+    // 重启之后的状态和重启之前一致
+    let client = server_with_options(&path, opts()).await.unwrap();
+    let restarted = client.table_list().await.unwrap().unwrap();
+    assert_eq!(restarted.len(), 1);
+    assert_eq!(restarted[0].name, "class_apply");
+    assert_eq!(restarted[0].schema, schema);
+    assert!(client.table("class_drop").await.unwrap().is_none());
+    let _ = tokio::fs::remove_dir_all(&path).await;
+}
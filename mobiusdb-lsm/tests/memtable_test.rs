@@ -1,6 +1,24 @@
-use arrow::array::RecordBatch;
+use std::{collections::HashMap, sync::Arc};
+
+use arrow::{
+    array::{Int64Array, RecordBatch, StringArray, UInt64Array},
+    datatypes::{DataType, Field, Schema},
+};
 use common::data_utils::{create_students, create_teacher_batch2_with_times};
-use mobiusdb_lsm::memtable::{array_data_utils::merge_batches_with_schema, MemTableService};
+use datafusion::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use mobiusdb_lsm::{
+    error::LsmError,
+    flush::flush,
+    memtable::{
+        array_data_utils::merge_batches_with_schema,
+        memtable_limit::{MemTableLimit, MemTableLimits},
+        primary_key::PrimaryKeys,
+        time_column::TimeColumns,
+        MemTableService,
+    },
+    utils::time_utils::now,
+    TABLE_NAME,
+};
 
 pub mod common {
     mod batch_merge;
@@ -42,7 +60,7 @@ fn create_group2_student() -> RecordBatch {
 async fn query_test() {
     let mut mem_table = MemTableService::new();
     let group1 = create_teacher_batch2_with_times("class", 30);
-    let r = mem_table.insert_batch(&group1).await;
+    let r = mem_table.insert_batch(&group1, 0).await;
     let resp = mem_table.query_with_table_prefix("class").await;
     println!("resp: {:?}", resp);
 }
@@ -55,3 +73,321 @@ fn merge_batchs_test() {
     let resp = merge_batches_with_schema(&schema, &[group1, group2]);
     println!("resp: {:?}", resp);
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn memtable_limits_should_seal_memtables() {
+    let limits = MemTableLimits::new()
+        .with_limit(MemTableLimit::new().with_max_rows(100))
+        .with_table_limit("class_rows", MemTableLimit::new().with_max_rows(6));
+    let mut mem_table = MemTableService::new().with_limits(limits.clone());
+    for i in 0..4 {
+        let batch = create_teacher_batch2_with_times("class_rows", i);
+        assert!(mem_table.insert_batch(&batch, i as u64).await.unwrap());
+    }
+    // 每6行变为一个immutable
+    let indexs = mem_table.table_indexs();
+    assert_eq!(indexs.get_immutables().get_tables("class_rows").len(), 2);
+    assert!(indexs.get_mutables().get_table("class_rows").is_none());
+    // 超过内存上限之后，从最大的memtable开始转移到immutables中
+    let batch = create_teacher_batch2_with_times("class_small", 1);
+    mem_table.insert_batch(&batch, 4).await.unwrap();
+    for i in 0..10 {
+        let batch = create_teacher_batch2_with_times("class_budget", i);
+        mem_table.insert_batch(&batch, 5 + i as u64).await.unwrap();
+    }
+    assert!(mem_table.seal_memtables().is_empty());
+    let budget = mem_table.memory_usage() - 1;
+    let mut mem_table = mem_table.with_limits(limits.with_memory_budget(budget));
+    let sealed = mem_table.seal_memtables();
+    assert_eq!(sealed.len(), 1);
+    assert_eq!(sealed[0].get_prefix_name(), "class_budget");
+    let rows: usize = mem_table
+        .query_with_table_prefix("class_budget")
+        .await
+        .unwrap()
+        .iter()
+        .map(|batch| batch.num_rows())
+        .sum();
+    assert_eq!(rows, 30);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn tables_should_list_all_tables() {
+    let limits =
+        MemTableLimits::new().with_table_limit("class_a", MemTableLimit::new().with_max_rows(3));
+    let mut mem_table = MemTableService::new().with_limits(limits);
+    assert!(mem_table.tables().await.unwrap().is_empty());
+    for i in 0..3 {
+        let batch = create_teacher_batch2_with_times("class_a", i);
+        mem_table.insert_batch(&batch, i as u64).await.unwrap();
+    }
+    let batch = create_teacher_batch2_with_times("class_b", 1);
+    mem_table.insert_batch(&batch, 3).await.unwrap();
+    let tables = mem_table.tables().await.unwrap();
+    let names: Vec<&str> = tables.iter().map(|table| table.name.as_str()).collect();
+    assert_eq!(names, vec!["class_a", "class_b"]);
+    // class_a 每3行一个memtable
+    assert_eq!(tables[0].rows, 9);
+    assert_eq!(tables[0].memtables, 3);
+    assert_eq!(tables[0].file_count(), 0);
+    assert!(tables[0].size > 0);
+    assert!(tables[0].start <= tables[0].end);
+    assert!(tables[0].schema.field_with_name("teach").is_ok());
+    assert_eq!(tables[1].rows, 3);
+    assert_eq!(tables[1].memtables, 1);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn logical_table_should_query_with_sql() {
+    let limits = MemTableLimits::new().with_limit(MemTableLimit::new().with_max_rows(6));
+    let mut mem_table = MemTableService::new().with_limits(limits);
+    for i in 0..5 {
+        let batch = create_teacher_batch2_with_times("class_sql", i);
+        mem_table.insert_batch(&batch, i as u64).await.unwrap();
+    }
+    // 2个immutable和1个mutable，通过前缀直接查询
+    assert_eq!(mem_table.logical_table_names(), vec!["class_sql"]);
+    let resp = mem_table
+        .query("select count(*) from class_sql where age < 19")
+        .await
+        .unwrap();
+    let count = resp[0]
+        .column(0)
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .unwrap()
+        .value(0);
+    assert_eq!(count, 5);
+    // 时间字段上的条件用于跳过时间范围不重叠的memtable
+    let resp = mem_table
+        .query("select count(*) from class_sql where timestamp < 1")
+        .await
+        .unwrap();
+    let count = resp[0]
+        .column(0)
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .unwrap()
+        .value(0);
+    assert_eq!(count, 0);
+    // 删除之后不能再查询
+    mem_table.drop_table("class_sql").unwrap();
+    assert!(mem_table.logical_table_names().is_empty());
+    assert!(mem_table.query("select * from class_sql").await.is_err());
+    assert!(mem_table
+        .query_with_table_prefix("class_sql")
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn schema_registry_should_evolve_table_schema() {
+    let limits = MemTableLimits::new().with_limit(MemTableLimit::new().with_max_rows(3));
+    let mut mem_table = MemTableService::new().with_limits(limits);
+    // age: Int32，写满之后变为immutable
+    let batch = create_teacher_batch2_with_times("class_evolve", 1);
+    mem_table.insert_batch(&batch, 0).await.unwrap();
+    // age放宽为Int64，并且新增level字段
+    let schema = Arc::new(
+        Schema::new(vec![
+            Field::new("level", DataType::Utf8, false),
+            Field::new("age", DataType::Int64, true),
+            Field::new("timestamp", DataType::UInt64, true),
+        ])
+        .with_metadata(HashMap::from([(
+            TABLE_NAME.to_string(),
+            "class_evolve".to_string(),
+        )])),
+    );
+    let batch = RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(StringArray::from(vec!["A", "B"])),
+            Arc::new(Int64Array::from(vec![100, 200])),
+            Arc::new(UInt64Array::from(vec![now() as u64, now() as u64])),
+        ],
+    )
+    .unwrap();
+    mem_table.check_schema(&batch).await.unwrap();
+    mem_table.insert_batch(&batch, 1).await.unwrap();
+    let tables = mem_table.tables().await.unwrap();
+    let fields: Vec<(&str, &DataType)> = tables[0]
+        .schema
+        .fields()
+        .iter()
+        .map(|f| (f.name().as_str(), f.data_type()))
+        .collect();
+    // 时间字段排在第一位，其他字段按第一次出现的先后顺序排列
+    assert_eq!(
+        fields,
+        vec![
+            ("timestamp", &DataType::UInt64),
+            ("name", &DataType::Utf8),
+            ("age", &DataType::Int64),
+            ("teach", &DataType::Utf8),
+            ("level", &DataType::Utf8),
+        ]
+    );
+    // 之前写入的数据按放宽之后的类型查询
+    let resp = mem_table
+        .query("select sum(age), count(level) from class_evolve")
+        .await
+        .unwrap();
+    let sum = resp[0]
+        .column(0)
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .unwrap()
+        .value(0);
+    assert_eq!(sum, 1 + 19 + 20 + 100 + 200);
+    // 不兼容的类型被拒绝
+    let schema = Arc::new(
+        Schema::new(vec![
+            Field::new("age", DataType::Utf8, true),
+            Field::new("timestamp", DataType::UInt64, true),
+        ])
+        .with_metadata(HashMap::from([(
+            TABLE_NAME.to_string(),
+            "class_evolve".to_string(),
+        )])),
+    );
+    let batch = RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(StringArray::from(vec!["18"])),
+            Arc::new(UInt64Array::from(vec![now() as u64])),
+        ],
+    )
+    .unwrap();
+    let resp = mem_table.check_schema(&batch).await;
+    assert!(matches!(resp, Err(LsmError::SchemaConflict { .. })));
+    assert!(mem_table.insert_batch(&batch, 2).await.is_err());
+}
+
+fn create_readings(readings: Vec<(&str, u64, i64)>) -> RecordBatch {
+    let schema = Arc::new(
+        Schema::new(vec![
+            Field::new("host", DataType::Utf8, false),
+            Field::new("timestamp", DataType::UInt64, false),
+            Field::new("value", DataType::Int64, false),
+        ])
+        .with_metadata(HashMap::from([(
+            TABLE_NAME.to_string(),
+            "device".to_string(),
+        )])),
+    );
+    let hosts: Vec<&str> = readings.iter().map(|r| r.0).collect();
+    let times: Vec<u64> = readings.iter().map(|r| r.1).collect();
+    let values: Vec<i64> = readings.iter().map(|r| r.2).collect();
+    RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(StringArray::from(hosts)),
+            Arc::new(UInt64Array::from(times)),
+            Arc::new(Int64Array::from(values)),
+        ],
+    )
+    .unwrap()
+}
+
+async fn query_values(mem_table: &MemTableService, sql: &str) -> Vec<i64> {
+    let resp = mem_table.query(sql).await.unwrap();
+    resp.iter()
+        .flat_map(|batch| {
+            batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap()
+                .values()
+                .to_vec()
+        })
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn primary_key_should_dedup_by_last_write() {
+    let limits = MemTableLimits::new().with_limit(MemTableLimit::new().with_max_rows(4));
+    let mut mem_table = MemTableService::new()
+        .with_limits(limits)
+        .with_primary_keys(PrimaryKeys::new().with_table_key("device", ["host"]));
+    // 同一个memtable中重复的数据，写满之后变为immutable
+    let batch = create_readings(vec![("a", 1, 10), ("b", 1, 20), ("a", 1, 11)]);
+    mem_table.insert_batch(&batch, 0).await.unwrap();
+    let batch = create_readings(vec![("a", 2, 30), ("b", 1, 21)]);
+    mem_table.insert_batch(&batch, 1).await.unwrap();
+    // 新的memtable中覆盖immutable中的数据
+    let batch = create_readings(vec![("a", 1, 12), ("c", 1, 40)]);
+    mem_table.insert_batch(&batch, 2).await.unwrap();
+    let sql = "select value from device order by host, timestamp";
+    assert_eq!(query_values(&mem_table, sql).await, vec![12, 30, 21, 40]);
+    let sql = "select count(*) from device";
+    assert_eq!(query_values(&mem_table, sql).await, vec![4]);
+    // 写入sstable时去重，写入之后仍然按写入顺序覆盖
+    let data_path = std::env::temp_dir().join(format!("mobiusdb-dedup-{}", now()));
+    std::fs::create_dir_all(&data_path).unwrap();
+    let tasks = mem_table
+        .flush_tasks(data_path.to_str().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(tasks.len(), 1);
+    for (memtable, sstable) in flush(tasks).await {
+        mem_table.finish_flush(&memtable, sstable).await.unwrap();
+    }
+    let sql = "select value from device where host = 'a' and timestamp = 1";
+    assert_eq!(query_values(&mem_table, sql).await, vec![12]);
+    let sql = "select value from device order by host, timestamp";
+    assert_eq!(query_values(&mem_table, sql).await, vec![12, 30, 21, 40]);
+    let _ = std::fs::remove_dir_all(&data_path);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn time_column_should_be_first_in_table_schema() {
+    let limits = MemTableLimits::new().with_limit(MemTableLimit::new().with_max_rows(2));
+    let mut mem_table = MemTableService::new()
+        .with_limits(limits)
+        .with_time_columns(TimeColumns::new().with_table_column("sensor", "ts"));
+    let schema = Arc::new(
+        Schema::new(vec![
+            Field::new("host", DataType::Utf8, false),
+            Field::new("ts", DataType::UInt64, false),
+        ])
+        .with_metadata(HashMap::from([(
+            TABLE_NAME.to_string(),
+            "sensor".to_string(),
+        )])),
+    );
+    let batch = RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(StringArray::from(vec!["a", "b"])),
+            Arc::new(UInt64Array::from(vec![1, 2])),
+        ],
+    )
+    .unwrap();
+    mem_table.insert_batch(&batch, 0).await.unwrap();
+    let first_field = |schema: &Schema| schema.field(0).name().clone();
+    // 表的schema和逻辑表的schema都按表的时间字段排列
+    let tables = mem_table.tables().await.unwrap();
+    assert_eq!(first_field(&tables[0].schema), "ts");
+    let resp = mem_table.query("select * from sensor").await.unwrap();
+    assert_eq!(first_field(&resp[0].schema()), "ts");
+    // 写入sstable的数据也使用同样的schema
+    let data_path = std::env::temp_dir().join(format!("mobiusdb-time-first-{}", now()));
+    std::fs::create_dir_all(&data_path).unwrap();
+    let tasks = mem_table
+        .flush_tasks(data_path.to_str().unwrap())
+        .await
+        .unwrap();
+    for (memtable, sstable) in flush(tasks).await {
+        let file = std::fs::File::open(sstable.file_path()).unwrap();
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+        assert_eq!(first_field(builder.schema()), "ts");
+        mem_table.finish_flush(&memtable, sstable).await.unwrap();
+    }
+    let resp = mem_table.query("select * from sensor").await.unwrap();
+    assert_eq!(first_field(&resp[0].schema()), "ts");
+    assert_eq!(resp[0].num_rows(), 2);
+    let _ = std::fs::remove_dir_all(&data_path);
+}